@@ -0,0 +1,80 @@
+use std::panic;
+use std::path::Path;
+
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::task;
+
+use crate::codec::{compress_checked, decode_with, CompressOptions, DecompressOptions, DecompressReport};
+use crate::error::RuffmanError;
+use crate::warning::Warning;
+
+/// Compress a file as `compress_file_with` does, reading and writing it through `tokio::fs`
+/// and coding it on a blocking task, so an async service can compress without holding up
+/// its runtime. The whole output is built in memory before anything is written, and
+/// `verify_after` checks it there rather than reading the file back.
+pub async fn compress_file_async(
+    input_path: &Path,
+    output_path: &Path,
+    options: &CompressOptions,
+) -> Result<Vec<Warning>, RuffmanError> {
+    options.validate()?;
+    info!("compressing {} to {}", input_path.display(), output_path.display());
+    let input = fs::read(input_path).await?;
+    let input_len = input.len();
+    let options = options.clone();
+    let (compressed, warnings) = run_blocking(move || compress_checked(&input, &options)).await?;
+
+    if let Err(e) = fs::write(output_path, &compressed).await {
+        let _ = fs::remove_file(output_path).await;
+        return Err(e.into());
+    }
+    info!("wrote {}: {} -> {} bytes", output_path.display(), input_len, compressed.len());
+    Ok(warnings)
+}
+
+/// Decompress a file as `decompress_file_with` does, with the file I/O through `tokio::fs`
+/// and decoding on a blocking task. `sparse` is ignored.
+pub async fn decompress_file_async(
+    input_path: &Path,
+    output_path: &Path,
+    options: &DecompressOptions,
+) -> Result<DecompressReport, RuffmanError> {
+    info!("decompressing {} to {}", input_path.display(), output_path.display());
+    let compressed = fs::read(input_path).await?;
+    let compressed_len = compressed.len();
+    let decode_options = options.clone();
+    let (decompressed, damage) = run_blocking(move || decode_with(&compressed, &decode_options)).await?;
+
+    let mut open = fs::OpenOptions::new();
+    open.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if let Some(mode) = options.output_mode {
+        open.mode(mode);
+    }
+    let mut output = open.open(output_path).await?;
+    output.write_all(&decompressed).await?;
+    // A tokio file finishes writes in the background; flushing waits for them and their errors
+    output.flush().await?;
+
+    if let Some(damage) = &damage {
+        warn!("{}: recovered {} bytes before damage: {}", input_path.display(), decompressed.len(), damage);
+    }
+    info!("wrote {}: {} -> {} bytes", output_path.display(), compressed_len, decompressed.len());
+    Ok(DecompressReport {
+        bytes_written: decompressed.len(),
+        damage,
+    })
+}
+
+// Run CPU-bound codec work off the async worker threads. A panic in it is passed on to the
+// caller; a task dropped because the runtime is shutting down counts as cancelled.
+async fn run_blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, RuffmanError> + Send + 'static,
+) -> Result<T, RuffmanError> {
+    match task::spawn_blocking(work).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+        Err(_) => Err(RuffmanError::Cancelled),
+    }
+}
@@ -0,0 +1,362 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::codec::{compress_with_stats, CompressionStats};
+use crate::error::RuffmanError;
+use crate::stream::compress_stream_indexed;
+
+/// Block sizes `suggest_block_size` is usually given: 4 KiB to 4 MiB in steps of 4
+pub const BLOCK_SIZE_CANDIDATES: [usize; 6] = [4 << 10, 16 << 10, 64 << 10, 256 << 10, 1 << 20, 4 << 20];
+
+// Output within this fraction of the smallest counts as just as small
+const BLOCK_SIZE_TOLERANCE: f64 = 0.01;
+
+/// Fraction by which a file's ratio may grow over its baseline before `compare_baseline`
+/// counts it as a regression when not told otherwise
+pub const DEFAULT_BASELINE_TOLERANCE: f64 = 0.01;
+
+/// Result of compressing one file of a corpus
+#[derive(Debug, Clone)]
+pub struct CorpusEntry {
+    pub path: PathBuf,
+    pub stats: CompressionStats,
+    pub elapsed: Duration,
+}
+
+/// Per-file results, aggregate totals and the files that could not be read
+#[derive(Debug)]
+pub struct CorpusReport {
+    pub entries: Vec<CorpusEntry>,
+    pub total: CorpusEntry,
+    pub skipped: Vec<(PathBuf, io::Error)>,
+}
+
+/// How a sample came out coded in blocks of one size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockSizeTrial {
+    pub block_size: usize,
+    /// Total size of the members, without an index
+    pub compressed_size: u64,
+    pub elapsed: Duration,
+}
+
+/// Every block size tried, in the order given, and the one to use
+#[derive(Debug, Clone)]
+pub struct BlockSizeSuggestion {
+    pub trials: Vec<BlockSizeTrial>,
+    pub best: usize,
+}
+
+/// Code `sample` as `compress_stream` would with each of `candidates` as the block size,
+/// throwing the output away, and suggest one. Larger blocks share a header between more
+/// bytes, while smaller ones adapt to data that changes along the file and keep memory
+/// and latency down, so the suggestion is the smallest size whose output is within 1% of
+/// the smallest output.
+///
+/// Panics if `candidates` is empty.
+pub fn suggest_block_size(sample: &[u8], candidates: &[usize]) -> BlockSizeSuggestion {
+    assert!(!candidates.is_empty(), "no block sizes to try");
+    let trials: Vec<BlockSizeTrial> = candidates
+        .iter()
+        .map(|&block_size| {
+            let start = Instant::now();
+            let index =
+                compress_stream_indexed(sample, io::sink(), block_size).expect("coding into a sink cannot fail");
+            BlockSizeTrial {
+                block_size,
+                compressed_size: index.members.iter().map(|member| member.compressed_size).sum(),
+                elapsed: start.elapsed(),
+            }
+        })
+        .collect();
+
+    let smallest = trials.iter().map(|trial| trial.compressed_size).min().unwrap_or(0);
+    let good_enough = |trial: &&BlockSizeTrial| {
+        trial.compressed_size as f64 <= smallest as f64 * (1.0 + BLOCK_SIZE_TOLERANCE)
+    };
+    let best = trials.iter().filter(good_enough).map(|trial| trial.block_size).min().unwrap_or(candidates[0]);
+    BlockSizeSuggestion { trials, best }
+}
+
+/// Render the trials as an aligned table, marking the suggested size. Sizes are written
+/// the way `pipe --block-size` takes them, e.g. `64K`.
+pub fn render_block_sizes(suggestion: &BlockSizeSuggestion, sample_size: usize) -> String {
+    let mut table = format!("{:>10}  {:>12}  {:>7}  {:>10}\n", "block", "compressed", "ratio", "MB/s");
+    for trial in &suggestion.trials {
+        let ratio = if sample_size == 0 { 0.0 } else { trial.compressed_size as f64 / sample_size as f64 };
+        let secs = trial.elapsed.as_secs_f64();
+        let throughput = if secs == 0.0 { 0.0 } else { sample_size as f64 / secs / 1_000_000.0 };
+        let marker = if trial.block_size == suggestion.best { "  <- suggested" } else { "" };
+        table.push_str(&format!(
+            "{:>10}  {:>12}  {:>7.3}  {:>10.2}{}\n",
+            size_label(trial.block_size),
+            trial.compressed_size,
+            ratio,
+            throughput,
+            marker
+        ));
+    }
+    table
+}
+
+// A size in whole mebibytes or kibibytes where it is one, otherwise in bytes
+fn size_label(bytes: usize) -> String {
+    match bytes {
+        0 => "0".to_string(),
+        _ if bytes.is_multiple_of(1 << 20) => format!("{}M", bytes >> 20),
+        _ if bytes.is_multiple_of(1 << 10) => format!("{}K", bytes >> 10),
+        _ => bytes.to_string(),
+    }
+}
+
+/// Throughput of an entry in megabytes of input per second
+pub fn throughput_mb_s(entry: &CorpusEntry) -> f64 {
+    let secs = entry.elapsed.as_secs_f64();
+    if secs == 0.0 {
+        return 0.0;
+    }
+    entry.stats.original_size as f64 / secs / 1_000_000.0
+}
+
+/// Compress every file under `dir` in memory and collect the results
+pub fn benchmark_corpus(dir: &Path) -> io::Result<CorpusReport> {
+    let mut paths = Vec::new();
+    collect_files(dir, &mut paths)?;
+    paths.sort();
+
+    let mut entries = Vec::new();
+    let mut skipped = Vec::new();
+    let mut total = CorpusEntry {
+        path: PathBuf::from("TOTAL"),
+        stats: CompressionStats {
+            original_size: 0,
+            compressed_size: 0,
+            header_size: 0,
+            distinct_symbols: 0,
+            #[cfg(feature = "blake3")]
+            hashes: None,
+        },
+        elapsed: Duration::ZERO,
+    };
+
+    for path in paths {
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) => {
+                skipped.push((path, e));
+                continue;
+            }
+        };
+
+        let start = Instant::now();
+        let (_, stats) = compress_with_stats(&data);
+        let elapsed = start.elapsed();
+
+        total.stats.original_size += stats.original_size;
+        total.stats.compressed_size += stats.compressed_size;
+        total.stats.header_size += stats.header_size;
+        total.elapsed += elapsed;
+
+        entries.push(CorpusEntry { path, stats, elapsed });
+    }
+
+    Ok(CorpusReport { entries, total, skipped })
+}
+
+// Recursively gather the regular files below a directory
+pub(crate) fn collect_files(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, paths)?;
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Render the report as an aligned, human readable table
+pub fn render_table(report: &CorpusReport) -> String {
+    let rows: Vec<&CorpusEntry> = report.entries.iter().chain(std::iter::once(&report.total)).collect();
+    let name_width = rows
+        .iter()
+        .map(|entry| entry.path.display().to_string().len())
+        .max()
+        .unwrap_or(0)
+        .max("file".len());
+
+    let mut table = format!(
+        "{:<name_width$}  {:>12}  {:>12}  {:>10}  {:>7}  {:>10}\n",
+        "file", "original", "compressed", "header", "ratio", "MB/s"
+    );
+    for entry in rows {
+        table.push_str(&format!(
+            "{:<name_width$}  {:>12}  {:>12}  {:>10}  {:>7.3}  {:>10.2}\n",
+            entry.path.display(),
+            entry.stats.original_size,
+            entry.stats.compressed_size,
+            entry.stats.header_size,
+            entry.stats.ratio(),
+            throughput_mb_s(entry)
+        ));
+    }
+    table
+}
+
+/// Render the report as CSV with a header row
+pub fn render_csv(report: &CorpusReport) -> String {
+    let mut csv = String::from("file,original,compressed,header,ratio,mb_per_s\n");
+    for entry in report.entries.iter().chain(std::iter::once(&report.total)) {
+        csv.push_str(&format!(
+            "{},{},{},{},{:.6},{:.2}\n",
+            entry.path.display(),
+            entry.stats.original_size,
+            entry.stats.compressed_size,
+            entry.stats.header_size,
+            entry.stats.ratio(),
+            throughput_mb_s(entry)
+        ));
+    }
+    csv
+}
+
+/// The ratio and throughput of every file of a corpus run, saved as JSON so later runs can
+/// be compared against it with `compare_baseline`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    pub files: Vec<BaselineEntry>,
+}
+
+/// One file of a `Baseline`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BaselineEntry {
+    /// Path relative to the corpus directory, with `/` between components, or `TOTAL`
+    pub path: String,
+    pub ratio: f64,
+    pub mb_per_s: f64,
+}
+
+impl Baseline {
+    /// The files of `report`, named relative to `dir`, the directory it was run over, so
+    /// the baseline still applies when the corpus is checked out somewhere else
+    pub fn from_report(report: &CorpusReport, dir: &Path) -> Self {
+        let files = report
+            .entries
+            .iter()
+            .chain(std::iter::once(&report.total))
+            .map(|entry| {
+                let relative = entry.path.strip_prefix(dir).unwrap_or(&entry.path);
+                let components: Vec<_> = relative.iter().map(|part| part.to_string_lossy()).collect();
+                BaselineEntry {
+                    path: components.join("/"),
+                    ratio: entry.stats.ratio(),
+                    mb_per_s: throughput_mb_s(entry),
+                }
+            })
+            .collect();
+        Baseline { files }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a baseline is always serializable")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, RuffmanError> {
+        serde_json::from_str(json).map_err(|e| RuffmanError::InvalidBaseline(e.to_string()))
+    }
+}
+
+/// How one file compares with its baseline
+#[derive(Debug, Clone, PartialEq)]
+pub struct BaselineDelta {
+    pub path: String,
+    pub baseline: BaselineEntry,
+    pub current: BaselineEntry,
+    /// Whether the ratio grew by more than the tolerance
+    pub regressed: bool,
+}
+
+/// A corpus run against a baseline: the files in both, and the ones in only one of them
+#[derive(Debug, Clone, PartialEq)]
+pub struct BaselineComparison {
+    pub deltas: Vec<BaselineDelta>,
+    /// In the baseline but not in the run, e.g. since removed from the corpus
+    pub missing: Vec<String>,
+    /// In the run but not in the baseline
+    pub added: Vec<String>,
+}
+
+impl BaselineComparison {
+    /// Whether any file's ratio regressed. Throughput varies too much between machines and
+    /// runs to fail on, so it is only reported.
+    pub fn regressed(&self) -> bool {
+        self.deltas.iter().any(|delta| delta.regressed)
+    }
+}
+
+/// Compare a run with a baseline, file by file. A file regresses when its ratio is more than
+/// `tolerance` (a fraction, e.g. 0.01 for 1%) above its baseline ratio.
+pub fn compare_baseline(current: &Baseline, baseline: &Baseline, tolerance: f64) -> BaselineComparison {
+    let mut deltas = Vec::new();
+    let mut added = Vec::new();
+    for entry in &current.files {
+        match baseline.files.iter().find(|old| old.path == entry.path) {
+            Some(old) => deltas.push(BaselineDelta {
+                path: entry.path.clone(),
+                baseline: old.clone(),
+                current: entry.clone(),
+                regressed: entry.ratio > old.ratio * (1.0 + tolerance),
+            }),
+            None => added.push(entry.path.clone()),
+        }
+    }
+    let missing = baseline
+        .files
+        .iter()
+        .filter(|old| !current.files.iter().any(|entry| entry.path == old.path))
+        .map(|old| old.path.clone())
+        .collect();
+    BaselineComparison { deltas, missing, added }
+}
+
+/// Render a comparison as an aligned table of ratio and throughput changes, marking the
+/// files that regressed, followed by the files in only one of the two runs
+pub fn render_comparison(comparison: &BaselineComparison) -> String {
+    let name_width = comparison.deltas.iter().map(|delta| delta.path.len()).max().unwrap_or(0).max("file".len());
+    let mut table = format!(
+        "{:<name_width$}  {:>9}  {:>9}  {:>9}  {:>10}  {:>10}\n",
+        "file", "baseline", "ratio", "change", "base MB/s", "MB/s"
+    );
+    for delta in &comparison.deltas {
+        table.push_str(&format!(
+            "{:<name_width$}  {:>9.4}  {:>9.4}  {:>+8.2}%  {:>10.2}  {:>10.2}{}\n",
+            delta.path,
+            delta.baseline.ratio,
+            delta.current.ratio,
+            percent_change(delta.baseline.ratio, delta.current.ratio),
+            delta.baseline.mb_per_s,
+            delta.current.mb_per_s,
+            if delta.regressed { "  <- regressed" } else { "" }
+        ));
+    }
+    for path in &comparison.missing {
+        table.push_str(&format!("missing from this run: {}\n", path));
+    }
+    for path in &comparison.added {
+        table.push_str(&format!("not in the baseline: {}\n", path));
+    }
+    table
+}
+
+// Change from `old` to `new` in percent of `old`, 0 when `old` is 0
+fn percent_change(old: f64, new: f64) -> f64 {
+    if old == 0.0 {
+        return 0.0;
+    }
+    (new - old) / old * 100.0
+}
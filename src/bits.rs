@@ -0,0 +1,167 @@
+use std::io::{self, ErrorKind, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+/// Where in each byte successive bits go
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BitOrder {
+    /// From the most significant bit down; the ruffman default
+    #[default]
+    MsbFirst,
+    /// From the least significant bit up, as DEFLATE and other LSB-first formats pack them
+    LsbFirst,
+}
+
+/// Packs bits into bytes, most significant bit first as in the body of a ruffman file
+/// unless built `with_bit_order`, and writes each byte to the underlying writer as soon as
+/// it is full
+#[derive(Debug)]
+pub struct BitWriter<W: Write> {
+    writer: W,
+    order: BitOrder,
+    byte: u8,
+    filled: u8,
+}
+
+impl<W: Write> BitWriter<W> {
+    pub fn new(writer: W) -> Self {
+        BitWriter::with_bit_order(writer, BitOrder::MsbFirst)
+    }
+
+    pub fn with_bit_order(writer: W, order: BitOrder) -> Self {
+        BitWriter {
+            writer,
+            order,
+            byte: 0,
+            filled: 0,
+        }
+    }
+
+    pub fn write_bit(&mut self, bit: bool) -> io::Result<()> {
+        match self.order {
+            BitOrder::MsbFirst => self.byte = (self.byte << 1) | bit as u8,
+            BitOrder::LsbFirst => self.byte |= (bit as u8) << self.filled,
+        }
+        self.filled += 1;
+        if self.filled == 8 {
+            self.writer.write_all(&[self.byte])?;
+            self.byte = 0;
+            self.filled = 0;
+        }
+        Ok(())
+    }
+
+    /// Write the low `len` bits of `code`, its most significant one first whatever the bit
+    /// order, which only decides where each bit lands in its byte. Huffman codes are
+    /// written this way in LSB-first formats too.
+    ///
+    /// Panics if `len` is above 32.
+    pub fn write_bits(&mut self, code: u32, len: u8) -> io::Result<()> {
+        assert!(len <= 32, "cannot write {} bits from a u32", len);
+        for shift in (0..len).rev() {
+            self.write_bit((code >> shift) & 1 == 1)?;
+        }
+        Ok(())
+    }
+
+    // Write a code spelled out as '0' and '1' characters, which may be longer than 32 bits
+    pub(crate) fn write_code(&mut self, code: &str) -> io::Result<()> {
+        for bit in code.bytes() {
+            self.write_bit(bit == b'1')?;
+        }
+        Ok(())
+    }
+
+    /// Bits written to the last, still partial byte, from 0 to 7
+    pub fn pending_bits(&self) -> u8 {
+        self.filled
+    }
+
+    /// Pad the last partial byte with zero bits, write it and give the writer back
+    pub fn finish(self) -> io::Result<W> {
+        self.finish_with(0)
+    }
+
+    /// Like `finish`, taking the padding bits from the low bits of `padding` instead
+    pub fn finish_with(mut self, padding: u8) -> io::Result<W> {
+        if self.filled > 0 {
+            let pad = 8 - self.filled;
+            let padding = padding & ((1 << pad) - 1);
+            let byte = match self.order {
+                BitOrder::MsbFirst => (self.byte << pad) | padding,
+                BitOrder::LsbFirst => self.byte | (padding << self.filled),
+            };
+            self.writer.write_all(&[byte])?;
+        }
+        Ok(self.writer)
+    }
+}
+
+/// Reads bits back from bytes, most significant bit first unless built `with_bit_order`,
+/// the counterpart of `BitWriter`
+#[derive(Debug)]
+pub struct BitReader<R: Read> {
+    reader: R,
+    order: BitOrder,
+    byte: u8,
+    remaining: u8,
+}
+
+impl<R: Read> BitReader<R> {
+    pub fn new(reader: R) -> Self {
+        BitReader::with_bit_order(reader, BitOrder::MsbFirst)
+    }
+
+    pub fn with_bit_order(reader: R, order: BitOrder) -> Self {
+        BitReader {
+            reader,
+            order,
+            byte: 0,
+            remaining: 0,
+        }
+    }
+
+    /// The next bit, or `None` once the input is used up
+    pub fn read_bit(&mut self) -> io::Result<Option<bool>> {
+        if self.remaining == 0 {
+            let mut byte = [0u8];
+            loop {
+                match self.reader.read(&mut byte) {
+                    Ok(0) => return Ok(None),
+                    Ok(_) => break,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+            self.byte = byte[0];
+            self.remaining = 8;
+        }
+        self.remaining -= 1;
+        let shift = match self.order {
+            BitOrder::MsbFirst => self.remaining,
+            BitOrder::LsbFirst => 7 - self.remaining,
+        };
+        Ok(Some((self.byte >> shift) & 1 == 1))
+    }
+
+    /// The next `count` bits as the low bits of a `u32`, the first one most significant
+    /// whatever the bit order, or `None` if the input ends before all of them were read.
+    ///
+    /// Panics if `count` is above 32.
+    pub fn read_bits(&mut self, count: u8) -> io::Result<Option<u32>> {
+        assert!(count <= 32, "cannot read {} bits into a u32", count);
+        let mut bits = 0u32;
+        for _ in 0..count {
+            match self.read_bit()? {
+                Some(bit) => bits = (bits << 1) | bit as u32,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(bits))
+    }
+
+    /// Give the reader back; bits left in a partly read byte are dropped
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
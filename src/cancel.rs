@@ -0,0 +1,36 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::error::RuffmanError;
+
+// Symbols coded, or bits decoded, between looks at the token
+pub(crate) const CHECK_INTERVAL: usize = 1 << 16;
+
+/// A flag another thread can set to stop a compression or decompression that was given a
+/// clone of it. Cancelled file operations fail with `RuffmanError::Cancelled` and leave no
+/// output behind.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every operation holding a clone of this token to stop
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// Fail with `Cancelled` if there is a token and it has been cancelled
+pub(crate) fn check(cancel: Option<&CancellationToken>) -> Result<(), RuffmanError> {
+    match cancel {
+        Some(token) if token.is_cancelled() => Err(RuffmanError::Cancelled),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+/// Record the code length of every byte, with 0 for bytes that have no code
+pub fn codes_to_lengths(codes: &HashMap<u8, String>) -> [u8; 256] {
+    let mut lengths = [0u8; 256];
+    for (&byte, code) in codes {
+        lengths[byte as usize] = code.len() as u8;
+    }
+    lengths
+}
+
+/// Assign canonical codes from per-byte code lengths, skipping bytes of length 0.
+///
+/// Codes are handed out in order of increasing length, ties broken by byte value,
+/// each one being the previous code plus one, left-shifted to the new length.
+/// The lengths must satisfy Kraft's inequality for the result to be a prefix code.
+pub fn lengths_to_codes(lengths: [u8; 256]) -> HashMap<u8, String> {
+    let mut symbols: Vec<u8> = (0..=255u8).filter(|&byte| lengths[byte as usize] > 0).collect();
+    symbols.sort_by_key(|&byte| (lengths[byte as usize], byte));
+
+    let mut codes = HashMap::with_capacity(symbols.len());
+    let mut code: Vec<u8> = Vec::new();
+    for (index, &byte) in symbols.iter().enumerate() {
+        if index > 0 {
+            increment(&mut code);
+        }
+        code.resize(lengths[byte as usize] as usize, b'0');
+        codes.insert(byte, String::from_utf8(code.clone()).unwrap());
+    }
+    codes
+}
+
+/// The canonical codes of `lengths_to_codes` as (byte, code, length) triples sorted by byte,
+/// each code in the low `length` bits of its `u32` as `BitWriter::write_bits` takes it.
+///
+/// Returns `None` if a length is above 32, since that code does not fit; `lengths_to_codes`
+/// has no such limit.
+pub fn lengths_to_packed_codes(lengths: &[u8; 256]) -> Option<Vec<(u8, u32, u8)>> {
+    let mut symbols: Vec<u8> = (0..=255u8).filter(|&byte| lengths[byte as usize] > 0).collect();
+    symbols.sort_by_key(|&byte| (lengths[byte as usize], byte));
+
+    let mut packed = Vec::with_capacity(symbols.len());
+    let mut code = 0u32;
+    let mut previous_len = 0;
+    for (index, &byte) in symbols.iter().enumerate() {
+        let len = lengths[byte as usize];
+        if len > 32 {
+            return None;
+        }
+        // The same steps as `lengths_to_codes`: add one, then extend with zeros. Only the
+        // first code can be extended by all 32 bits, and it is 0.
+        if index > 0 {
+            code = code.wrapping_add(1);
+        }
+        code = code.checked_shl((len - previous_len) as u32).unwrap_or(0);
+        packed.push((byte, code, len));
+        previous_len = len;
+    }
+    packed.sort_unstable_by_key(|&(byte, _, _)| byte);
+    Some(packed)
+}
+
+// Add one to a big-endian string of '0'/'1' digits in place
+fn increment(code: &mut [u8]) {
+    for bit in code.iter_mut().rev() {
+        if *bit == b'0' {
+            *bit = b'1';
+            return;
+        }
+        *bit = b'0';
+    }
+}
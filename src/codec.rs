@@ -0,0 +1,1725 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+use crate::bits::{BitOrder, BitReader, BitWriter};
+use crate::cancel::{check, CancellationToken, CHECK_INTERVAL};
+use crate::coder::{codec_for, HUFFMAN_MODE, TABLE_REFERENCE_MODE};
+use crate::columns::{join_columns, split_columns, ColumnLayout};
+use crate::error::RuffmanError;
+use crate::frame::{frame_prefix, read_frame, FRAME_PREFIX_LEN};
+use crate::canonical::{codes_to_lengths, lengths_to_codes, lengths_to_packed_codes};
+use crate::huffman::{build_codes_from_leaves, build_codes_verified, ordered_leaves, TieBreak, COUNTED};
+use crate::input::open_input;
+use crate::nibble::{join_nibbles, split_nibbles};
+use crate::quantize::{dequantize, quantize};
+use crate::text::normalize_line_endings;
+use crate::warning::Warning;
+
+/// Bytes every ruffman file starts with
+pub const MAGIC: [u8; 4] = *b"RUFF";
+
+/// Longest comment, in bytes, that can be stored in a header
+pub const MAX_COMMENT_LEN: usize = 4096;
+
+// First two bytes of every gzip member
+pub(crate) const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// Granularity at which `write_sparse` looks for zeros, the usual file system block size
+const SPARSE_BLOCK_SIZE: usize = 4096;
+
+// Value of the padding bits at the end of the body. Decoding stops at the header's bit
+// count, so any value reads back the same; zeros keep output reproducible.
+pub(crate) const PADDING: u8 = 0;
+
+// Longest code accepted in a header. Huffman codes over `usize` counts stay far below it.
+const MAX_CODE_LENGTH: u8 = 127;
+
+// Offset of the little-endian header length, after the magic and the mode byte
+pub(crate) const HEADER_LEN_START: usize = MAGIC.len() + 1;
+
+// Size of the header length
+const HEADER_LEN_SIZE: usize = 4;
+
+// Offset of the header itself
+pub(crate) const HEADER_START: usize = HEADER_LEN_START + HEADER_LEN_SIZE;
+
+// Most entries a code table can have, one per byte value
+const MAX_CODE_ENTRIES: u64 = 256;
+
+// What a file that refers to its code table puts before the Huffman file inside it: the
+// magic, the mode byte and the table's hash
+const TABLE_REFERENCE_LEN: usize = HEADER_LEN_START + 4;
+
+// Header encoding, spelled out so the on-disk format never depends on bincode's
+// defaults or on the host: fixed-width integers, little-endian, `usize` as `u64`
+fn header_options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_little_endian()
+        .allow_trailing_bytes()
+}
+
+// A code table as the header stores it, as (byte, code length) pairs sorted by byte
+type CodeTable = Vec<(u8, u8)>;
+
+// A code as its length in bits and its value, which a decoder builds up bit by bit
+// without allocating. Every code fits, since none is longer than `MAX_CODE_LENGTH` bits.
+pub(crate) type PackedCode = (u8, u128);
+
+// Codes mapped back to their bytes, with the length of the longest one
+type DecodingTable = (HashMap<PackedCode, u8>, usize);
+
+// Everything the decoder needs besides the bit stream itself. The code table is stored
+// canonically, as (byte, code length) pairs sorted by byte; both sides rebuild the codes
+// from the lengths with `lengths_to_codes`.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Header {
+    pub(crate) code_lengths: CodeTable,
+    // Always 64-bit, whatever the platform's `usize`; `parse` checks they fit one before
+    // `bit_count` and `byte_count` are used
+    pub(crate) original_bit_count: u64,
+    pub(crate) original_byte_count: u64,
+    pub(crate) columns: Option<ColumnLayout>,
+    pub(crate) nibbles: bool,
+    // Number of buckets bytes were quantized into; decoding only gets them back approximately
+    pub(crate) quantize_levels: Option<u8>,
+    pub(crate) comment: Option<String>,
+    // CRC-32 of the body bytes, checked before decoding starts
+    pub(crate) body_checksum: Option<u32>,
+    // Tie-break policy the code lengths were built with; decoding only needs the lengths
+    pub(crate) tie_break: TieBreak,
+    pub(crate) bit_order: BitOrder,
+    // When the file was compressed, in seconds since the Unix epoch
+    pub(crate) created: Option<u64>,
+    // Order-1 code tables: for each byte that precedes another, sorted by it, the code
+    // lengths of the bytes that follow it, laid out like `code_lengths`. The first byte is
+    // coded as if preceded by 0. `code_lengths` is then empty.
+    pub(crate) contexts: Option<Vec<(u8, CodeTable)>>,
+    // Offset of the body in the file, filled in when the header is parsed
+    #[serde(skip)]
+    pub(crate) body_start: usize,
+}
+
+impl Header {
+    // Keep the bytes that have a code, in byte order
+    pub(crate) fn new(lengths: &[u8; 256], original_bit_count: u64, original_byte_count: u64) -> Self {
+        Header {
+            code_lengths: table_entries(lengths),
+            original_bit_count,
+            original_byte_count,
+            columns: None,
+            nibbles: false,
+            quantize_levels: None,
+            comment: None,
+            body_checksum: None,
+            tie_break: TieBreak::default(),
+            bit_order: BitOrder::default(),
+            created: None,
+            contexts: None,
+            body_start: 0,
+        }
+    }
+
+    // Unused low bits in the last body byte, from 0 to 7
+    pub(crate) fn padding_bits(&self) -> u8 {
+        ((8 - self.original_bit_count % 8) % 8) as u8
+    }
+
+    pub(crate) fn bit_count(&self) -> usize {
+        self.original_bit_count as usize
+    }
+
+    pub(crate) fn byte_count(&self) -> usize {
+        self.original_byte_count as usize
+    }
+
+    // Whether the decoded bytes still have to go through `restore`
+    fn is_transformed(&self) -> bool {
+        self.columns.is_some() || self.nibbles || self.quantize_levels.is_some()
+    }
+
+    // Undo the transforms applied before coding, in reverse order
+    fn restore(&self, decoded: Vec<u8>) -> Result<Vec<u8>, RuffmanError> {
+        let decoded = if self.nibbles { join_nibbles(&decoded)? } else { decoded };
+        let decoded = match &self.columns {
+            Some(layout) => join_columns(layout, &decoded)?,
+            None => decoded,
+        };
+        match self.quantize_levels {
+            Some(levels) => dequantize(&decoded, levels),
+            None => Ok(decoded),
+        }
+    }
+
+    // Rebuild the canonical codes, mapping each one straight back to its byte
+    pub(crate) fn decoding_table(&self) -> Result<HashMap<String, u8>, RuffmanError> {
+        decoding_table_for(&self.code_lengths)
+    }
+
+    // The table to decode each byte with, by the byte before it, with the length of its
+    // longest code. Without order-1 contexts every byte shares one table.
+    pub(crate) fn decoding_tables(&self) -> Result<Vec<DecodingTable>, RuffmanError> {
+        let with_longest = |table: HashMap<String, u8>| {
+            let longest = table.keys().map(String::len).max().unwrap_or(0);
+            (pack_decoding_table(table), longest)
+        };
+        let Some(contexts) = &self.contexts else {
+            return Ok(vec![with_longest(self.decoding_table()?)]);
+        };
+        let mut tables = vec![(HashMap::new(), 0); 256];
+        let mut previous = None;
+        for (context, code_lengths) in contexts {
+            if previous.is_some_and(|previous| previous >= *context) {
+                return Err(corrupt_at(HEADER_START, "context tables are not sorted by byte"));
+            }
+            tables[*context as usize] = with_longest(decoding_table_for(code_lengths)?);
+            previous = Some(*context);
+        }
+        Ok(tables)
+    }
+}
+
+// Key a decoding table by packed codes instead of '0' and '1' strings
+pub(crate) fn pack_decoding_table(table: HashMap<String, u8>) -> HashMap<PackedCode, u8> {
+    table
+        .into_iter()
+        .map(|(code, byte)| ((code.len() as u8, u128::from_str_radix(&code, 2).expect("codes are binary")), byte))
+        .collect()
+}
+
+// Add `bit` to the end of a code being read
+pub(crate) fn push_bit(code: &mut PackedCode, bit: bool) {
+    *code = (code.0 + 1, (code.1 << 1) | bit as u128);
+}
+
+// The (byte, code length) pairs a code table is stored as: the bytes that have a code, in
+// byte order
+fn table_entries(lengths: &[u8; 256]) -> CodeTable {
+    (0..=255u8)
+        .filter(|&byte| lengths[byte as usize] > 0)
+        .map(|byte| (byte, lengths[byte as usize]))
+        .collect()
+}
+
+// What a file that refers to its code table stores of it: the CRC-32 of all 256 lengths
+fn table_hash(lengths: &[u8; 256]) -> u32 {
+    crc32fast::hash(lengths)
+}
+
+// Rebuild the canonical codes of a code table stored as (byte, code length) pairs, after
+// checking the pairs are sorted and form a prefix code
+fn decoding_table_for(code_lengths: &[(u8, u8)]) -> Result<HashMap<String, u8>, RuffmanError> {
+    let mut lengths = [0u8; 256];
+    // Kraft sum scaled by 2^MAX_CODE_LENGTH; above that the lengths cannot form a prefix code
+    let mut kraft_sum = 0u128;
+    let mut previous = None;
+    for &(byte, length) in code_lengths {
+        if previous.is_some_and(|previous| previous >= byte) {
+            return Err(corrupt_at(HEADER_START, "code table is not sorted by byte"));
+        }
+        if length == 0 || length > MAX_CODE_LENGTH {
+            return Err(corrupt_at(HEADER_START, "invalid code length"));
+        }
+        kraft_sum += 1 << (MAX_CODE_LENGTH - length);
+        if kraft_sum > 1 << MAX_CODE_LENGTH {
+            return Err(corrupt_at(HEADER_START, "code lengths do not form a prefix code"));
+        }
+        lengths[byte as usize] = length;
+        previous = Some(byte);
+    }
+    let table = lengths_to_codes(lengths).into_iter().map(|(byte, code)| (code, byte)).collect();
+    Ok(table)
+}
+
+/// Sizes recorded while compressing a buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionStats {
+    pub original_size: usize,
+    pub compressed_size: usize,
+    pub header_size: usize,
+    /// Distinct symbols coded, 0 for totals over several buffers
+    pub distinct_symbols: usize,
+    /// BLAKE3 hashes of the input and output, for indexing content without hashing it again.
+    /// `None` for totals over several buffers and for `read_compression_stats`.
+    #[cfg(feature = "blake3")]
+    pub hashes: Option<ContentHashes>,
+}
+
+/// BLAKE3 hashes of a buffer and of its compressed form
+#[cfg(feature = "blake3")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentHashes {
+    pub original: [u8; 32],
+    pub compressed: [u8; 32],
+}
+
+impl CompressionStats {
+    /// Compressed size as a fraction of the original size (lower is better)
+    pub fn ratio(&self) -> f64 {
+        size_ratio(self.original_size, self.compressed_size)
+    }
+
+    /// Whether the buffer held a single distinct symbol. It codes to one bit per byte, so
+    /// `ratio` comes out near 1/8 plus the header, however large the input.
+    pub fn single_symbol(&self) -> bool {
+        self.distinct_symbols == 1
+    }
+}
+
+// Compressed size as a fraction of the original size, 0 for empty input
+fn size_ratio(original_size: usize, compressed_size: usize) -> f64 {
+    if original_size == 0 {
+        return 0.0;
+    }
+    compressed_size as f64 / original_size as f64
+}
+
+/// Container format written by compression
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// The native Huffman format
+    #[default]
+    Ruffman,
+    /// A standard gzip member, readable by any gzip tool
+    #[cfg(feature = "gzip")]
+    Gzip,
+}
+
+/// Capacity of the buffered readers and writers used for files unless configured otherwise
+pub const DEFAULT_IO_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Options controlling compression
+#[derive(Debug, Clone)]
+pub struct CompressOptions {
+    pub format: Format,
+    /// Split newline-delimited records on this delimiter and code them column by column
+    /// (native format only)
+    pub columns: Option<u8>,
+    /// Code 4-bit halves of bytes instead of whole bytes (native format only)
+    pub nibbles: bool,
+    /// Free-form UTF-8 text stored in the header, at most `MAX_COMMENT_LEN` bytes
+    /// (native format only)
+    pub comment: Option<String>,
+    /// Store a CRC-32 of the compressed body so damage is caught before decoding
+    /// (native format only)
+    pub body_checksum: bool,
+    /// After writing a file, decompress it in memory and remove it unless it matches the input
+    pub verify_after: bool,
+    /// Capacity of the file reader and writer buffers
+    pub io_buffer_size: usize,
+    /// Lossy: map bytes onto this many equal-width levels (2 to 255) before coding; decoding
+    /// gives back the middle of each byte's level rather than the byte (native format only)
+    pub quantize: Option<u8>,
+    /// Turn CRLF line endings into LF before compressing. Lossy: decompression gives back
+    /// the normalized text, not the original bytes, so only use it on text.
+    pub normalize_line_endings: bool,
+    /// Don't write a file whose ratio (compressed / original) would be above this
+    pub min_ratio: Option<f64>,
+    /// How the Huffman tree orders symbols of equal frequency (native format only)
+    pub tie_break: TieBreak,
+    /// Code with these per-byte code lengths, e.g. `read_code_lengths` of an earlier
+    /// version of the file, instead of building new ones, so similar files share codes.
+    /// If the coded data has a byte of length 0, new lengths are built after all and
+    /// `compress_with_warnings` says so. The file still stores its table unless
+    /// `table_reference` is set (native format only).
+    pub reuse_code_lengths: Option<[u8; 256]>,
+    /// When the data is coded with `reuse_code_lengths`, store a hash of the table instead
+    /// of the table itself, two bytes less for every byte value with a code. The output
+    /// then only decompresses given the same lengths, with `decompress_with_code_lengths`
+    /// or `DecompressOptions::code_lengths` (native format only).
+    pub table_reference: bool,
+    /// Bytes to reserve up front when reading input of unknown length, such as a pipe, to
+    /// save growing the buffer as it arrives. Any value gives the same output.
+    pub input_size_hint: Option<usize>,
+    /// Stop with `RuffmanError::Cancelled` once this is cancelled. Only the functions that
+    /// return a `Result` (file, reader and writer ones) check it.
+    pub cancel: Option<CancellationToken>,
+    /// How the body packs bits into bytes, for readers that expect LSB-first data. It is
+    /// stored in the header and decoding follows it (native format only).
+    pub bit_order: BitOrder,
+    /// When the data was compressed, in seconds since the Unix epoch (UTC), recorded in the
+    /// header for auditing and cache invalidation and shown by `inspect`. `None`, the
+    /// default, keeps the output independent of when it was made (native format only).
+    pub created: Option<u64>,
+    /// Code each byte with a table of its own for the byte before it, an order-1 model that
+    /// suits text, where what follows a letter is far from random. The header then holds a
+    /// table for every byte that precedes another, so it pays off on larger inputs.
+    /// `reuse_code_lengths` does not apply (native format only).
+    pub order1: bool,
+    /// Put the output behind its length, as `write_frame` does, so framed outputs written
+    /// one after another can be split apart with `read_frame`
+    pub framed: bool,
+    /// Pad the output with zero bytes to a multiple of this many, e.g. a storage block size
+    /// for direct I/O. Decoding stops at the bit count in the header and never reads the
+    /// padding. With `framed`, the padding is inside the frame and the whole frame is
+    /// aligned. `None`, 0 and 1 add none (native format only).
+    pub align: Option<usize>,
+    /// Check every Huffman tree built against the invariants a correct one keeps, and fail
+    /// with `RuffmanError::InvalidTree` if it breaks one; the functions that return no
+    /// `Result` panic instead. A debugging aid for work on the tree code; the output is the same.
+    pub verify_tree: bool,
+}
+
+impl Default for CompressOptions {
+    fn default() -> Self {
+        CompressOptions {
+            format: Format::default(),
+            columns: None,
+            nibbles: false,
+            comment: None,
+            body_checksum: false,
+            verify_after: false,
+            io_buffer_size: DEFAULT_IO_BUFFER_SIZE,
+            quantize: None,
+            normalize_line_endings: false,
+            min_ratio: None,
+            tie_break: TieBreak::default(),
+            reuse_code_lengths: None,
+            table_reference: false,
+            input_size_hint: None,
+            cancel: None,
+            bit_order: BitOrder::default(),
+            created: None,
+            order1: false,
+            framed: false,
+            align: None,
+            verify_tree: false,
+        }
+    }
+}
+
+impl CompressOptions {
+    /// Check the options that cannot be applied as given
+    pub fn validate(&self) -> Result<(), RuffmanError> {
+        if let Some(comment) = &self.comment {
+            if comment.len() > MAX_COMMENT_LEN {
+                return Err(RuffmanError::CommentTooLong(comment.len()));
+            }
+        }
+        if let Some(levels) = self.quantize {
+            if levels < 2 {
+                return Err(RuffmanError::InvalidQuantizeLevels(levels));
+            }
+        }
+        if let Some(lengths) = &self.reuse_code_lengths {
+            Header::new(lengths, 0, 0).decoding_table().map_err(|_| RuffmanError::InvalidCodeLengths)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compress a buffer into the ruffman format
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    compress_with_stats(data).0
+}
+
+/// Compress a buffer using the given options.
+///
+/// Panics if the options fail `CompressOptions::validate`.
+pub fn compress_with(data: &[u8], options: &CompressOptions) -> Vec<u8> {
+    options.validate().expect("invalid compression options");
+    compress_cancellable(data, options, None).expect(UNCANCELLED)
+}
+
+// Compress with validated options, stopping early if `cancel` is cancelled
+fn compress_cancellable(
+    data: &[u8],
+    options: &CompressOptions,
+    cancel: Option<&CancellationToken>,
+) -> Result<Vec<u8>, RuffmanError> {
+    let data = if options.normalize_line_endings { normalize_line_endings(data) } else { Cow::Borrowed(data) };
+    let mut compressed = match options.format {
+        Format::Ruffman => compress_traced(&data, options, cancel, None)?.0,
+        #[cfg(feature = "gzip")]
+        Format::Gzip => {
+            check(cancel)?;
+            crate::gzip::compress(&data)
+        }
+    };
+    let framed_len = compressed.len() + if options.framed { FRAME_PREFIX_LEN } else { 0 };
+    compressed.resize(compressed.len() + alignment_padding(framed_len, options), 0);
+    if !options.framed {
+        return Ok(compressed);
+    }
+    let mut framed = Vec::with_capacity(FRAME_PREFIX_LEN + compressed.len());
+    framed.extend_from_slice(&frame_prefix(compressed.len())?);
+    framed.extend_from_slice(&compressed);
+    Ok(framed)
+}
+
+// The compressed file inside `output`, which is framed if `options.framed` says so
+fn unframed<'a>(output: &'a [u8], options: &CompressOptions) -> &'a [u8] {
+    match options.framed {
+        true => output.get(FRAME_PREFIX_LEN..).unwrap_or_default(),
+        false => output,
+    }
+}
+
+// Compression only fails when cancelled or when `verify_tree` finds a broken tree, so this
+// holds wherever no token is passed, short of a bug in the tree code
+const UNCANCELLED: &str = "compression without a cancellation token cannot fail";
+
+/// Compress a buffer using the given options, also returning any warnings
+pub fn compress_with_warnings(data: &[u8], options: &CompressOptions) -> (Vec<u8>, Vec<Warning>) {
+    let compressed = compress_with(data, options);
+    let used_lengths = read_code_lengths(unframed(&compressed, options)).ok();
+    let warnings = warnings_for(data, compressed.len(), used_lengths, options);
+    (compressed, warnings)
+}
+
+// What `compress_with_warnings` warns about `data` compressed to `output_len` bytes, any
+// frame prefix and padding included, coded with `used_lengths` if it is in the native format
+fn warnings_for(
+    data: &[u8],
+    output_len: usize,
+    used_lengths: Option<[u8; 256]>,
+    options: &CompressOptions,
+) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    if data.starts_with(&MAGIC) || data.starts_with(&GZIP_MAGIC) {
+        warnings.push(Warning::AlreadyCompressed);
+    }
+    if let (Some(lengths), Some(used)) = (&options.reuse_code_lengths, used_lengths) {
+        if used != *lengths {
+            warnings.push(Warning::CodeLengthsNotReused);
+        }
+    }
+    if output_len > data.len() {
+        warnings.push(Warning::Expanded {
+            original_size: data.len(),
+            compressed_size: output_len,
+        });
+    }
+    for warning in &warnings {
+        warn!("{}", warning);
+    }
+    warnings
+}
+
+/// Compress a buffer straight into `writer`, e.g. a `Tee` over several sinks, returning any
+/// warnings. The options are validated first.
+pub fn compress_to_writer<W: Write>(
+    data: &[u8],
+    options: &CompressOptions,
+    mut writer: W,
+) -> Result<Vec<Warning>, RuffmanError> {
+    options.validate()?;
+    let compressed = compress_cancellable(data, options, options.cancel.as_ref())?;
+    let used_lengths = read_code_lengths(unframed(&compressed, options)).ok();
+    let warnings = warnings_for(data, compressed.len(), used_lengths, options);
+    writer.write_all(&compressed)?;
+    writer.flush()?;
+    Ok(warnings)
+}
+
+/// Compress a buffer into `writer` wherever it stands, e.g. between records of a container
+/// format of your own, and return the number of bytes written. The blob is a complete
+/// compressed file, magic and header included, so it can be decoded on its own from its
+/// offset: `decompress_to_vec` of the bytes from there stops at the end of the blob,
+/// whatever follows it. The writer is not flushed, and warnings are only logged.
+pub fn compress_into<W: Write + ?Sized>(
+    data: &[u8],
+    options: &CompressOptions,
+    writer: &mut W,
+) -> Result<usize, RuffmanError> {
+    options.validate()?;
+    let compressed = compress_cancellable(data, options, options.cancel.as_ref())?;
+    warnings_for(data, compressed.len(), read_code_lengths(unframed(&compressed, options)).ok(), options);
+    writer.write_all(&compressed)?;
+    Ok(compressed.len())
+}
+
+/// Read all of `reader`, compress it with the default options and write the result to
+/// `writer`. Works the same for files, pipes, sockets and in-memory cursors.
+pub fn compress_reader_to_writer<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+) -> Result<CompressionStats, RuffmanError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let (compressed, stats) = compress_with_stats(&data);
+    writer.write_all(&compressed)?;
+    writer.flush()?;
+    Ok(stats)
+}
+
+/// Exact length of `compress(data)`, worked out from the code lengths and bit count
+/// without coding the body or allocating the output
+pub fn compressed_size(data: &[u8]) -> usize {
+    let leaves = ordered_leaves(data, TieBreak::default());
+    let lengths = codes_to_lengths(&build_codes_from_leaves(leaves.clone()).expect(COUNTED));
+    let bit_count: u64 = leaves.iter().map(|&(byte, count)| count as u64 * lengths[byte as usize] as u64).sum();
+    let header = Header::new(&lengths, bit_count, data.len() as u64);
+    let header_len = header_options().serialized_size(&header).expect("header is always serializable") as usize;
+    HEADER_START + header_len + bit_count.div_ceil(8) as usize
+}
+
+/// Compress a buffer and report the sizes of the resulting sections
+pub fn compress_with_stats(data: &[u8]) -> (Vec<u8>, CompressionStats) {
+    compress_traced(data, &CompressOptions::default(), None, None).expect(UNCANCELLED)
+}
+
+/// Compress a buffer, calling `on_symbol` with every input byte and the code emitted for it
+pub fn compress_with_trace(data: &[u8], mut on_symbol: impl FnMut(u8, &str)) -> Vec<u8> {
+    compress_traced(data, &CompressOptions::default(), None, Some(&mut on_symbol)).expect(UNCANCELLED).0
+}
+
+fn compress_traced(
+    original: &[u8],
+    options: &CompressOptions,
+    cancel: Option<&CancellationToken>,
+    on_symbol: Option<&mut OnSymbol>,
+) -> Result<(Vec<u8>, CompressionStats), RuffmanError> {
+    check(cancel)?;
+    let mut plan = plan_native(original, options)?;
+    let output = Vec::with_capacity(plan.compressed_len());
+    let (mut output, checksum) = write_native(&plan, output, cancel, on_symbol)?;
+    if options.body_checksum {
+        let header_bytes = plan.finish_header(checksum);
+        let start = plan.header_start();
+        output[start..start + header_bytes.len()].copy_from_slice(&header_bytes);
+    }
+
+    debug!(
+        "coded {} bytes into {} ({} of header, {} distinct symbols)",
+        plan.data.len(),
+        output.len(),
+        plan.header_size(),
+        plan.header.code_lengths.len()
+    );
+    let stats = CompressionStats {
+        original_size: original.len(),
+        compressed_size: output.len(),
+        header_size: plan.header_size(),
+        distinct_symbols: plan.distinct_symbols,
+        #[cfg(feature = "blake3")]
+        hashes: Some(ContentHashes {
+            original: *blake3::hash(original).as_bytes(),
+            compressed: *blake3::hash(&output).as_bytes(),
+        }),
+    };
+    Ok((output, stats))
+}
+
+// Everything about native output that is known before its body is coded: the transformed
+// data, its codes and its header, which already holds the exact bit count
+struct Plan<'a> {
+    data: Cow<'a, [u8]>,
+    lengths: [u8; 256],
+    codes: HashMap<u8, String>,
+    // With an order-1 model, the codes to use after each byte, which replace `codes`
+    context_codes: Option<Vec<HashMap<u8, String>>>,
+    // The same codes as `write_bits` takes them, indexed by byte, one table, or one per
+    // context with an order-1 model; `None` if a code is too long for a `u32`
+    packed_codes: Option<Vec<PackedTable>>,
+    header: Header,
+    distinct_symbols: usize,
+    // Hash of the table the output refers to instead of storing it in `header`
+    table_reference: Option<u32>,
+}
+
+impl Plan<'_> {
+    // Store the body's checksum, if the header has room for one, and give the final header
+    fn finish_header(&mut self, checksum: u32) -> Vec<u8> {
+        if self.header.body_checksum.is_some() {
+            self.header.body_checksum = Some(checksum);
+        }
+        header_options().serialize(&self.header).expect("header is always serializable")
+    }
+
+    // Offset of the header length, after the hash of a table the output refers to
+    fn header_start(&self) -> usize {
+        HEADER_START + if self.table_reference.is_some() { TABLE_REFERENCE_LEN } else { 0 }
+    }
+
+    // The code of `byte` in the table chosen by the byte before it, `context`
+    fn code(&self, context: u8, byte: u8) -> &str {
+        match &self.context_codes {
+            Some(tables) => &tables[context as usize][&byte],
+            None => &self.codes[&byte],
+        }
+    }
+
+    fn header_size(&self) -> usize {
+        let header_len = header_options().serialized_size(&self.header).expect("header is always serializable");
+        self.header_start() + header_len as usize
+    }
+
+    fn compressed_len(&self) -> usize {
+        self.header_size() + self.header.bit_count().div_ceil(8)
+    }
+}
+
+// Frequencies counted over the data cannot overflow, so this only fails if `verify_tree`
+// finds a broken tree
+fn plan_native<'a>(original: &'a [u8], options: &CompressOptions) -> Result<Plan<'a>, RuffmanError> {
+    let data = match options.quantize {
+        Some(levels) => Cow::Owned(quantize(original, levels)),
+        None => Cow::Borrowed(original),
+    };
+    let (columns, data) = match options.columns {
+        Some(delimiter) => {
+            let (layout, transformed) = split_columns(&data, delimiter);
+            (Some(layout), Cow::Owned(transformed))
+        }
+        None => (None, data),
+    };
+    let data = if options.nibbles { Cow::Owned(split_nibbles(&data)) } else { data };
+
+    // Only the code lengths come from the tree; the codes themselves are canonical
+    let mut counts = [0u64; 256];
+    for &byte in data.iter() {
+        counts[byte as usize] += 1;
+    }
+    let build: BuildCodes = if options.verify_tree { build_codes_verified } else { build_codes_from_leaves };
+    let order1 = options.order1.then(|| order1_lengths(&data, options.tie_break, build)).transpose()?;
+    let lengths = match options.reuse_code_lengths {
+        _ if order1.is_some() => [0; 256],
+        Some(lengths) if (0..256).all(|byte| counts[byte] == 0 || lengths[byte] > 0) => lengths,
+        _ => codes_to_lengths(&build(ordered_leaves(&data, options.tie_break))?),
+    };
+    let bit_count = match &order1 {
+        Some((_, bit_count)) => *bit_count,
+        None => (0..256).map(|byte| counts[byte] * lengths[byte] as u64).sum(),
+    };
+
+    // Only the reused table can be referred to, the decoder having no other
+    let table_reference = match options.reuse_code_lengths {
+        Some(reused) if options.table_reference && order1.is_none() && reused == lengths => {
+            Some(table_hash(&lengths))
+        }
+        _ => None,
+    };
+
+    let mut header = Header::new(&lengths, bit_count, data.len() as u64);
+    if table_reference.is_some() {
+        header.code_lengths.clear();
+    }
+    header.contexts = order1.as_ref().map(|(tables, _)| {
+        (0..=255u8)
+            .map(|context| (context, table_entries(&tables[context as usize])))
+            .filter(|(_, entries)| !entries.is_empty())
+            .collect()
+    });
+    header.columns = columns;
+    header.nibbles = options.nibbles;
+    header.quantize_levels = options.quantize;
+    header.comment = options.comment.clone();
+    header.tie_break = options.tie_break;
+    header.bit_order = options.bit_order;
+    header.created = options.created;
+    // Filled in after the body, in place, since a `Some` serializes to the same length whatever its value
+    if options.body_checksum {
+        header.body_checksum = Some(0);
+    }
+    Ok(Plan {
+        data,
+        lengths,
+        codes: lengths_to_codes(lengths),
+        packed_codes: match &order1 {
+            Some((tables, _)) => tables.iter().map(packed_table).collect(),
+            None => packed_table(&lengths).map(|table| vec![table]),
+        },
+        context_codes: order1.map(|(tables, _)| tables.into_iter().map(lengths_to_codes).collect()),
+        header,
+        distinct_symbols: counts.iter().filter(|&&count| count > 0).count(),
+        table_reference,
+    })
+}
+
+// Called with every byte coded and its code, as `compress_with_trace` takes it
+type OnSymbol<'a> = dyn FnMut(u8, &str) + 'a;
+
+// Codes and their lengths indexed by byte, for `BitWriter::write_bits`
+type PackedTable = [(u32, u8); 256];
+
+// `lengths_to_packed_codes` indexed by byte, or `None` if a code does not fit a `u32`
+fn packed_table(lengths: &[u8; 256]) -> Option<PackedTable> {
+    let mut table = [(0, 0); 256];
+    for (byte, code, len) in lengths_to_packed_codes(lengths)? {
+        table[byte as usize] = (code, len);
+    }
+    Some(table)
+}
+
+// Builds the codes of leaves in tie-break order, checking the tree or not
+type BuildCodes = fn(Vec<(u8, usize)>) -> Result<HashMap<u8, String>, RuffmanError>;
+
+// Order-1 code lengths: for each byte, those of the bytes that follow it, with the number
+// of bits they code `data` in
+fn order1_lengths(data: &[u8], tie_break: TieBreak, build: BuildCodes) -> Result<(Vec<[u8; 256]>, u64), RuffmanError> {
+    // Kept in input order, so every tie-break policy still applies within a context
+    let mut followers = vec![Vec::new(); 256];
+    let mut context = 0u8;
+    for &byte in data {
+        followers[context as usize].push(byte);
+        context = byte;
+    }
+    let mut bit_count = 0;
+    let tables = followers
+        .iter()
+        .map(|following| {
+            if following.is_empty() {
+                return Ok([0; 256]);
+            }
+            let leaves = ordered_leaves(following, tie_break);
+            let lengths = codes_to_lengths(&build(leaves)?);
+            bit_count += following.iter().map(|&byte| lengths[byte as usize] as u64).sum::<u64>();
+            Ok(lengths)
+        })
+        .collect::<Result<_, RuffmanError>>()?;
+    Ok((tables, bit_count))
+}
+
+// Write `plan` as a complete file to `writer`, coding the body as it goes, and return the
+// writer with the body's checksum. A header with a checksum still needs it filling in.
+// Codes are written from their packed form where they fit one, and looked up as strings
+// only if they do not or `on_symbol` wants to see them.
+fn write_native<W: Write>(
+    plan: &Plan,
+    mut writer: W,
+    cancel: Option<&CancellationToken>,
+    mut on_symbol: Option<&mut OnSymbol>,
+) -> Result<(W, u32), RuffmanError> {
+    let header_bytes = header_options().serialize(&plan.header).expect("header is always serializable");
+    if let Some(hash) = plan.table_reference {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&[TABLE_REFERENCE_MODE])?;
+        writer.write_all(&hash.to_le_bytes())?;
+    }
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&[HUFFMAN_MODE])?;
+    writer.write_all(&(header_bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(&header_bytes)?;
+
+    let checksummed = ChecksumWriter {
+        inner: writer,
+        checksum: crc32fast::Hasher::new(),
+    };
+    let mut body = BitWriter::with_bit_order(checksummed, plan.header.bit_order);
+    let mut context = 0u8;
+    for (index, &byte) in plan.data.iter().enumerate() {
+        if index % CHECK_INTERVAL == 0 {
+            check(cancel)?;
+        }
+        #[cfg(test)]
+        let byte = match tests::INJECT_FAULT.get() {
+            true if index == 0 => plan.codes.keys().copied().find(|&other| other != byte).unwrap_or(byte),
+            _ => byte,
+        };
+        if let Some(on_symbol) = on_symbol.as_mut() {
+            on_symbol(byte, plan.code(context, byte));
+        }
+        match &plan.packed_codes {
+            Some(tables) => {
+                let table = if plan.context_codes.is_some() { context as usize } else { 0 };
+                let (code, len) = tables[table][byte as usize];
+                body.write_bits(code, len)?;
+            }
+            None => body.write_code(plan.code(context, byte))?,
+        }
+        context = byte;
+    }
+    let written = body.finish_with(PADDING)?;
+    Ok((written.inner, written.checksum.finalize()))
+}
+
+// Passes bytes on to `inner`, taking their CRC-32 on the way
+struct ChecksumWriter<W> {
+    inner: W,
+    checksum: crc32fast::Hasher,
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.checksum.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Code length of every byte in a buffer produced by `compress`, 0 for bytes without a
+/// code, for `CompressOptions::reuse_code_lengths`
+pub fn read_code_lengths(compressed: &[u8]) -> Result<[u8; 256], RuffmanError> {
+    let (header, _) = parse_header(compressed)?;
+    header.decoding_table()?;
+    let mut lengths = [0u8; 256];
+    for &(byte, length) in &header.code_lengths {
+        lengths[byte as usize] = length;
+    }
+    Ok(lengths)
+}
+
+/// Sizes of a buffer produced by `compress`, read back from its header without decoding
+/// the body, e.g. for a file written earlier. `original_size` is the coded byte count,
+/// which is the input size unless a transform such as `columns` or `nibbles` changed it;
+/// callers that know the input size can put it in its place.
+pub fn read_compression_stats(compressed: &[u8]) -> Result<CompressionStats, RuffmanError> {
+    let (header, _) = parse(compressed)?;
+    let distinct_symbols = match &header.contexts {
+        Some(contexts) => {
+            let mut coded = [false; 256];
+            for &(byte, _) in contexts.iter().flat_map(|(_, entries)| entries) {
+                coded[byte as usize] = true;
+            }
+            coded.iter().filter(|&&coded| coded).count()
+        }
+        None => header.code_lengths.len(),
+    };
+    Ok(CompressionStats {
+        original_size: header.byte_count(),
+        compressed_size: compressed.len(),
+        header_size: header.body_start,
+        distinct_symbols,
+        #[cfg(feature = "blake3")]
+        hashes: None,
+    })
+}
+
+/// Decompress a buffer produced by `compress` or, with the `gzip` feature, a gzip member
+pub fn decompress_to_vec(compressed: &[u8]) -> Result<Vec<u8>, RuffmanError> {
+    #[cfg(feature = "gzip")]
+    if crate::gzip::is_gzip(compressed) {
+        return crate::gzip::decompress(compressed);
+    }
+
+    codec_for(compressed)?.decode(compressed)
+}
+
+// Decompress a buffer in the Huffman mode
+pub(crate) fn decode_huffman(compressed: &[u8], cancel: Option<&CancellationToken>) -> Result<Vec<u8>, RuffmanError> {
+    let (header, body) = parse(compressed)?;
+    let mut decompressed_data = Vec::with_capacity(header.byte_count());
+    decode_body(&header, body, cancel, |byte| decompressed_data.push(byte))?;
+    header.restore(decompressed_data)
+}
+
+/// Decompress a buffer written with `CompressOptions::table_reference`, given the code
+/// lengths it was coded with, e.g. `read_code_lengths` of the file they were reused from.
+/// Lengths other than those it refers to fail with `RuffmanError::CodeTableMismatch`. Any
+/// other buffer decompresses as with `decompress_to_vec`, and `lengths` go unused.
+pub fn decompress_with_code_lengths(compressed: &[u8], lengths: &[u8; 256]) -> Result<Vec<u8>, RuffmanError> {
+    if compressed.get(MAGIC.len()) != Some(&TABLE_REFERENCE_MODE) {
+        return decompress_to_vec(compressed);
+    }
+    let (hash, mut header, body) = parse_table_reference(compressed)?;
+    if hash != table_hash(lengths) {
+        return Err(RuffmanError::CodeTableMismatch);
+    }
+    header.code_lengths = table_entries(lengths);
+    let mut decompressed_data = Vec::with_capacity(header.byte_count());
+    decode_body(&header, body, None, |byte| decompressed_data.push(byte))?;
+    header.restore(decompressed_data)
+}
+
+/// Data salvaged from a damaged buffer by `decompress_recover`
+#[derive(Debug)]
+pub struct Recovery {
+    /// The bytes decoded before the damage, a prefix of the original data
+    pub data: Vec<u8>,
+    /// What stopped decoding early, if anything
+    pub error: Option<RuffmanError>,
+}
+
+/// Decompress as much of a truncated or corrupt buffer as possible. Only an unreadable
+/// header is an error; damage to the body ends decoding and is reported in the result.
+pub fn decompress_recover(compressed: &[u8]) -> Result<Recovery, RuffmanError> {
+    let (header, body) = parse_header(compressed)?;
+    recover(header, body)
+}
+
+// `decompress_recover`, for a buffer that may refer to the code table `lengths`
+fn decompress_recover_with_code_lengths(compressed: &[u8], lengths: &[u8; 256]) -> Result<Recovery, RuffmanError> {
+    if compressed.get(MAGIC.len()) != Some(&TABLE_REFERENCE_MODE) {
+        return decompress_recover(compressed);
+    }
+    let (hash, mut header, body) = split_table_reference(compressed, parse_header)?;
+    if hash != table_hash(lengths) {
+        return Err(RuffmanError::CodeTableMismatch);
+    }
+    header.code_lengths = table_entries(lengths);
+    recover(header, body)
+}
+
+// Decode what there is of `body`, stopping at the first damage
+fn recover(mut header: Header, body: &[u8]) -> Result<Recovery, RuffmanError> {
+    // Only decode the bits that are actually there, and no more bytes than those could hold
+    header.original_bit_count = header.original_bit_count.min(body.len() as u64 * 8).min(usize::MAX as u64);
+    header.original_byte_count = header.original_byte_count.min(header.original_bit_count);
+    // A damaged body fails its checksum, but its readable prefix is still worth having
+    header.body_checksum = None;
+
+    let mut data = Vec::new();
+    let error = decode_body(&header, body, None, |byte| data.push(byte)).err();
+
+    // A partial column-major body cannot be put back into row order
+    if header.is_transformed() {
+        return match error {
+            None => Ok(Recovery {
+                data: header.restore(data)?,
+                error: None,
+            }),
+            Some(error) => Ok(Recovery {
+                data: Vec::new(),
+                error: Some(error),
+            }),
+        };
+    }
+    Ok(Recovery { data, error })
+}
+
+/// Decompress a buffer into a new temporary file, for APIs that want a path rather than
+/// bytes. The file is only readable by the current user and is deleted when the returned
+/// `TempPath` is dropped. Returns the path and the number of bytes written.
+#[cfg(feature = "tempfile")]
+pub fn decompress_to_tempfile(compressed: &[u8]) -> Result<(tempfile::TempPath, usize), RuffmanError> {
+    let data = decompress_to_vec(compressed)?;
+    let mut file = tempfile::NamedTempFile::new()?;
+    file.write_all(&data)?;
+    file.flush()?;
+    Ok((file.into_temp_path(), data.len()))
+}
+
+/// Decompress a buffer produced by `compress` into `out`, returning the number of bytes written
+pub fn decompress_into_slice(compressed: &[u8], out: &mut [u8]) -> Result<usize, RuffmanError> {
+    #[cfg(feature = "gzip")]
+    if crate::gzip::is_gzip(compressed) {
+        return copy_into_slice(&crate::gzip::decompress(compressed)?, out);
+    }
+
+    let (header, body) = parse(compressed)?;
+    // Transformed data has to be restored in a buffer of its own first
+    if header.is_transformed() {
+        return copy_into_slice(&decompress_to_vec(compressed)?, out);
+    }
+    if out.len() < header.byte_count() {
+        return Err(RuffmanError::OutputTooSmall {
+            needed: header.byte_count(),
+            available: out.len(),
+        });
+    }
+
+    let mut written = 0;
+    decode_body(&header, body, None, |byte| {
+        out[written] = byte;
+        written += 1;
+    })?;
+    Ok(written)
+}
+
+fn copy_into_slice(decompressed: &[u8], out: &mut [u8]) -> Result<usize, RuffmanError> {
+    if out.len() < decompressed.len() {
+        return Err(RuffmanError::OutputTooSmall {
+            needed: decompressed.len(),
+            available: out.len(),
+        });
+    }
+    out[..decompressed.len()].copy_from_slice(decompressed);
+    Ok(decompressed.len())
+}
+
+// Split a compressed buffer into its header and body, checking the sizes it claims
+pub(crate) fn parse(compressed: &[u8]) -> Result<(Header, &[u8]), RuffmanError> {
+    let (header, body) = parse_header(compressed)?;
+
+    // Cheap structural checks, before the body checksum or any decoding
+    let body_len = header.original_bit_count.div_ceil(8);
+    if (body.len() as u64) < body_len {
+        return Err(RuffmanError::TruncatedBody {
+            expected: usize::try_from(body_len).unwrap_or(usize::MAX),
+            actual: body.len(),
+        });
+    }
+    // Every code is at least one bit long
+    if header.original_byte_count > header.original_bit_count {
+        return Err(corrupt_at(HEADER_START, "byte count larger than bit count"));
+    }
+    // Both counts then fit a `usize` wherever the bit count does
+    if usize::try_from(header.original_bit_count).is_err() {
+        return Err(RuffmanError::TooLarge(header.original_bit_count));
+    }
+    Ok((header, body))
+}
+
+// Split a file that refers to its code table into the table's hash and the header and body
+// of the Huffman file inside it, checking the sizes it claims as `parse` does. The header's
+// table is left empty for the caller to fill in.
+pub(crate) fn parse_table_reference(compressed: &[u8]) -> Result<(u32, Header, &[u8]), RuffmanError> {
+    split_table_reference(compressed, parse)
+}
+
+// `parse` or `parse_header`
+type ParseFn = fn(&[u8]) -> Result<(Header, &[u8]), RuffmanError>;
+
+// `parse_table_reference`, reading the inner file with `parse_inner`
+fn split_table_reference(
+    compressed: &[u8],
+    parse_inner: ParseFn,
+) -> Result<(u32, Header, &[u8]), RuffmanError> {
+    if !compressed.starts_with(&MAGIC) {
+        return Err(RuffmanError::BadMagic);
+    }
+    let Some(hash) = compressed.get(HEADER_LEN_START..TABLE_REFERENCE_LEN) else {
+        return Err(corrupt_at(HEADER_LEN_START, "missing code table hash"));
+    };
+    let hash = u32::from_le_bytes(hash.try_into().unwrap());
+    // Offsets in the inner file are moved to where it sits in this one
+    let (mut header, body) = parse_inner(&compressed[TABLE_REFERENCE_LEN..]).map_err(|e| match e {
+        RuffmanError::CorruptDataAt { offset, message } => RuffmanError::CorruptDataAt {
+            offset: offset + TABLE_REFERENCE_LEN,
+            message,
+        },
+        e => e,
+    })?;
+    if !header.code_lengths.is_empty() || header.contexts.is_some() {
+        return Err(corrupt_at(TABLE_REFERENCE_LEN + HEADER_START, "file refers to a code table but stores one"));
+    }
+    header.body_start += TABLE_REFERENCE_LEN;
+    Ok((hash, header, body))
+}
+
+// Split a compressed buffer into its header and whatever body follows it
+pub(crate) fn parse_header(compressed: &[u8]) -> Result<(Header, &[u8]), RuffmanError> {
+    if !compressed.starts_with(&MAGIC) {
+        return Err(RuffmanError::BadMagic);
+    }
+    match compressed.get(MAGIC.len()) {
+        Some(&HUFFMAN_MODE) => {}
+        Some(&TABLE_REFERENCE_MODE) => return Err(RuffmanError::CodeTableRequired),
+        Some(&mode) => return Err(RuffmanError::UnsupportedMode(mode)),
+        None => return Err(corrupt_at(MAGIC.len(), "missing mode byte")),
+    }
+    if compressed.len() < HEADER_START {
+        return Err(corrupt_at(HEADER_LEN_START, "missing header length"));
+    }
+    let header_len = u32::from_le_bytes(compressed[HEADER_LEN_START..HEADER_START].try_into().unwrap()) as usize;
+    let header_end = HEADER_START + header_len;
+    if compressed.len() < header_end {
+        return Err(corrupt_at(HEADER_START, "truncated header"));
+    }
+
+    // The code table comes first, behind its entry count; refuse an impossible count before
+    // bincode sizes anything by it
+    let entry_count = compressed
+        .get(HEADER_START..HEADER_START + 8)
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()));
+    if entry_count.is_some_and(|count| count > MAX_CODE_ENTRIES) {
+        return Err(corrupt_at(HEADER_START, "code table has more entries than there are byte values"));
+    }
+
+    // Deserialize the code table from the compressed data
+    let mut header: Header = header_options()
+        .deserialize(&compressed[HEADER_START..header_end])
+        .map_err(|e| {
+            // The serializer's own message means nothing to users; keep it for debugging
+            debug!("header does not deserialize: {}", e);
+            RuffmanError::UnsupportedHeaderEncoding
+        })?;
+    header.body_start = header_end;
+    let body = &compressed[header_end..];
+
+    if header.comment.as_ref().is_some_and(|comment| comment.len() > MAX_COMMENT_LEN) {
+        return Err(corrupt_at(HEADER_START, "comment longer than allowed"));
+    }
+    Ok((header, body))
+}
+
+// Decode the body, handing every decoded byte to `emit`. At most
+// `header.original_byte_count` bytes are emitted, and none if the body checksum does not match.
+pub(crate) fn decode_body(
+    header: &Header,
+    body: &[u8],
+    cancel: Option<&CancellationToken>,
+    mut emit: impl FnMut(u8),
+) -> Result<(), RuffmanError> {
+    let tables = header.decoding_tables()?;
+    // Only order-1 data switches tables, by the last byte decoded
+    let order1 = header.contexts.is_some();
+    let mut context = 0;
+
+    if let Some(expected) = header.body_checksum {
+        // Only the bytes the bit count covers, not alignment padding or a following member
+        let body_len = (header.original_bit_count.div_ceil(8) as usize).min(body.len());
+        let actual = crc32fast::hash(&body[..body_len]);
+        if actual != expected {
+            return Err(RuffmanError::BodyChecksumMismatch { expected, actual });
+        }
+    }
+
+    // Reconstruct the original data using the Huffman codes
+    let mut decoded = 0;
+    let mut code: PackedCode = (0, 0);
+    // Offset of the byte holding bit `index` of the body
+    let offset_of = |index: usize| header.body_start + index / 8;
+
+    // Only the header's bit count is read, which leaves the padding out
+    let mut bits = BitReader::with_bit_order(body, header.bit_order);
+    let mut bit_count = 0;
+    for index in 0..header.bit_count() {
+        if index % (CHECK_INTERVAL * 8) == 0 {
+            check(cancel)?;
+        }
+        let Some(bit) = bits.read_bit()? else {
+            break;
+        };
+        bit_count += 1;
+        push_bit(&mut code, bit);
+        // No code is longer than the longest, so a longer run of bits can be rejected straight away
+        let (codes, longest_code) = &tables[context];
+        if let Some(&byte) = codes.get(&code) {
+            if decoded == header.byte_count() {
+                return Err(corrupt_at(offset_of(index), "more data than the header announces"));
+            }
+            emit(byte);
+            decoded += 1;
+            code = (0, 0);
+            if order1 {
+                context = byte as usize;
+            }
+        } else if code.0 as usize >= *longest_code {
+            return Err(corrupt_at(offset_of(index + 1 - code.0 as usize), "bits match no code"));
+        }
+    }
+
+    if code.0 > 0 {
+        let code_start = bit_count - code.0 as usize;
+        return Err(corrupt_at(offset_of(code_start), "body ends in the middle of a code"));
+    }
+    if decoded != header.byte_count() {
+        return Err(RuffmanError::LengthMismatch {
+            expected: header.byte_count(),
+            actual: decoded,
+        });
+    }
+
+    Ok(())
+}
+
+/// Options controlling decompression
+#[derive(Debug, Clone)]
+pub struct DecompressOptions {
+    /// Write out whatever decodes before damage instead of failing outright
+    pub recover: bool,
+    /// Capacity of the file reader and writer buffers
+    pub io_buffer_size: usize,
+    /// Leave holes instead of writing blocks of zeros, so sparse files stay sparse
+    pub sparse: bool,
+    /// Stop with `RuffmanError::Cancelled` once this is cancelled, before the output is created
+    pub cancel: Option<CancellationToken>,
+    /// Permission bits, e.g. `0o600`, to create the output file with instead of the usual
+    /// defaults, less any the umask clears. They are set as the file is created, so it is
+    /// never readable more widely; a file that already exists keeps its own. In place, they
+    /// replace the input's permissions. Ignored except on Unix.
+    pub output_mode: Option<u32>,
+    /// Read the input as framed compressed files, as written with `CompressOptions::framed`,
+    /// and decompress each in turn, the output being all of them one after another
+    pub framed: bool,
+    /// Code lengths to decompress a file written with `CompressOptions::table_reference`
+    /// with, as `decompress_with_code_lengths` takes them. Other files leave them unused.
+    pub code_lengths: Option<[u8; 256]>,
+}
+
+impl Default for DecompressOptions {
+    fn default() -> Self {
+        DecompressOptions {
+            recover: false,
+            io_buffer_size: DEFAULT_IO_BUFFER_SIZE,
+            sparse: false,
+            cancel: None,
+            output_mode: None,
+            framed: false,
+            code_lengths: None,
+        }
+    }
+}
+
+/// Compress a file using Huffman encoding
+pub fn compress_file(input_path: &Path, output_path: &Path) -> Result<Vec<Warning>, RuffmanError> {
+    compress_file_with(input_path, output_path, &CompressOptions::default())
+}
+
+/// Compress a file using the given options, returning any warnings
+pub fn compress_file_with(
+    input_path: &Path,
+    output_path: &Path,
+    options: &CompressOptions,
+) -> Result<Vec<Warning>, RuffmanError> {
+    options.validate()?;
+    info!("compressing {} to {}", input_path.display(), output_path.display());
+    let input = open_input(File::open(input_path)?, options.io_buffer_size)?;
+    let warnings = write_compressed(&input, output_path, options)?;
+    // Output coded from a mapped file that changed meanwhile may mix old and new data
+    if let Err(e) = input.finish() {
+        let _ = fs::remove_file(output_path);
+        return Err(e.into());
+    }
+
+    Ok(warnings)
+}
+
+/// Compress `length` bytes of a file starting at `offset`, or everything from `offset` on
+/// without a length, as `compress_file_with` does for a whole file. Only that range is
+/// read, so a record can be taken out of a large file without copying it first, and the
+/// result decompresses to just those bytes. A range that runs past the end of the file is
+/// an error.
+pub fn compress_file_range(
+    input_path: &Path,
+    output_path: &Path,
+    offset: u64,
+    length: Option<u64>,
+    options: &CompressOptions,
+) -> Result<Vec<Warning>, RuffmanError> {
+    options.validate()?;
+    let mut input_file = File::open(input_path)?;
+    let file_len = input_file.metadata()?.len();
+    let end = match length {
+        Some(length) => offset.checked_add(length),
+        None => Some(file_len),
+    };
+    let Some(end) = end.filter(|&end| offset <= end && end <= file_len) else {
+        return Err(RuffmanError::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("range at offset {} runs past the end of {} ({} bytes)", offset, input_path.display(), file_len),
+        )));
+    };
+    info!("compressing bytes {}..{} of {} to {}", offset, end, input_path.display(), output_path.display());
+    input_file.seek(SeekFrom::Start(offset))?;
+    let mut input = Vec::with_capacity((end - offset) as usize);
+    input_file.take(end - offset).read_to_end(&mut input)?;
+    if input.len() as u64 != end - offset {
+        return Err(RuffmanError::Io(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "input file changed while it was being compressed",
+        )));
+    }
+    write_compressed(&input, output_path, options)
+}
+
+/// Compress everything `reader` yields, e.g. stdin, to a file, as `compress_file_with` does
+/// for a file. `options.input_size_hint` sizes the input buffer.
+pub fn compress_reader_to_file<R: Read>(
+    mut reader: R,
+    output_path: &Path,
+    options: &CompressOptions,
+) -> Result<Vec<Warning>, RuffmanError> {
+    options.validate()?;
+    let mut input = Vec::with_capacity(options.input_size_hint.unwrap_or(0));
+    reader.read_to_end(&mut input)?;
+    write_compressed(&input, output_path, options)
+}
+
+/// Decompress the gzip file at `input_path` and compress what it holds to `output_path`, as
+/// `compress_file_with` does for a file, to move `.gz` files over to `options.format`. The
+/// input is recognised by its magic bytes, whatever it is called.
+#[cfg(feature = "gzip")]
+pub fn transcode_from_gzip(
+    input_path: &Path,
+    output_path: &Path,
+    options: &CompressOptions,
+) -> Result<Vec<Warning>, RuffmanError> {
+    options.validate()?;
+    let compressed = fs::read(input_path)?;
+    if !crate::gzip::is_gzip(&compressed) {
+        return Err(RuffmanError::CorruptData(format!("{} is not a gzip file", input_path.display())));
+    }
+    info!("transcoding {} to {}", input_path.display(), output_path.display());
+    write_compressed(&crate::gzip::decompress(&compressed)?, output_path, options)
+}
+
+// Compress `input` to `output_path`, subject to `min_ratio` and `verify_after`
+fn write_compressed(input: &[u8], output_path: &Path, options: &CompressOptions) -> Result<Vec<Warning>, RuffmanError> {
+    let cancel = options.cancel.as_ref();
+    check(cancel)?;
+    let data = if options.normalize_line_endings { normalize_line_endings(input) } else { Cow::Borrowed(input) };
+    // Native output is planned up front and its body coded straight into the file; only
+    // gzip output is built in memory first
+    let output = match options.format {
+        Format::Ruffman => Output::Native(Box::new(plan_native(&data, options)?)),
+        #[cfg(feature = "gzip")]
+        Format::Gzip => Output::Buffered(crate::gzip::compress(&data)),
+    };
+    let (compressed_len, used_lengths) = match &output {
+        Output::Native(plan) => (plan.compressed_len(), Some(plan.lengths)),
+        #[cfg(feature = "gzip")]
+        Output::Buffered(compressed) => (compressed.len(), None),
+    };
+    let framed_len = compressed_len + if options.framed { FRAME_PREFIX_LEN } else { 0 };
+    // Padding goes inside the frame, so the frame as a whole ends on the boundary
+    let padding = alignment_padding(framed_len, options);
+    let prefix = options.framed.then(|| frame_prefix(compressed_len + padding)).transpose()?;
+    let output_len = framed_len + padding;
+    let warnings = warnings_for(input, output_len, used_lengths, options);
+
+    // The size is known before anything is written, so the output is only created if it is worth it
+    if let Some(threshold) = options.min_ratio {
+        let ratio = size_ratio(input.len(), output_len);
+        if ratio > threshold {
+            info!("not writing {}: ratio {:.3} is above {:.3}", output_path.display(), ratio, threshold);
+            return Err(RuffmanError::NotWorthCompressing { ratio, threshold });
+        }
+    }
+
+    let output_file = File::create(output_path)?;
+    let mut writer = BufWriter::with_capacity(options.io_buffer_size, output_file);
+    let written = match prefix {
+        Some(prefix) => writer.write_all(&prefix).map_err(RuffmanError::from),
+        None => Ok(()),
+    };
+    let start = (framed_len - compressed_len) as u64;
+    let written = written
+        .and_then(|()| write_output(output, writer, start, cancel))
+        // Extending the file fills it with zeros without writing them
+        .and_then(|file| file.set_len(output_len as u64).map_err(RuffmanError::from));
+    if let Err(e) = written {
+        let _ = fs::remove_file(output_path);
+        return Err(e);
+    }
+
+    if options.verify_after {
+        let expected = lossy_round_trip(input, options)?;
+        let written = fs::read(output_path)?;
+        if decompress_written(&written, options).ok().as_deref() != Some(&expected[..]) {
+            warn!("{} does not decompress to its input; removing it", output_path.display());
+            fs::remove_file(output_path)?;
+            return Err(RuffmanError::VerificationFailed);
+        }
+    }
+    info!("wrote {}: {} -> {} bytes", output_path.display(), input.len(), output_len);
+    Ok(warnings)
+}
+
+// Compress `input` in memory, subject to `min_ratio` and `verify_after` as in
+// `write_compressed`, for callers that write the result themselves
+#[cfg(feature = "tokio")]
+pub(crate) fn compress_checked(
+    input: &[u8],
+    options: &CompressOptions,
+) -> Result<(Vec<u8>, Vec<Warning>), RuffmanError> {
+    let compressed = compress_cancellable(input, options, options.cancel.as_ref())?;
+    let used_lengths = read_code_lengths(unframed(&compressed, options)).ok();
+    let warnings = warnings_for(input, compressed.len(), used_lengths, options);
+    if let Some(threshold) = options.min_ratio {
+        let ratio = size_ratio(input.len(), compressed.len());
+        if ratio > threshold {
+            return Err(RuffmanError::NotWorthCompressing { ratio, threshold });
+        }
+    }
+    if options.verify_after {
+        let expected = lossy_round_trip(input, options)?;
+        if decompress_written(&compressed, options).ok().as_deref() != Some(&expected[..]) {
+            return Err(RuffmanError::VerificationFailed);
+        }
+    }
+    Ok((compressed, warnings))
+}
+
+// Compressed output on its way to a file
+enum Output<'a> {
+    Native(Box<Plan<'a>>),
+    #[cfg(feature = "gzip")]
+    Buffered(Vec<u8>),
+}
+
+// Write `output` to a file whose first `start` bytes, a frame prefix, are already in
+// `writer`, and give the file back
+fn write_output(
+    output: Output,
+    writer: BufWriter<File>,
+    start: u64,
+    cancel: Option<&CancellationToken>,
+) -> Result<File, RuffmanError> {
+    match output {
+        Output::Native(mut plan) => {
+            let (writer, checksum) = write_native(&plan, writer, cancel, None)?;
+            let mut file = writer.into_inner().map_err(|e| e.into_error())?;
+            if plan.header.body_checksum.is_some() {
+                file.seek(SeekFrom::Start(start + plan.header_start() as u64))?;
+                file.write_all(&plan.finish_header(checksum))?;
+            }
+            Ok(file)
+        }
+        #[cfg(feature = "gzip")]
+        Output::Buffered(compressed) => {
+            let mut writer = writer;
+            writer.write_all(&compressed)?;
+            Ok(writer.into_inner().map_err(|e| e.into_error())?)
+        }
+    }
+}
+
+// Decompress output written with `options`, with the reused table in case it only refers to it
+fn decompress_written(written: &[u8], options: &CompressOptions) -> Result<Vec<u8>, RuffmanError> {
+    match &options.reuse_code_lengths {
+        Some(lengths) => decompress_with_code_lengths(unframed(written, options), lengths),
+        None => decompress_to_vec(unframed(written, options)),
+    }
+}
+
+// Zero bytes to add to `len` bytes of output to reach a multiple of `options.align`
+fn alignment_padding(len: usize, options: &CompressOptions) -> usize {
+    match options.align {
+        Some(align) if align > 1 && options.format == Format::Ruffman => (align - len % align) % align,
+        _ => 0,
+    }
+}
+
+// What decompressing `data` compressed with `options` should give back, which is only
+// `data` itself when no lossy option is set
+fn lossy_round_trip<'a>(data: &'a [u8], options: &CompressOptions) -> Result<Cow<'a, [u8]>, RuffmanError> {
+    let data = if options.normalize_line_endings { normalize_line_endings(data) } else { Cow::Borrowed(data) };
+    Ok(match options.quantize {
+        Some(levels) => Cow::Owned(dequantize(&quantize(&data, levels), levels)?),
+        None => data,
+    })
+}
+
+/// BLAKE3 hash of what `reader` decompresses to, for checking a file against a known hash
+/// without writing its contents anywhere. In recover mode, damage is an error here too,
+/// since a partial result cannot match.
+#[cfg(feature = "blake3")]
+pub fn decompressed_hash<R: Read>(reader: R, options: &DecompressOptions) -> Result<[u8; 32], RuffmanError> {
+    let mut hasher = blake3::Hasher::new();
+    let report = decompress_reader_to_writer(reader, &mut hasher, options)?;
+    if let Some(damage) = report.damage {
+        return Err(damage);
+    }
+    Ok(*hasher.finalize().as_bytes())
+}
+
+/// Decompress a file using Huffman encoding
+pub fn decompress_file(input_path: &Path, output_path: &Path) -> Result<(), RuffmanError> {
+    decompress_file_with(input_path, output_path, &DecompressOptions::default()).map(|_| ())
+}
+
+/// Outcome of decompressing a file
+#[derive(Debug)]
+pub struct DecompressReport {
+    pub bytes_written: usize,
+    /// In recover mode, the damage that cut decoding short
+    pub damage: Option<RuffmanError>,
+}
+
+/// Decompress a file using the given options
+pub fn decompress_file_with(
+    input_path: &Path,
+    output_path: &Path,
+    options: &DecompressOptions,
+) -> Result<DecompressReport, RuffmanError> {
+    info!("decompressing {} to {}", input_path.display(), output_path.display());
+    let input_file = File::open(input_path)?;
+    let mut reader = BufReader::with_capacity(options.io_buffer_size, input_file);
+    let mut compressed_data = Vec::new();
+    reader.read_to_end(&mut compressed_data)?;
+    let (decompressed_data, damage) = decode_with(&compressed_data, options)?;
+
+    let output_file = create_output(output_path, options.output_mode)?;
+    if options.sparse {
+        write_sparse(output_file, &decompressed_data, options.io_buffer_size)?;
+    } else {
+        let mut writer = BufWriter::with_capacity(options.io_buffer_size, output_file);
+        writer.write_all(&decompressed_data)?;
+        writer.flush()?;
+    }
+
+    if let Some(damage) = &damage {
+        warn!("{}: recovered {} bytes before damage: {}", input_path.display(), decompressed_data.len(), damage);
+    }
+    info!("wrote {}: {} -> {} bytes", output_path.display(), compressed_data.len(), decompressed_data.len());
+    Ok(DecompressReport {
+        bytes_written: decompressed_data.len(),
+        damage,
+    })
+}
+
+/// Decompress everything `reader` yields, e.g. stdin, and write the result to `writer`,
+/// e.g. stdout piped into another process. Output goes out through `write_all`, so a full
+/// pipe only holds decoding up until the reader on the other end catches up. `sparse` only
+/// applies to files and is ignored.
+pub fn decompress_reader_to_writer<R: Read, W: Write>(
+    mut reader: R,
+    writer: W,
+    options: &DecompressOptions,
+) -> Result<DecompressReport, RuffmanError> {
+    let mut compressed_data = Vec::new();
+    reader.read_to_end(&mut compressed_data)?;
+    let (decompressed_data, damage) = decode_with(&compressed_data, options)?;
+
+    let mut writer = BufWriter::with_capacity(options.io_buffer_size, writer);
+    writer.write_all(&decompressed_data)?;
+    writer.flush()?;
+    Ok(DecompressReport {
+        bytes_written: decompressed_data.len(),
+        damage,
+    })
+}
+
+/// Create or truncate `path` for writing, as decompression creates its output: a new file
+/// gets the permission bits `mode`, e.g. `0o600`, less any the umask clears, as it is
+/// created rather than afterwards. A file that already exists keeps its own, and `mode` is
+/// ignored except on Unix.
+pub fn create_output(path: &Path, mode: Option<u32>) -> io::Result<File> {
+    let mut open = fs::OpenOptions::new();
+    open.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::OpenOptionsExt;
+        open.mode(mode);
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    open.open(path)
+}
+
+// Decode a whole compressed buffer as `options` ask, with the damage that stopped decoding
+// early in recover mode
+pub(crate) fn decode_with(
+    compressed_data: &[u8],
+    options: &DecompressOptions,
+) -> Result<(Vec<u8>, Option<RuffmanError>), RuffmanError> {
+    if !options.framed {
+        return decode_one(compressed_data, options);
+    }
+    let mut frames = compressed_data;
+    let mut decompressed_data = Vec::new();
+    loop {
+        let frame = match read_frame(&mut frames) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return Ok((decompressed_data, None)),
+            // The frames before a cut-off one are still whole
+            Err(e) if options.recover => return Ok((decompressed_data, Some(e))),
+            Err(e) => return Err(e),
+        };
+        let (decoded, damage) = decode_one(&frame, options)?;
+        decompressed_data.extend_from_slice(&decoded);
+        // Recovery ends at the first damage, as it does within a file
+        if damage.is_some() {
+            return Ok((decompressed_data, damage));
+        }
+    }
+}
+
+// Decode a single compressed file as `decode_with` does
+fn decode_one(
+    compressed_data: &[u8],
+    options: &DecompressOptions,
+) -> Result<(Vec<u8>, Option<RuffmanError>), RuffmanError> {
+    let cancel = options.cancel.as_ref();
+    let (decompressed_data, damage) = if options.recover {
+        let recovery = match &options.code_lengths {
+            Some(lengths) => decompress_recover_with_code_lengths(compressed_data, lengths)?,
+            None => decompress_recover(compressed_data)?,
+        };
+        (recovery.data, recovery.error)
+    } else if let Some(lengths) = &options.code_lengths {
+        (decompress_with_code_lengths(compressed_data, lengths)?, None)
+    } else if cancel.is_some() && codec_for(compressed_data).is_ok_and(|codec| codec.mode() == HUFFMAN_MODE) {
+        (decode_huffman(compressed_data, cancel)?, None)
+    } else {
+        (decompress_to_vec(compressed_data)?, None)
+    };
+    check(cancel)?;
+    Ok((decompressed_data, damage))
+}
+
+// Write `data` to a fresh file, seeking over every all-zero block instead of writing it so
+// the file system can leave a hole there. `set_len` covers a hole at the very end.
+fn write_sparse(file: File, data: &[u8], buffer_size: usize) -> Result<(), RuffmanError> {
+    let mut writer = BufWriter::with_capacity(buffer_size, file);
+    for block in data.chunks(SPARSE_BLOCK_SIZE) {
+        if block.iter().all(|&byte| byte == 0) {
+            writer.seek(SeekFrom::Current(block.len() as i64))?;
+        } else {
+            writer.write_all(block)?;
+        }
+    }
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.set_len(data.len() as u64)?;
+    Ok(())
+}
+
+fn corrupt_at(offset: usize, message: &str) -> RuffmanError {
+    RuffmanError::CorruptDataAt {
+        offset,
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    thread_local! {
+        // Makes `write_native` code the first byte as another one, as a codec bug would
+        pub(super) static INJECT_FAULT: Cell<bool> = const { Cell::new(false) };
+    }
+
+    fn verified() -> CompressOptions {
+        CompressOptions {
+            verify_after: true,
+            ..CompressOptions::default()
+        }
+    }
+
+    #[test]
+    fn verify_after_keeps_good_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let (input, output) = (dir.path().join("input"), dir.path().join("output"));
+        fs::write(&input, b"abracadabra").unwrap();
+        compress_file_with(&input, &output, &verified()).unwrap();
+        assert_eq!(decompress_to_vec(&fs::read(&output).unwrap()).unwrap(), b"abracadabra");
+    }
+
+    #[test]
+    fn verify_after_removes_output_of_a_faulty_codec() {
+        let dir = tempfile::tempdir().unwrap();
+        let (input, output) = (dir.path().join("input"), dir.path().join("output"));
+        fs::write(&input, b"abracadabra").unwrap();
+        INJECT_FAULT.set(true);
+        let result = compress_file_with(&input, &output, &verified());
+        INJECT_FAULT.set(false);
+        assert!(matches!(result, Err(RuffmanError::VerificationFailed)));
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn faulty_codec_goes_unnoticed_without_verify_after() {
+        let dir = tempfile::tempdir().unwrap();
+        let (input, output) = (dir.path().join("input"), dir.path().join("output"));
+        fs::write(&input, b"abracadabra").unwrap();
+        INJECT_FAULT.set(true);
+        let result = compress_file_with(&input, &output, &CompressOptions::default());
+        INJECT_FAULT.set(false);
+        assert!(result.is_ok());
+        let decompressed = decompress_to_vec(&fs::read(&output).unwrap()).ok();
+        assert_ne!(decompressed.as_deref(), Some(&b"abracadabra"[..]));
+    }
+
+    #[test]
+    fn checksum_writer_takes_the_crc_of_uneven_writes() {
+        let data = include_bytes!("codec.rs");
+        let mut writer = ChecksumWriter { inner: Vec::new(), checksum: crc32fast::Hasher::new() };
+        let mut rest = &data[..];
+        for size in [1, 7, 0, 300, 4096].iter().cycle() {
+            if rest.is_empty() {
+                break;
+            }
+            let (chunk, tail) = rest.split_at((*size).min(rest.len()));
+            writer.write_all(chunk).unwrap();
+            rest = tail;
+        }
+        assert_eq!(writer.inner, data);
+        assert_eq!(writer.checksum.finalize(), crc32fast::hash(data));
+    }
+}
@@ -0,0 +1,57 @@
+use crate::codec::{compress, decode_huffman, MAGIC};
+use crate::error::RuffmanError;
+
+// Mode byte of files whose body is Huffman coded
+pub(crate) const HUFFMAN_MODE: u8 = 0;
+
+// Mode byte of messages coded by a `DictionaryCodec`, which carry no code table
+pub(crate) const DICTIONARY_MODE: u8 = 1;
+
+// Mode byte of files that refer to their code table by its hash instead of storing it:
+// the hash, then a Huffman file whose table is left out
+pub(crate) const TABLE_REFERENCE_MODE: u8 = 2;
+
+/// A way of coding whole buffers, selected on decode by the mode byte after the magic
+pub trait Codec {
+    /// Mode byte written after the magic of everything this codec encodes
+    fn mode(&self) -> u8;
+    /// Code `data` into a complete compressed file
+    fn encode(&self, data: &[u8]) -> Vec<u8>;
+    /// Decode a complete compressed file whose mode byte is `self.mode()`
+    fn decode(&self, encoded: &[u8]) -> Result<Vec<u8>, RuffmanError>;
+}
+
+/// The Huffman coder, with default options
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HuffmanCodec;
+
+impl Codec for HuffmanCodec {
+    fn mode(&self) -> u8 {
+        HUFFMAN_MODE
+    }
+
+    fn encode(&self, data: &[u8]) -> Vec<u8> {
+        compress(data)
+    }
+
+    fn decode(&self, encoded: &[u8]) -> Result<Vec<u8>, RuffmanError> {
+        decode_huffman(encoded, None)
+    }
+}
+
+// The codec that wrote `compressed`, going by its mode byte
+pub(crate) fn codec_for(compressed: &[u8]) -> Result<&'static dyn Codec, RuffmanError> {
+    if !compressed.starts_with(&MAGIC) {
+        return Err(RuffmanError::BadMagic);
+    }
+    match compressed.get(MAGIC.len()) {
+        Some(&HUFFMAN_MODE) => Ok(&HuffmanCodec),
+        Some(&DICTIONARY_MODE) => Err(RuffmanError::DictionaryRequired),
+        Some(&TABLE_REFERENCE_MODE) => Err(RuffmanError::CodeTableRequired),
+        Some(&mode) => Err(RuffmanError::UnsupportedMode(mode)),
+        None => Err(RuffmanError::CorruptDataAt {
+            offset: MAGIC.len(),
+            message: "missing mode byte".to_string(),
+        }),
+    }
+}
@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::RuffmanError;
+
+// How newline-delimited records were split, so column-major bytes can be put back in row order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ColumnLayout {
+    delimiter: u8,
+    shape: RowShape,
+    trailing_newline: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+enum RowShape {
+    // Every record has the same number of fields
+    Uniform { rows: u64, fields: u32 },
+    // Ragged records: the number of fields of each one
+    Ragged(Vec<u32>),
+}
+
+impl RowShape {
+    fn rows(&self) -> u64 {
+        match self {
+            RowShape::Uniform { rows, .. } => *rows,
+            RowShape::Ragged(counts) => counts.len() as u64,
+        }
+    }
+
+    fn fields(&self, row: usize) -> usize {
+        match self {
+            RowShape::Uniform { fields, .. } => *fields as usize,
+            RowShape::Ragged(counts) => counts[row] as usize,
+        }
+    }
+
+    fn max_fields(&self) -> usize {
+        match self {
+            RowShape::Uniform { fields, .. } => *fields as usize,
+            RowShape::Ragged(counts) => counts.iter().max().copied().unwrap_or(0) as usize,
+        }
+    }
+}
+
+// Reorder records column-major so similar values end up next to each other. Every
+// field is written followed by the delimiter, first column of every row first.
+pub(crate) fn split_columns(data: &[u8], delimiter: u8) -> (ColumnLayout, Vec<u8>) {
+    let trailing_newline = data.last() == Some(&b'\n');
+    let records = if trailing_newline { &data[..data.len() - 1] } else { data };
+    let rows: Vec<Vec<&[u8]>> = if data.is_empty() {
+        Vec::new()
+    } else {
+        records
+            .split(|&byte| byte == b'\n')
+            .map(|record| record.split(|&byte| byte == delimiter).collect())
+            .collect()
+    };
+
+    let counts: Vec<u32> = rows.iter().map(|row| row.len() as u32).collect();
+    let max_fields = counts.iter().max().copied().unwrap_or(0);
+
+    let mut transformed = Vec::with_capacity(data.len() + 1);
+    for column in 0..max_fields as usize {
+        for row in &rows {
+            if let Some(field) = row.get(column) {
+                transformed.extend_from_slice(field);
+                transformed.push(delimiter);
+            }
+        }
+    }
+
+    let shape = if counts.iter().all(|&count| count == max_fields) {
+        RowShape::Uniform {
+            rows: counts.len() as u64,
+            fields: max_fields,
+        }
+    } else {
+        RowShape::Ragged(counts)
+    };
+    let layout = ColumnLayout {
+        delimiter,
+        shape,
+        trailing_newline,
+    };
+    (layout, transformed)
+}
+
+// Undo `split_columns`
+pub(crate) fn join_columns(layout: &ColumnLayout, transformed: &[u8]) -> Result<Vec<u8>, RuffmanError> {
+    let corrupt = || RuffmanError::CorruptData("column layout does not match the data".to_string());
+
+    // Every field takes at least its delimiter, so this also bounds the allocation below
+    let rows = layout.shape.rows();
+    if rows > transformed.len() as u64 {
+        return Err(corrupt());
+    }
+    let rows = rows as usize;
+
+    let mut fields: Vec<Vec<&[u8]>> = vec![Vec::new(); rows];
+    let mut pieces = transformed.split(|&byte| byte == layout.delimiter);
+    for column in 0..layout.shape.max_fields() {
+        for (row, row_fields) in fields.iter_mut().enumerate() {
+            if layout.shape.fields(row) > column {
+                row_fields.push(pieces.next().ok_or_else(corrupt)?);
+            }
+        }
+    }
+    // The last delimiter leaves one empty piece behind
+    if rows > 0 && (pieces.next() != Some(&[]) || pieces.next().is_some()) {
+        return Err(corrupt());
+    }
+
+    let mut data = Vec::with_capacity(transformed.len());
+    for (row, row_fields) in fields.iter().enumerate() {
+        for (column, field) in row_fields.iter().enumerate() {
+            if column > 0 {
+                data.push(layout.delimiter);
+            }
+            data.extend_from_slice(field);
+        }
+        if row + 1 < rows || layout.trailing_newline {
+            data.push(b'\n');
+        }
+    }
+    Ok(data)
+}
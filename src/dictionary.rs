@@ -0,0 +1,197 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, Write};
+
+use crate::bits::{BitReader, BitWriter};
+use crate::canonical::{codes_to_lengths, lengths_to_codes};
+use crate::codec::{pack_decoding_table, push_bit, PackedCode, MAGIC, PADDING};
+use crate::coder::{Codec, DICTIONARY_MODE};
+use crate::error::RuffmanError;
+use crate::frame::{read_frame, write_frame};
+use crate::huffman::{build_codes, COUNTED};
+
+// Offset of the little-endian byte count, after the magic and the mode byte
+const COUNT_START: usize = MAGIC.len() + 1;
+
+// Offset of the body
+const BODY_START: usize = COUNT_START + 8;
+
+/// Byte frequencies over a sliding window of the most recent bytes coded, kept in step
+/// on both ends of a stream of messages
+#[derive(Debug, Clone)]
+pub struct Dictionary {
+    window: VecDeque<u8>,
+    counts: [usize; 256],
+    max_size: usize,
+}
+
+impl Dictionary {
+    /// An empty dictionary remembering at most `max_size` bytes. Its window grows as bytes
+    /// arrive, so a large limit costs nothing until it is reached.
+    pub fn new(max_size: usize) -> Self {
+        Dictionary {
+            window: VecDeque::new(),
+            counts: [0; 256],
+            max_size,
+        }
+    }
+
+    /// Number of bytes currently remembered
+    pub fn len(&self) -> usize {
+        self.window.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.window.is_empty()
+    }
+
+    // Slide the window over `data`, forgetting the oldest bytes beyond `max_size`
+    fn update(&mut self, data: &[u8]) {
+        let data = &data[data.len().saturating_sub(self.max_size)..];
+        for &byte in data {
+            if self.window.len() == self.max_size {
+                let oldest = self.window.pop_front().unwrap();
+                self.counts[oldest as usize] -= 1;
+            }
+            self.window.push_back(byte);
+            self.counts[byte as usize] += 1;
+        }
+    }
+
+    // Canonical codes for every byte value, favouring the ones seen recently. Every count
+    // is bumped by one so bytes the window has not seen can still be coded.
+    fn codes(&self) -> HashMap<u8, String> {
+        let frequency = (0..=255u8).map(|byte| (byte, self.counts[byte as usize] + 1)).collect();
+        lengths_to_codes(codes_to_lengths(&build_codes(frequency).expect(COUNTED)))
+    }
+}
+
+/// Codes a stream of similar messages with Huffman codes taken from a `Dictionary` of the
+/// ones before, so no code table is sent and each message only carries its length. The
+/// decoding side must see the same messages in the same order, starting from a dictionary
+/// of the same size.
+#[derive(Debug)]
+pub struct DictionaryCodec {
+    dictionary: RefCell<Dictionary>,
+}
+
+impl DictionaryCodec {
+    pub fn new(max_size: usize) -> Self {
+        DictionaryCodec {
+            dictionary: RefCell::new(Dictionary::new(max_size)),
+        }
+    }
+
+    /// The dictionary as it stands after the messages coded so far
+    pub fn dictionary(&self) -> Dictionary {
+        self.dictionary.borrow().clone()
+    }
+}
+
+impl Codec for DictionaryCodec {
+    fn mode(&self) -> u8 {
+        DICTIONARY_MODE
+    }
+
+    fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let mut dictionary = self.dictionary.borrow_mut();
+        let codes = dictionary.codes();
+        let mut output = Vec::with_capacity(BODY_START + data.len());
+        output.extend_from_slice(&MAGIC);
+        output.push(DICTIONARY_MODE);
+        output.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        let mut body = BitWriter::new(output);
+        for byte in data {
+            body.write_code(&codes[byte]).expect("writing to a Vec cannot fail");
+        }
+
+        dictionary.update(data);
+        body.finish_with(PADDING).expect("writing to a Vec cannot fail")
+    }
+
+    // The dictionary only moves on once a message has decoded cleanly
+    fn decode(&self, encoded: &[u8]) -> Result<Vec<u8>, RuffmanError> {
+        if !encoded.starts_with(&MAGIC) {
+            return Err(RuffmanError::BadMagic);
+        }
+        match encoded.get(MAGIC.len()) {
+            Some(&DICTIONARY_MODE) => {}
+            Some(&mode) => return Err(RuffmanError::UnsupportedMode(mode)),
+            None => return Err(corrupt_at(MAGIC.len(), "missing mode byte")),
+        }
+        if encoded.len() < BODY_START {
+            return Err(corrupt_at(COUNT_START, "missing byte count"));
+        }
+        let count = u64::from_le_bytes(encoded[COUNT_START..BODY_START].try_into().unwrap()) as usize;
+
+        let mut dictionary = self.dictionary.borrow_mut();
+        let table = pack_decoding_table(dictionary.codes().into_iter().map(|(byte, code)| (code, byte)).collect());
+        // Padding can hold a prefix of a code but never a whole one
+        let body = &encoded[BODY_START..];
+        let mut bits = BitReader::new(body);
+        let mut decoded = Vec::with_capacity(count.min(body.len().saturating_mul(8)));
+        let mut code: PackedCode = (0, 0);
+        while decoded.len() < count {
+            let Some(bit) = bits.read_bit().expect("reading from a slice cannot fail") else {
+                break;
+            };
+            push_bit(&mut code, bit);
+            if let Some(&byte) = table.get(&code) {
+                decoded.push(byte);
+                code = (0, 0);
+            }
+        }
+        if decoded.len() != count {
+            return Err(RuffmanError::LengthMismatch {
+                expected: count,
+                actual: decoded.len(),
+            });
+        }
+
+        dictionary.update(&decoded);
+        Ok(decoded)
+    }
+}
+
+/// Code every line `reader` yields, newline included, as a message of its own with a
+/// `DictionaryCodec` of `dictionary_size` bytes, writing each one as a frame. Log lines
+/// and other records that resemble the ones before them cost little more than their codes.
+pub fn compress_messages<R: BufRead, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    dictionary_size: usize,
+) -> Result<(), RuffmanError> {
+    let codec = DictionaryCodec::new(dictionary_size);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        write_frame(&mut writer, &codec.encode(&line))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Decode the frames `compress_messages` wrote with a dictionary of the same size, writing
+/// the messages one after another
+pub fn decompress_messages<R: BufRead, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    dictionary_size: usize,
+) -> Result<(), RuffmanError> {
+    let codec = DictionaryCodec::new(dictionary_size);
+    while let Some(frame) = read_frame(&mut reader)? {
+        writer.write_all(&codec.decode(&frame)?)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn corrupt_at(offset: usize, message: &str) -> RuffmanError {
+    RuffmanError::CorruptDataAt {
+        offset,
+        message: message.to_string(),
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt;
+use std::io;
+
+/// Errors produced while compressing or decompressing
+#[derive(Debug)]
+pub enum RuffmanError {
+    /// Reading or writing failed
+    Io(io::Error),
+    /// The input does not start with the ruffman magic
+    BadMagic,
+    /// The compressed body does not match the checksum stored in the header
+    BodyChecksumMismatch { expected: u32, actual: u32 },
+    /// The file was written in a mode this build cannot decode
+    UnsupportedMode(u8),
+    /// The data was coded by a `DictionaryCodec` and can only be decoded by its counterpart
+    DictionaryRequired,
+    /// The body is shorter, in bytes, than the header's bit count needs
+    TruncatedBody { expected: usize, actual: usize },
+    /// The body decoded to fewer bytes than the header's byte count
+    LengthMismatch { expected: usize, actual: usize },
+    /// The compressed data is malformed
+    CorruptData(String),
+    /// The header cannot be read, most likely because the file was written by a version
+    /// of ruffman with a different header layout
+    UnsupportedHeaderEncoding,
+    /// The compressed data is malformed, first noticed at `offset` bytes into the file
+    CorruptDataAt { offset: usize, message: String },
+    /// A header comment exceeds `MAX_COMMENT_LEN` bytes
+    CommentTooLong(usize),
+    /// `CompressOptions::quantize` asks for fewer than two levels
+    InvalidQuantizeLevels(u8),
+    /// Code lengths given for reuse do not form a prefix code
+    InvalidCodeLengths,
+    /// `build_tree` was given no symbol with a nonzero frequency
+    EmptyInput,
+    /// Symbol frequencies whose total does not fit a `usize`
+    FrequencyOverflow,
+    /// The header's bit count is more than this platform's `usize` can address
+    TooLarge(u64),
+    /// The operation's `CancellationToken` was cancelled
+    Cancelled,
+    /// A CSV model could not be read; `line` counts from 1
+    InvalidModel { line: usize, message: String },
+    /// A benchmark baseline is not JSON in the form `Baseline::to_json` writes
+    InvalidBaseline(String),
+    /// Decompressing freshly compressed output did not reproduce the input
+    VerificationFailed,
+    /// The caller's output buffer cannot hold the decompressed data
+    OutputTooSmall { needed: usize, available: usize },
+    /// Compression would not reach the ratio asked for with `CompressOptions::min_ratio`
+    NotWorthCompressing { ratio: f64, threshold: f64 },
+    /// The file only refers to the code table it was coded with, which has to be supplied
+    /// with `decompress_with_code_lengths`
+    CodeTableRequired,
+    /// The code lengths supplied for a file that refers to its table are not that table
+    CodeTableMismatch,
+    /// `CompressOptions::verify_tree` found a Huffman tree that breaks an invariant, which
+    /// means a bug in the tree code
+    InvalidTree(String),
+}
+
+impl fmt::Display for RuffmanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuffmanError::Io(e) => write!(f, "{}", e),
+            RuffmanError::BadMagic => write!(f, "not a ruffman file"),
+            RuffmanError::BodyChecksumMismatch { expected, actual } => {
+                write!(f, "compressed body checksum mismatch: expected {:08x}, got {:08x}", expected, actual)
+            }
+            RuffmanError::UnsupportedMode(mode) => write!(f, "unsupported mode {}", mode),
+            RuffmanError::DictionaryRequired => write!(f, "data was coded against a dictionary"),
+            RuffmanError::TruncatedBody { expected, actual } => {
+                write!(f, "truncated body: expected {} bytes, found {}", expected, actual)
+            }
+            RuffmanError::LengthMismatch { expected, actual } => {
+                write!(f, "length mismatch: header announces {} bytes, decoded {}", expected, actual)
+            }
+            RuffmanError::CorruptData(message) => write!(f, "corrupt data: {}", message),
+            RuffmanError::UnsupportedHeaderEncoding => write!(
+                f,
+                "header cannot be read; the file may be damaged or written by an incompatible version of ruffman"
+            ),
+            RuffmanError::CorruptDataAt { offset, message } => {
+                write!(f, "corrupt data at byte {}: {}", offset, message)
+            }
+            RuffmanError::CommentTooLong(len) => {
+                write!(f, "comment is {} bytes long, the limit is {}", len, crate::MAX_COMMENT_LEN)
+            }
+            RuffmanError::InvalidCodeLengths => write!(f, "code lengths do not form a prefix code"),
+            RuffmanError::EmptyInput => write!(f, "no symbols to build a Huffman tree from"),
+            RuffmanError::FrequencyOverflow => {
+                write!(f, "symbol frequencies add up to more than {}, too many for a Huffman tree", usize::MAX)
+            }
+            RuffmanError::TooLarge(bits) => write!(f, "{} bits is more than this platform can decode", bits),
+            RuffmanError::Cancelled => write!(f, "operation cancelled"),
+            RuffmanError::InvalidModel { line, message } => write!(f, "invalid model at line {}: {}", line, message),
+            RuffmanError::InvalidBaseline(message) => write!(f, "invalid baseline: {}", message),
+            RuffmanError::InvalidQuantizeLevels(levels) => {
+                write!(f, "cannot quantize into {} levels, at least 2 are needed", levels)
+            }
+            RuffmanError::VerificationFailed => write!(f, "compressed output does not decompress to the input"),
+            RuffmanError::OutputTooSmall { needed, available } => {
+                write!(f, "output buffer too small: need {} bytes, have {}", needed, available)
+            }
+            RuffmanError::NotWorthCompressing { ratio, threshold } => {
+                write!(f, "not worth compressing: ratio {:.3} is above {:.3}", ratio, threshold)
+            }
+            RuffmanError::CodeTableRequired => {
+                write!(f, "file refers to a code table it does not store; supply the file it was compressed against")
+            }
+            RuffmanError::CodeTableMismatch => write!(f, "supplied code table is not the one the file refers to"),
+            RuffmanError::InvalidTree(problem) => write!(f, "Huffman tree invariant broken: {}", problem),
+        }
+    }
+}
+
+impl std::error::Error for RuffmanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RuffmanError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RuffmanError {
+    fn from(e: io::Error) -> Self {
+        RuffmanError::Io(e)
+    }
+}
+
+impl From<RuffmanError> for io::Error {
+    fn from(e: RuffmanError) -> Self {
+        match e {
+            RuffmanError::Io(e) => e,
+            other => io::Error::new(io::ErrorKind::InvalidData, other),
+        }
+    }
+}
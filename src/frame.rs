@@ -0,0 +1,50 @@
+use std::io::{self, ErrorKind, Read, Write};
+
+use crate::error::RuffmanError;
+
+/// Bytes of the little-endian length in front of every frame
+pub const FRAME_PREFIX_LEN: usize = 4;
+
+/// Write `message`, e.g. a compressed file, behind its length as a little-endian `u32`, so
+/// `read_frame` can take it back off a stream of other frames.
+///
+/// Fails with `InvalidInput` if `message` is 4 GiB or longer.
+pub fn write_frame<W: Write>(mut writer: W, message: &[u8]) -> io::Result<()> {
+    writer.write_all(&frame_prefix(message.len())?)?;
+    writer.write_all(message)
+}
+
+/// The next message `write_frame` wrote to `reader`, or `None` if the stream ends cleanly
+/// before another frame starts. A stream that ends partway through a frame is corrupt.
+pub fn read_frame<R: Read>(mut reader: R) -> Result<Option<Vec<u8>>, RuffmanError> {
+    let mut prefix = [0u8; FRAME_PREFIX_LEN];
+    let mut filled = 0;
+    while filled < prefix.len() {
+        match reader.read(&mut prefix[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(truncated()),
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let len = u32::from_le_bytes(prefix) as u64;
+    // Grown as the message arrives, so a damaged length cannot allocate it all at once
+    let mut message = Vec::new();
+    reader.take(len).read_to_end(&mut message)?;
+    if (message.len() as u64) < len {
+        return Err(truncated());
+    }
+    Ok(Some(message))
+}
+
+// The length prefix of a frame holding `len` bytes
+pub(crate) fn frame_prefix(len: usize) -> io::Result<[u8; FRAME_PREFIX_LEN]> {
+    u32::try_from(len).map(u32::to_le_bytes).map_err(|_| {
+        io::Error::new(ErrorKind::InvalidInput, format!("a message of {} bytes is too long for a frame", len))
+    })
+}
+
+fn truncated() -> RuffmanError {
+    RuffmanError::CorruptData("stream ends partway through a frame".to_string())
+}
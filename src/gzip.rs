@@ -0,0 +1,29 @@
+use std::io::{Read, Write};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::codec::GZIP_MAGIC;
+use crate::error::RuffmanError;
+
+// Check whether a buffer starts like a gzip member
+pub(crate) fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&GZIP_MAGIC)
+}
+
+// Compress a buffer into a gzip member
+pub(crate) fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).expect("writing to a Vec cannot fail");
+    encoder.finish().expect("writing to a Vec cannot fail")
+}
+
+// Decompress a gzip member
+pub(crate) fn decompress(compressed: &[u8]) -> Result<Vec<u8>, RuffmanError> {
+    let mut decompressed = Vec::new();
+    GzDecoder::new(compressed)
+        .read_to_end(&mut decompressed)
+        .map_err(|e| RuffmanError::CorruptData(format!("invalid gzip data: {}", e)))?;
+    Ok(decompressed)
+}
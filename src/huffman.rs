@@ -0,0 +1,381 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+use crate::canonical::{codes_to_lengths, lengths_to_packed_codes};
+use crate::error::RuffmanError;
+
+/// A unit the Huffman coder can assign codes to. Bytes are what the file format stores;
+/// wider symbols can be coded through `SymbolCodec` and `symbol_code_lengths`.
+pub trait Symbol: Copy + Eq + Hash + Ord + Debug {}
+
+impl Symbol for u8 {}
+impl Symbol for u16 {}
+impl Symbol for u32 {}
+
+/// How the Huffman tree orders symbols of equal frequency. Each policy gives a valid code,
+/// but they can give different code lengths, so matching another tool's output means
+/// matching its policy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TieBreak {
+    /// Lower symbols first; the deterministic default
+    #[default]
+    SymbolAsc,
+    /// Higher symbols first
+    SymbolDesc,
+    /// Symbols in the order they first appear in the input
+    Insertion,
+}
+
+// Define a node in the Huffman tree
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) struct Node<S = u8> {
+    pub(crate) freq: usize,
+    pub(crate) char_val: Option<S>,
+    pub(crate) left: Option<Box<Node<S>>>,
+    pub(crate) right: Option<Box<Node<S>>>,
+    // Tie-break key: leaves are numbered in symbol order, internal nodes after them in
+    // creation order, so equal frequencies always merge the same way
+    pub(crate) order: usize,
+}
+
+impl<S: Symbol> Node<S> {
+    pub(crate) fn new(
+        freq: usize,
+        char_val: Option<S>,
+        left: Option<Box<Node<S>>>,
+        right: Option<Box<Node<S>>>,
+        order: usize,
+    ) -> Self {
+        Node {
+            freq,
+            char_val,
+            left,
+            right,
+            order,
+        }
+    }
+}
+
+// Custom ordering to make BinaryHeap a min-heap, popping the lowest order first on ties
+impl<S: Symbol> Ord for Node<S> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.freq.cmp(&self.freq).then_with(|| other.order.cmp(&self.order))
+    }
+}
+
+impl<S: Symbol> PartialOrd for Node<S> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Build a frequency table for characters in the input
+pub(crate) fn build_frequency_table<S: Symbol>(data: &[S]) -> HashMap<S, usize> {
+    let mut frequency = HashMap::new();
+    for &symbol in data {
+        *frequency.entry(symbol).or_insert(0) += 1;
+    }
+    frequency
+}
+
+// The symbols of `data` and their frequencies, in the order `tie_break` numbers the leaves
+pub(crate) fn ordered_leaves<S: Symbol>(data: &[S], tie_break: TieBreak) -> Vec<(S, usize)> {
+    let mut leaves: Vec<(S, usize)> = build_frequency_table(data).into_iter().collect();
+    match tie_break {
+        TieBreak::SymbolAsc => leaves.sort_unstable(),
+        TieBreak::SymbolDesc => leaves.sort_unstable_by_key(|&(symbol, _)| Reverse(symbol)),
+        TieBreak::Insertion => {
+            let mut first_seen = HashMap::new();
+            for (position, &symbol) in data.iter().enumerate() {
+                first_seen.entry(symbol).or_insert(position);
+            }
+            leaves.sort_unstable_by_key(|(symbol, _)| first_seen[symbol]);
+        }
+    }
+    leaves
+}
+
+// Frequencies counted over data in memory add up to at most its length, so merging them
+// cannot overflow; only tables handed in from outside, such as models, can
+pub(crate) const COUNTED: &str = "frequencies counted over data in memory cannot overflow";
+
+// Build a Huffman tree over leaves numbered in the order given. Fails if two subtrees'
+// frequencies add up to more than a `usize` holds, which would otherwise wrap around and
+// merge the tree in the wrong order.
+pub(crate) fn build_huffman_tree<S: Symbol>(
+    leaves: Vec<(S, usize)>,
+) -> Result<Option<Box<Node<S>>>, RuffmanError> {
+    let mut heap = BinaryHeap::new();
+
+    let mut order = 0;
+    for (symbol, freq) in leaves {
+        heap.push(Box::new(Node::new(freq, Some(symbol), None, None, order)));
+        order += 1;
+    }
+
+    while heap.len() > 1 {
+        let left = heap.pop().unwrap();
+        let right = heap.pop().unwrap();
+
+        let combined_freq = left.freq.checked_add(right.freq).ok_or(RuffmanError::FrequencyOverflow)?;
+        let new_node = Node::new(combined_freq, None, Some(left), Some(right), order);
+        order += 1;
+
+        heap.push(Box::new(new_node));
+    }
+
+    Ok(heap.pop())
+}
+
+// Generate Huffman codes from the Huffman tree
+pub(crate) fn generate_codes<S: Symbol>(node: &Option<Box<Node<S>>>, prefix: String, codes: &mut HashMap<S, String>) {
+    if let Some(n) = node {
+        if let Some(c) = n.char_val {
+            codes.insert(c, prefix);
+        } else {
+            generate_codes(&n.left, format!("{}0", prefix), codes);
+            generate_codes(&n.right, format!("{}1", prefix), codes);
+        }
+    }
+}
+
+// Build the Huffman codes for a frequency table, breaking ties by symbol
+pub(crate) fn build_codes<S: Symbol>(frequency: HashMap<S, usize>) -> Result<HashMap<S, String>, RuffmanError> {
+    // HashMap iteration order varies between runs, so number the leaves by symbol
+    let mut leaves: Vec<(S, usize)> = frequency.into_iter().collect();
+    leaves.sort_unstable();
+    build_codes_from_leaves(leaves)
+}
+
+// Build the Huffman codes for leaves in tie-break order, giving a lone symbol a one-bit code
+pub(crate) fn build_codes_from_leaves<S: Symbol>(
+    leaves: Vec<(S, usize)>,
+) -> Result<HashMap<S, String>, RuffmanError> {
+    Ok(tree_codes(&build_huffman_tree(leaves)?))
+}
+
+// Like `build_codes_from_leaves`, then fail with `RuffmanError::InvalidTree` if the tree or
+// its codes break an invariant `check_tree` looks for; the debugging aid behind
+// `CompressOptions::verify_tree`
+pub(crate) fn build_codes_verified<S: Symbol>(
+    leaves: Vec<(S, usize)>,
+) -> Result<HashMap<S, String>, RuffmanError> {
+    let huffman_tree = build_huffman_tree(leaves)?;
+    let codes = tree_codes(&huffman_tree);
+    check_tree(&huffman_tree, &codes).map_err(RuffmanError::InvalidTree)?;
+    Ok(codes)
+}
+
+fn tree_codes<S: Symbol>(huffman_tree: &Option<Box<Node<S>>>) -> HashMap<S, String> {
+    let mut codes = HashMap::new();
+    generate_codes(huffman_tree, String::new(), &mut codes);
+
+    // A tree with a single leaf yields an empty code, which could never be decoded
+    if codes.len() == 1 {
+        for code in codes.values_mut() {
+            code.push('0');
+        }
+    }
+    codes
+}
+
+// Check what a correct tree guarantees: every internal node has two children and the sum
+// of their frequencies, every leaf's code is as long as the leaf is deep (one bit for a
+// lone leaf), no code is a prefix of another, and the codes of a tree of two or more
+// leaves use up the code space exactly, Kraft's inequality holding with equality
+fn check_tree<S: Symbol>(
+    huffman_tree: &Option<Box<Node<S>>>,
+    codes: &HashMap<S, String>,
+) -> Result<(), String> {
+    let Some(root) = huffman_tree else {
+        return if codes.is_empty() { Ok(()) } else { Err("codes without a tree".to_string()) };
+    };
+    let mut leaves = 0;
+    let mut pending = vec![(root, 0usize)];
+    while let Some((node, depth)) = pending.pop() {
+        match (node.char_val, &node.left, &node.right) {
+            (Some(symbol), None, None) => {
+                let expected = if depth == 0 { 1 } else { depth };
+                match codes.get(&symbol) {
+                    Some(code) if code.len() == expected => {}
+                    Some(code) => {
+                        return Err(format!("{:?} has a {}-bit code at depth {}", symbol, code.len(), depth))
+                    }
+                    None => return Err(format!("{:?} is in the tree without a code", symbol)),
+                }
+                leaves += 1;
+            }
+            (None, Some(left), Some(right)) => {
+                if left.freq.checked_add(right.freq) != Some(node.freq) {
+                    return Err(format!("a node's frequency {} is not the sum of its children's", node.freq));
+                }
+                pending.push((left, depth + 1));
+                pending.push((right, depth + 1));
+            }
+            _ => return Err("a node is neither a leaf nor has two children".to_string()),
+        }
+    }
+    if leaves != codes.len() {
+        return Err(format!("{} leaves but {} codes", leaves, codes.len()));
+    }
+
+    // In sorted order a code is followed directly by any code it is a prefix of
+    let mut sorted: Vec<&String> = codes.values().collect();
+    sorted.sort_unstable();
+    if let Some(pair) = sorted.windows(2).find(|pair| pair[1].starts_with(pair[0].as_str())) {
+        return Err(format!("{} is a prefix of {}", pair[0], pair[1]));
+    }
+
+    // Kraft sum scaled by 2^MAX_KRAFT_DEPTH, exact for any code no longer than that
+    const MAX_KRAFT_DEPTH: usize = 127;
+    if let Some(code) = codes.values().find(|code| code.len() > MAX_KRAFT_DEPTH) {
+        return Err(format!("a {}-bit code is too long to check", code.len()));
+    }
+    let kraft_sum: u128 = codes.values().map(|code| 1u128 << (MAX_KRAFT_DEPTH - code.len())).sum();
+    if leaves > 1 && kraft_sum != 1 << MAX_KRAFT_DEPTH {
+        return Err("the codes do not fill the code space".to_string());
+    }
+    Ok(())
+}
+
+/// The Huffman codes of a set of byte frequencies, built by `build_tree`
+#[derive(Debug, Clone)]
+pub struct HuffmanTree {
+    codes: HashMap<u8, String>,
+    lengths: [u8; 256],
+}
+
+impl HuffmanTree {
+    /// The code of `byte` as '0' and '1' characters, or `None` if its frequency was 0
+    pub fn code(&self, byte: u8) -> Option<&str> {
+        self.codes.get(&byte).map(String::as_str)
+    }
+
+    /// Every byte with a code and its code
+    pub fn codes(&self) -> &HashMap<u8, String> {
+        &self.codes
+    }
+
+    /// Code length of every byte value, 0 for bytes without a code
+    pub fn code_lengths(&self) -> &[u8; 256] {
+        &self.lengths
+    }
+}
+
+/// Build the Huffman tree for the frequency of every byte value, breaking ties by byte
+/// value as `compress` does. A lone byte gets a one-bit code; no nonzero frequency at all
+/// gives `RuffmanError::EmptyInput`, and frequencies adding up to more than `usize::MAX`
+/// give `RuffmanError::FrequencyOverflow`.
+pub fn build_tree(freq: &[usize; 256]) -> Result<HuffmanTree, RuffmanError> {
+    let leaves: Vec<(u8, usize)> =
+        (0..=255u8).zip(freq.iter().copied()).filter(|&(_, count)| count > 0).collect();
+    if leaves.is_empty() {
+        return Err(RuffmanError::EmptyInput);
+    }
+    let codes = build_codes_from_leaves(leaves)?;
+    let lengths = codes_to_lengths(&codes);
+    Ok(HuffmanTree { codes, lengths })
+}
+
+/// Huffman code length of every byte value in `data`, 0 for bytes that do not occur
+pub fn code_lengths(data: &[u8]) -> [u8; 256] {
+    codes_to_lengths(&build_codes(build_frequency_table(data)).expect(COUNTED))
+}
+
+/// The canonical codes `compress` gives the bytes in `data`, as (byte, code, length) triples
+/// sorted by byte, ready for `BitWriter::write_bits` without a `String` per code. See
+/// `lengths_to_packed_codes`.
+///
+/// Returns `None` if a code is longer than 32 bits, which only inputs of many megabytes
+/// with Fibonacci-like byte counts reach; `compress` then codes them from strings instead.
+pub fn packed_codes(data: &[u8]) -> Option<Vec<(u8, u32, u8)>> {
+    lengths_to_packed_codes(&code_lengths(data))
+}
+
+/// How far apart two sets of `code_lengths` are: the mean, over all 256 byte values, of
+/// the difference between their lengths, with 0 for a byte that has no code. Identical
+/// distributions give 0.0; the further their byte frequencies drift apart, the larger it gets.
+pub fn code_length_distance(a: &[u8; 256], b: &[u8; 256]) -> f64 {
+    let total: u32 = a.iter().zip(b).map(|(&a, &b)| a.abs_diff(b) as u32).sum();
+    total as f64 / 256.0
+}
+
+/// Huffman code length of every distinct symbol in `data`, for alphabets wider than a
+/// byte such as 16-bit samples. `code_lengths` is the byte case.
+pub fn symbol_code_lengths<S: Symbol>(data: &[S]) -> HashMap<S, u8> {
+    build_codes(build_frequency_table(data))
+        .expect(COUNTED)
+        .into_iter()
+        .map(|(symbol, code)| (symbol, code.len() as u8))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples() -> Vec<Vec<u8>> {
+        vec![
+            b"z".to_vec(),
+            b"abracadabra".to_vec(),
+            (0..=255u8).collect(),
+            (0..5000u32).map(|i| (i * i % 251) as u8).collect(),
+        ]
+    }
+
+    fn tree_of(data: &[u8]) -> Option<Box<Node<u8>>> {
+        build_huffman_tree(ordered_leaves(data, TieBreak::default())).unwrap()
+    }
+
+    #[test]
+    fn built_trees_pass() {
+        for data in samples() {
+            for tie_break in [TieBreak::SymbolAsc, TieBreak::SymbolDesc, TieBreak::Insertion] {
+                let tree = build_huffman_tree(ordered_leaves(&data, tie_break)).unwrap();
+                assert_eq!(check_tree(&tree, &tree_codes(&tree)), Ok(()));
+                assert!(build_codes_verified(ordered_leaves(&data, tie_break)).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn a_wrong_frequency_fails() {
+        let mut tree = tree_of(b"abracadabra");
+        let codes = tree_codes(&tree);
+        tree.as_mut().unwrap().freq += 1;
+        assert!(check_tree(&tree, &codes).unwrap_err().contains("not the sum"));
+    }
+
+    #[test]
+    fn a_missing_child_fails() {
+        let mut tree = tree_of(b"abracadabra");
+        let codes = tree_codes(&tree);
+        tree.as_mut().unwrap().right = None;
+        assert!(check_tree(&tree, &codes).unwrap_err().contains("two children"));
+    }
+
+    #[test]
+    fn codes_that_do_not_match_the_tree_fail() {
+        let tree = tree_of(b"abracadabra");
+        let mut codes = tree_codes(&tree);
+        codes.get_mut(&b'a').unwrap().push('0');
+        assert!(check_tree(&tree, &codes).unwrap_err().contains("depth"));
+
+        let mut codes = tree_codes(&tree);
+        codes.remove(&b'r');
+        assert!(check_tree(&tree, &codes).unwrap_err().contains("without a code"));
+    }
+
+    #[test]
+    fn a_code_shared_by_two_leaves_fails() {
+        // Each code is as long as its leaf is deep, so only the prefix check catches it
+        let leaf = |symbol, freq, order| Some(Box::new(Node::new(freq, Some(symbol), None, None, order)));
+        let tree = Some(Box::new(Node::new(3, None, leaf(b'a', 1, 0), leaf(b'b', 2, 1), 2)));
+        let codes = HashMap::from([(b'a', "0".to_string()), (b'b', "0".to_string())]);
+        assert!(check_tree(&tree, &codes).unwrap_err().contains("prefix"));
+    }
+}
@@ -0,0 +1,177 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::codec::{
+    compress_file_with, decompress_file_with, CompressOptions, DecompressOptions, DecompressReport, Format,
+};
+use crate::error::RuffmanError;
+use crate::warning::Warning;
+
+/// Extension `compress_in_place` gives files in the ruffman format
+pub const EXTENSION: &str = "huff";
+
+// Extension given to gzip output
+#[cfg(feature = "gzip")]
+const GZIP_EXTENSION: &str = "gz";
+
+/// How `compress_in_place` and `decompress_in_place` treat the files around them
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InPlaceOptions {
+    /// Keep the input next to the result instead of removing it
+    pub keep: bool,
+    /// Replace a file that already has the target name instead of failing
+    pub force: bool,
+}
+
+/// Replace `path` with its compressed form, `path` plus `.huff` (`.gz` for gzip output).
+/// The original is only removed, unless `keep` is set, once the compressed file has been
+/// synced and renamed into place. Returns the new path and any warnings.
+pub fn compress_in_place(
+    path: &Path,
+    options: &CompressOptions,
+    in_place: &InPlaceOptions,
+) -> Result<(PathBuf, Vec<Warning>), RuffmanError> {
+    let target = compressed_path(path, options.format);
+    let warnings = replace(
+        path,
+        &target,
+        in_place.force,
+        true,
+        |temp| compress_file_with(path, temp, options),
+        |_| in_place.keep,
+    )?;
+    Ok((target, warnings))
+}
+
+/// Name for the compressed form of `path` in `format`: `path` plus `.huff` (or `.gz`)
+pub fn compressed_path(path: &Path, format: Format) -> PathBuf {
+    let extension = match format {
+        Format::Ruffman => EXTENSION,
+        #[cfg(feature = "gzip")]
+        Format::Gzip => GZIP_EXTENSION,
+    };
+    let mut target = path.as_os_str().to_owned();
+    target.push(".");
+    target.push(extension);
+    PathBuf::from(target)
+}
+
+/// Replace a compressed `path` with its decompressed form, `path` without its `.huff`
+/// (or `.gz`) extension. If decoding stopped at damage in recover mode, the compressed file
+/// is kept regardless of `keep`.
+pub fn decompress_in_place(
+    path: &Path,
+    options: &DecompressOptions,
+    in_place: &InPlaceOptions,
+) -> Result<(PathBuf, DecompressReport), RuffmanError> {
+    let target = decompressed_path(path).ok_or_else(|| {
+        invalid_input(format!("{} does not end in .{}", path.display(), EXTENSION))
+    })?;
+
+    let report = replace(
+        path,
+        &target,
+        in_place.force,
+        !cfg!(unix) || options.output_mode.is_none(),
+        |temp| decompress_file_with(path, temp, options),
+        |report| in_place.keep || report.damage.is_some(),
+    )?;
+    Ok((target, report))
+}
+
+// Write `target` through `write`, which is handed a temporary path next to it, then rename
+// the result into place and remove `source` unless `keep` says otherwise. An existing
+// regular file at `target` is only replaced when `force` is set. The result gets the
+// permissions of `source` if `copy_permissions` is set.
+fn replace<T>(
+    source: &Path,
+    target: &Path,
+    force: bool,
+    copy_permissions: bool,
+    write: impl FnOnce(&Path) -> Result<T, RuffmanError>,
+    keep: impl FnOnce(&T) -> bool,
+) -> Result<T, RuffmanError> {
+    // Following a link would leave the link pointing at a file that is about to be removed
+    let metadata = fs::symlink_metadata(source)?;
+    if metadata.file_type().is_symlink() {
+        return Err(invalid_input(format!("{} is a symbolic link", source.display())));
+    }
+    if !metadata.is_file() {
+        return Err(invalid_input(format!("{} is not a regular file", source.display())));
+    }
+    let taken = |path: &Path| match fs::symlink_metadata(path) {
+        Ok(metadata) => !force || !metadata.is_file(),
+        Err(_) => false,
+    };
+    if taken(target) {
+        return Err(already_exists(target));
+    }
+
+    let mut temp = target.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    if taken(&temp) {
+        return Err(already_exists(&temp));
+    }
+
+    let result = write(&temp).and_then(|value| {
+        if copy_permissions {
+            fs::set_permissions(&temp, metadata.permissions())?;
+        }
+        File::open(&temp)?.sync_all()?;
+        // Something may have taken the name while we were writing
+        if taken(target) {
+            return Err(already_exists(target));
+        }
+        fs::rename(&temp, target)?;
+        Ok(value)
+    });
+    let value = match result {
+        Ok(value) => value,
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            return Err(e);
+        }
+    };
+    sync_parent(target);
+
+    if !keep(&value) {
+        fs::remove_file(source)?;
+        sync_parent(source);
+    }
+    Ok(value)
+}
+
+// Make a rename or removal durable. Not every platform can open a directory, so failures are ignored.
+fn sync_parent(path: &Path) {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = File::open(parent) {
+        let _ = dir.sync_all();
+    }
+}
+
+/// Name for the decompressed form of `path`: `path` without its `.huff` (or `.gz`)
+/// extension, or `None` if it has neither
+pub fn decompressed_path(path: &Path) -> Option<PathBuf> {
+    let extension = path.extension()?;
+    let known = extension == EXTENSION;
+    #[cfg(feature = "gzip")]
+    let known = known || extension == GZIP_EXTENSION;
+    known.then(|| path.with_extension(OsString::new()))
+}
+
+fn invalid_input(message: String) -> RuffmanError {
+    RuffmanError::Io(io::Error::new(io::ErrorKind::InvalidInput, message))
+}
+
+fn already_exists(path: &Path) -> RuffmanError {
+    RuffmanError::Io(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{} already exists", path.display()),
+    ))
+}
@@ -0,0 +1,76 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::ops::Deref;
+
+// The contents of an input file, either read into memory or, with the `mmap` feature,
+// mapped read-only
+pub(crate) enum Input {
+    Read(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped {
+        map: memmap2::Mmap,
+        file: File,
+        metadata: std::fs::Metadata,
+    },
+}
+
+impl Deref for Input {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Input::Read(data) => data,
+            #[cfg(feature = "mmap")]
+            Input::Mapped { map, .. } => map,
+        }
+    }
+}
+
+// Load the input, mapping it when the `mmap` feature is enabled
+pub(crate) fn open_input(input_file: File, buffer_size: usize) -> io::Result<Input> {
+    #[cfg(feature = "mmap")]
+    {
+        let metadata = input_file.metadata()?;
+        // Pipes and other special files cannot be mapped, and empty files cannot be on
+        // every platform
+        if metadata.is_file() && metadata.len() > 0 {
+            // SAFETY: the map is only ever read. Another process truncating the file while
+            // it is mapped is not something we can prevent, so such changes are detected
+            // by `Input::finish` and reported instead of returning inconsistent output.
+            let map = unsafe { memmap2::Mmap::map(&input_file)? };
+            return Ok(Input::Mapped {
+                map,
+                file: input_file,
+                metadata,
+            });
+        }
+    }
+
+    let mut reader = BufReader::with_capacity(buffer_size, input_file);
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    Ok(Input::Read(data))
+}
+
+impl Input {
+    // Release the input, failing if a mapped file changed while it was in use
+    pub(crate) fn finish(self) -> io::Result<()> {
+        match self {
+            Input::Read(_) => Ok(()),
+            #[cfg(feature = "mmap")]
+            Input::Mapped { map, file, metadata } => {
+                let mapped_len = map.len() as u64;
+                drop(map);
+
+                let after = file.metadata()?;
+                if mapped_len != metadata.len()
+                    || after.len() != metadata.len()
+                    || after.modified().ok() != metadata.modified().ok()
+                {
+                    return Err(io::Error::other("input file changed while it was being compressed"));
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fmt::{self, Write};
+
+use crate::codec::{decode_body, parse};
+use crate::error::RuffmanError;
+
+/// Longest code, in bits, `has_long_codes` accepts before `inspect` warns by default
+pub const DEFAULT_MAX_CODE_LENGTH: usize = 24;
+
+/// What a compressed buffer contains and how well Huffman coding suited it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inspection {
+    pub compressed_size: usize,
+    pub header_size: usize,
+    /// Number of coded symbols, i.e. bytes after any transform
+    pub coded_bytes: usize,
+    pub distinct_symbols: usize,
+    pub shortest_code: usize,
+    pub longest_code: usize,
+    /// Nodes in the decoding tree rebuilt from the code lengths, leaves included. A table
+    /// decoder needs one entry per node.
+    pub tree_nodes: usize,
+    /// Depth of the deepest leaf, which is the longest code length
+    pub tree_depth: usize,
+    /// Code tables of an order-1 model, one per byte that precedes another, or 0 when every
+    /// byte is coded with the same table. The counts above then cover all of them.
+    pub contexts: usize,
+    /// Unused bits at the end of the body, from 0 to 7. Their value does not matter to
+    /// decoding, which stops at the header's bit count.
+    pub padding_bits: u8,
+    /// Average code length in bits per symbol, weighted by frequency
+    pub average_code_length: f64,
+    /// Shannon entropy of the coded symbols in bits per symbol; for order-1 data, given the
+    /// symbol before, which is what its codes can approach
+    pub entropy: f64,
+    pub comment: Option<String>,
+    /// When the file was compressed, in seconds since the Unix epoch, if it was recorded
+    pub created: Option<u64>,
+    /// Every byte with a code, in byte order; empty for order-1 data, where a byte's code
+    /// depends on the byte before it
+    pub symbols: Vec<SymbolEntry>,
+}
+
+/// One byte of the code table and how often it was coded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolEntry {
+    pub byte: u8,
+    /// 0 for a byte a reused code table has a code for but the data never uses
+    pub count: usize,
+    /// The byte's code as '0' and '1' characters
+    pub code: String,
+}
+
+/// Order of the rows of `Inspection::symbol_table`. Rows that tie are put in byte order,
+/// so every order gives the same listing each time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymbolOrder {
+    #[default]
+    Symbol,
+    /// Most frequent first
+    Frequency,
+    /// Shortest code first, which is the order of the canonical codes
+    CodeLength,
+}
+
+impl Inspection {
+    /// The entries of the code table in `order`
+    pub fn sorted_symbols(&self, order: SymbolOrder) -> Vec<&SymbolEntry> {
+        let mut rows: Vec<&SymbolEntry> = self.symbols.iter().collect();
+        match order {
+            SymbolOrder::Symbol => {}
+            SymbolOrder::Frequency => rows.sort_by_key(|entry| Reverse(entry.count)),
+            SymbolOrder::CodeLength => rows.sort_by_key(|entry| entry.code.len()),
+        }
+        rows
+    }
+
+    /// List the code table one byte per row, with its count, code length and code
+    pub fn symbol_table(&self, order: SymbolOrder) -> String {
+        let rows = self.sorted_symbols(order);
+        let mut table = format!("{:>4}  {:>12}  {:>6}  code\n", "byte", "count", "length");
+        for entry in rows {
+            writeln!(table, "{:>4}  {:>12}  {:>6}  {}", entry.byte, entry.count, entry.code.len(), entry.code).unwrap();
+        }
+        table
+    }
+
+    /// Whether only one distinct symbol was coded. Its entropy is 0 but it still takes one
+    /// bit per byte, so the body is about an eighth of the input and the header dominates.
+    pub fn single_symbol(&self) -> bool {
+        self.distinct_symbols == 1
+    }
+
+    /// Entropy divided by the average code length, between 0 and 1. Values well below 1
+    /// mean whole-bit codes fit the distribution poorly and a transform (RLE, MTF) may
+    /// help. A buffer with nothing coded, or with a single symbol that no prefix code
+    /// could store in less than a bit, counts as fully efficient.
+    pub fn efficiency(&self) -> f64 {
+        if self.average_code_length == 0.0 || self.single_symbol() {
+            return 1.0;
+        }
+        self.entropy / self.average_code_length
+    }
+
+    /// Whether the longest code is longer than `max` bits. Codes that long come from
+    /// frequencies that fall away steeply, close to a degenerate distribution, where a
+    /// transform such as RLE or MTF, or a coder without whole-bit codes, does better.
+    pub fn has_long_codes(&self, max: usize) -> bool {
+        self.longest_code > max
+    }
+
+    /// Ratio of the longest to the shortest code length
+    pub fn code_length_spread(&self) -> f64 {
+        if self.shortest_code == 0 {
+            return 0.0;
+        }
+        self.longest_code as f64 / self.shortest_code as f64
+    }
+}
+
+impl fmt::Display for Inspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(comment) = &self.comment {
+            writeln!(f, "comment:             {}", comment)?;
+        }
+        if let Some(created) = self.created {
+            writeln!(f, "created:             {} (seconds since the Unix epoch, UTC)", created)?;
+        }
+        writeln!(f, "compressed size:     {}", self.compressed_size)?;
+        writeln!(f, "header size:         {}", self.header_size)?;
+        writeln!(f, "coded bytes:         {}", self.coded_bytes)?;
+        if self.single_symbol() {
+            writeln!(f, "distinct symbols:    1 (single symbol, one bit per byte)")?;
+        } else {
+            writeln!(f, "distinct symbols:    {}", self.distinct_symbols)?;
+        }
+        writeln!(
+            f,
+            "code lengths:        {}..{} bits (spread {:.2})",
+            self.shortest_code,
+            self.longest_code,
+            self.code_length_spread()
+        )?;
+        if self.contexts > 0 {
+            writeln!(f, "order-1 contexts:    {}", self.contexts)?;
+        }
+        writeln!(f, "tree:                {} nodes, depth {}", self.tree_nodes, self.tree_depth)?;
+        writeln!(f, "padding:             {} bits", self.padding_bits)?;
+        writeln!(f, "average code length: {:.4} bits", self.average_code_length)?;
+        writeln!(f, "entropy:             {:.4} bits", self.entropy)?;
+        writeln!(f, "efficiency:          {:.4}", self.efficiency())
+    }
+}
+
+/// Describe a buffer produced by `compress`
+pub fn inspect(compressed: &[u8]) -> Result<Inspection, RuffmanError> {
+    let (header, body) = parse(compressed)?;
+
+    // The header only has code lengths, so count the symbols by decoding the body, and the
+    // pairs of them for order-1 data, which starts from a context of 0
+    let mut frequency = [0usize; 256];
+    let mut pairs = header.contexts.as_ref().map(|_| vec![[0usize; 256]; 256]);
+    let mut context = 0;
+    decode_body(&header, body, None, |byte| {
+        frequency[byte as usize] += 1;
+        if let Some(pairs) = &mut pairs {
+            pairs[context][byte as usize] += 1;
+            context = byte as usize;
+        }
+    })?;
+
+    let total = header.original_byte_count as f64;
+    let mut entropy = 0.0;
+    let distributions = match &pairs {
+        Some(pairs) => pairs.iter().collect(),
+        None => vec![&frequency],
+    };
+    for counts in distributions {
+        let context_total: usize = counts.iter().sum();
+        for &count in counts.iter().filter(|&&count| count > 0) {
+            let probability = count as f64 / context_total as f64;
+            entropy -= count as f64 / total * probability.log2();
+        }
+    }
+    // Each distinct prefix of a code, the empty one being the root, is a node of a tree;
+    // order-1 data has a tree per context
+    let tables = header.decoding_tables()?;
+    let tree_nodes = tables
+        .iter()
+        .filter(|(codes, _)| !codes.is_empty())
+        .map(|(codes, _)| {
+            codes
+                .keys()
+                .flat_map(|&(len, code)| (0..=len).map(move |prefix| (prefix, code >> (len - prefix))))
+                .collect::<HashSet<_>>()
+                .len()
+        })
+        .sum();
+    let code_length_range = tables.iter().flat_map(|(codes, _)| codes.keys().map(|&(len, _)| len as usize));
+    let shortest_code = code_length_range.clone().min().unwrap_or(0);
+    let longest_code = code_length_range.max().unwrap_or(0);
+
+    // Every coded bit belongs to exactly one code
+    let average_code_length = if total > 0.0 { header.original_bit_count as f64 / total } else { 0.0 };
+    let contexts = header.contexts.as_ref().map_or(0, Vec::len);
+    let codes = if contexts > 0 { Default::default() } else { header.decoding_table()? };
+    let mut symbols: Vec<SymbolEntry> = codes
+        .into_iter()
+        .map(|(code, byte)| SymbolEntry {
+            byte,
+            count: frequency[byte as usize],
+            code,
+        })
+        .collect();
+    symbols.sort_by_key(|entry| entry.byte);
+
+    Ok(Inspection {
+        compressed_size: compressed.len(),
+        header_size: compressed.len() - body.len(),
+        coded_bytes: header.byte_count(),
+        // A reused code table can have codes for bytes that never occur
+        distinct_symbols: frequency.iter().filter(|&&count| count > 0).count(),
+        shortest_code,
+        longest_code,
+        tree_nodes,
+        tree_depth: longest_code,
+        contexts,
+        padding_bits: header.padding_bits(),
+        average_code_length,
+        entropy,
+        comment: header.comment,
+        created: header.created,
+        symbols,
+    })
+}
@@ -0,0 +1,81 @@
+//! Huffman compression and decompression.
+
+// Declared first so its macros are visible in every other module
+#[macro_use]
+mod logging;
+
+pub mod benchmark;
+mod bits;
+mod cancel;
+mod canonical;
+mod codec;
+mod coder;
+mod columns;
+mod dictionary;
+mod error;
+mod frame;
+#[cfg(feature = "tokio")]
+mod async_io;
+#[cfg(feature = "gzip")]
+mod gzip;
+mod huffman;
+mod in_place;
+mod input;
+mod inspect;
+mod model;
+mod nibble;
+mod quantize;
+mod raw;
+mod scan;
+mod selfcheck;
+mod static_table;
+mod stream;
+mod stress;
+mod symbols;
+mod tee;
+mod text;
+mod warning;
+
+pub use bits::{BitOrder, BitReader, BitWriter};
+pub use cancel::CancellationToken;
+pub use canonical::{codes_to_lengths, lengths_to_codes, lengths_to_packed_codes};
+pub use codec::{
+    compress, compress_file, compress_file_range, compress_file_with, compress_into, compress_reader_to_file,
+    compress_reader_to_writer, compress_to_writer, compress_with, compress_with_stats, compress_with_trace,
+    compress_with_warnings, compressed_size, create_output, decompress_file, decompress_file_with,
+    decompress_into_slice, decompress_reader_to_writer, decompress_recover, decompress_to_vec,
+    decompress_with_code_lengths, read_code_lengths, read_compression_stats, CompressOptions, CompressionStats,
+    DecompressOptions, DecompressReport, Format, Recovery, DEFAULT_IO_BUFFER_SIZE, MAGIC, MAX_COMMENT_LEN,
+};
+#[cfg(feature = "tokio")]
+pub use async_io::{compress_file_async, decompress_file_async};
+#[cfg(feature = "blake3")]
+pub use codec::{decompressed_hash, ContentHashes};
+#[cfg(feature = "gzip")]
+pub use codec::transcode_from_gzip;
+#[cfg(feature = "tempfile")]
+pub use codec::decompress_to_tempfile;
+pub use coder::{Codec, HuffmanCodec};
+pub use dictionary::{compress_messages, decompress_messages, Dictionary, DictionaryCodec};
+pub use error::RuffmanError;
+pub use frame::{read_frame, write_frame, FRAME_PREFIX_LEN};
+pub use huffman::{
+    build_tree, code_length_distance, code_lengths, packed_codes, symbol_code_lengths, HuffmanTree, Symbol, TieBreak,
+};
+pub use in_place::{
+    compress_in_place, compressed_path, decompress_in_place, decompressed_path, InPlaceOptions, EXTENSION,
+};
+pub use inspect::{inspect, Inspection, SymbolEntry, SymbolOrder, DEFAULT_MAX_CODE_LENGTH};
+pub use model::{merge_models, Model};
+pub use raw::decode_raw;
+pub use scan::{scan, scan_dir, ScanEntry, ScanReport, ScanStatus};
+pub use selfcheck::{self_check, SelfCheckFailure};
+pub use static_table::{compress_static, decompress_static, StaticTable};
+pub use stream::{
+    compress_stream, compress_stream_indexed, compress_stream_indexed_with, decompress_stream,
+    decompress_stream_recover, read_stream_index, DamagedMember, MemberEntry, StreamIndex, DEFAULT_BLOCK_SIZE,
+};
+pub use stress::{stress_round_trips, StressFailure};
+pub use symbols::SymbolCodec;
+pub use tee::Tee;
+pub use warning::Warning;
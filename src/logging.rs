@@ -0,0 +1,25 @@
+// Events for hosts that collect logs. With the `log` feature they go to the `log` facade;
+// without it nothing is formatted, but the arguments still count as used.
+
+macro_rules! log_event {
+    ($level:ident, $($arg:tt)*) => {{
+        #[cfg(feature = "log")]
+        log::$level!($($arg)*);
+        #[cfg(not(feature = "log"))]
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    }};
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => { log_event!(debug, $($arg)*) };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => { log_event!(info, $($arg)*) };
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => { log_event!(warn, $($arg)*) };
+}
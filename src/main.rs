@@ -1,195 +1,1363 @@
-use std::collections::{BinaryHeap, HashMap};
-use std::fs::File;
-use std::io::{self, BufReader, BufWriter, Read, Write};
-use std::cmp::Ordering;
-use std::path::Path;
+use std::fs::{self, OpenOptions};
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-// Define a node in the Huffman tree
-#[derive(Debug, Eq, PartialEq)]
-struct Node {
-    freq: usize,
-    char_val: Option<u8>,
-    left: Option<Box<Node>>,
-    right: Option<Box<Node>>,
+use ruffman::benchmark::{
+    benchmark_corpus, compare_baseline, render_block_sizes, render_comparison, render_csv, render_table,
+    suggest_block_size, Baseline, BLOCK_SIZE_CANDIDATES, DEFAULT_BASELINE_TOLERANCE,
+};
+use ruffman::{
+    code_length_distance, code_lengths, compress_file_range, compress_file_with, compress_in_place,
+    compress_reader_to_file, compressed_path, create_output, decode_raw, decompress_file_with, decompress_in_place,
+    decompress_reader_to_writer, decompressed_path, inspect, read_code_lengths, read_compression_stats, BitOrder,
+    CompressOptions, CompressionStats, DecompressOptions, DecompressReport, Format, InPlaceOptions, Inspection, Model,
+    RuffmanError, SymbolOrder, TieBreak, Warning, DEFAULT_MAX_CODE_LENGTH, EXTENSION,
+};
+use ruffman::{
+    compress_messages, compress_stream, compress_stream_indexed_with, decompress_messages, decompress_stream,
+    decompress_stream_recover, read_stream_index, scan_dir, self_check, stress_round_trips, ScanStatus,
+    DEFAULT_BLOCK_SIZE,
+};
+
+fn usage(program: &str) -> ! {
+    eprintln!("Usage: {} compress [options] <input_file> [<output_file>]", program);
+    eprintln!("       {} compress [options] --input-list <file> [--out-dir <dir>]", program);
+    eprintln!("       {} decompress [options] <input_file> [<output_file>]", program);
+    eprintln!("       {} decompress --list <indexed_stream>", program);
+    eprintln!(
+        "       {} inspect [--symbols] [--sort-symbols symbol|frequency|length] [--max-code-length <bits>] \
+         [--report-format text|json] <compressed_file>",
+        program
+    );
+    eprintln!("       {} histogram <file> [--csv <out.csv>] [--smooth]", program);
+    eprintln!("       {} compare-trees <file> <file>", program);
+    eprintln!(
+        "       {} benchmark-corpus <directory> [--csv] [--save-baseline <file>] [--baseline <file> \
+         [--tolerance <fraction>]]",
+        program
+    );
+    eprintln!("       {} suggest-block-size [--sample <size>] <file>", program);
+    eprintln!("       {} scan [--deep] <directory>", program);
+    eprintln!(
+        "       {} transcode-from-gzip [--stats [--report-format text|json]] <input.gz> <output_file>",
+        program
+    );
+    eprintln!("       {} stress [--iterations <count>] [--seed <seed>]", program);
+    eprintln!("       {} pipe [--block-size <size>] [--index] [--chunk-checksum] < input > output", program);
+    eprintln!("       {} pipe --dictionary-size <size> < lines > output", program);
+    eprintln!("       {} unpipe < input > output", program);
+    eprintln!("       {} unpipe --dictionary-size <size> < input > lines", program);
+    eprintln!("       {} unpipe --recover <indexed_stream> > output", program);
+    eprintln!("       {} --selfcheck", program);
+    eprintln!();
+    eprintln!("Compress options:");
+    eprintln!("  --format ruffman|gzip  container to write (gzip needs the `gzip` feature)");
+    eprintln!("  --columns <delim>      code delimited records column by column");
+    eprintln!("  --nibble               code 4-bit halves of bytes");
+    eprintln!("  --order1               code each byte with a table chosen by the byte before it");
+    eprintln!("  --quantize <levels>    lossy: keep only one of 2 to 255 levels per byte");
+    eprintln!("  --comment <text>       store a comment in the header");
+    eprintln!("  --no-timestamp         don't record the compression time, for reproducible output");
+    eprintln!("  --text-normalize       turn CRLF into LF first (lossy, text only)");
+    eprintln!("  --body-checksum        store a checksum of the compressed body");
+    eprintln!("  --tiebreak <policy>    order of equal-frequency symbols: symbol-asc (default),");
+    eprintln!("                         symbol-desc or insertion");
+    eprintln!("  --bit-order <order>    pack body bits msb (default) or lsb first in each byte");
+    eprintln!("  --reuse-tree <file>    code with the code table of an earlier compressed file");
+    eprintln!("  --table-reference      with --reuse-tree, store only a hash of the reused table");
+    eprintln!("  --import-model <csv>   code with a table built from `histogram` byte counts");
+    eprintln!("  --smooth               with --import-model, give every byte a code so any input fits");
+    eprintln!("  --verify-after         check the output decompresses to the input");
+    eprintln!("  --verify-tree          debugging: check the Huffman tree's invariants, failing if one breaks");
+    eprintln!("  --framed               put the output behind its length, to be sent with others on a stream");
+    eprintln!("  --align <size>         pad the output with zeros to a multiple of <size>, e.g. 4K");
+    eprintln!("  --io-buffer-size <size> file buffer capacity, e.g. 64K or 1M (default 64K)");
+    eprintln!("  --stdin-size-hint <size> with `-` as <input_file>, expected input size, e.g. 100M");
+    eprintln!("  --offset <bytes>       with <output_file>, compress the input from this offset on");
+    eprintln!("  --length <bytes>       with <output_file>, compress only this many bytes of the input");
+    eprintln!("  --min-ratio <ratio>    don't write output larger than this fraction of the input");
+    eprintln!("  --append-log <file>    append a line describing the operation to a log");
+    eprintln!("  --stats                print the sizes and ratio of each result on stdout");
+    eprintln!("  --report-format <fmt>  --stats as text or json (default: text on a terminal, json otherwise)");
+    eprintln!("  --keep                 without <output_file>, keep the input next to the result");
+    eprintln!("  --force, --no-force    without <output_file>, replace an existing result (default: $RUFFMAN_FORCE)");
+    eprintln!("  --overwrite-if-newer   skip inputs whose result is newer than them, replace the rest");
+    eprintln!("  --quiet, --no-quiet    don't print warnings (default: $RUFFMAN_QUIET)");
+    eprintln!("  --input-list <file>    compress every path listed in <file> (`-` for stdin)");
+    eprintln!("  --out-dir <dir>        with --input-list, write <name>.huff files here instead of in place");
+    eprintln!("  --jobs <count>         with --input-list, compress this many files at once (needs `rayon`)");
+    eprintln!();
+    eprintln!("Decompress options:");
+    eprintln!("  --recover              write out what decodes before damage");
+    eprintln!("  --sparse               leave holes for blocks of zeros instead of writing them");
+    eprintln!("  --lengths <file>       decode a headerless stream using 256 code lengths from <file>");
+    eprintln!("  --raw-size <bytes>     with --lengths, the number of bytes to decode");
+    eprintln!("  --framed               decompress a series of --framed files into one output");
+    eprintln!("  --reuse-tree <file>    the file a --table-reference file reused its code table from");
+    eprintln!("  --expect-hash <hex>    write nothing; succeed only if the output's BLAKE3 hash is <hex>");
+    eprintln!("  --output-permissions <mode> create the output with this octal mode, e.g. 600 (Unix only)");
+    eprintln!("  --io-buffer-size, --append-log, --stats, --report-format, --keep, --force, --quiet as for compress");
+    eprintln!();
+    eprintln!("Without <output_file>, the input is replaced by <input_file>.{} (or the input", EXTENSION);
+    eprintln!("without that extension when decompressing). An <input_file> of `-` reads stdin, and");
+    eprintln!("decompress writes to stdout for an <output_file> of `-`.");
+    eprintln!("An <output_file> that is a directory gets the same name inside it.");
+    std::process::exit(1);
 }
 
-impl Node {
-    fn new(freq: usize, char_val: Option<u8>, left: Option<Box<Node>>, right: Option<Box<Node>>) -> Self {
-        Node {
-            freq,
-            char_val,
-            left,
-            right,
-        }
-    }
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+// The code lengths of the compressed file given to `--reuse-tree`
+fn read_reused_lengths(previous: &Path) -> [u8; 256] {
+    fs::read(previous)
+        .map_err(|e| e.to_string())
+        .and_then(|compressed| read_code_lengths(&compressed).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| fail(format!("Error reading {}: {}", previous.display(), e)))
 }
 
-// Custom ordering to make BinaryHeap a min-heap
-impl Ord for Node {
-    fn cmp(&self, other: &Self) -> Ordering {
-        other.freq.cmp(&self.freq)
+// Parse the value of `--format`
+fn parse_format(value: Option<&String>) -> Format {
+    match value.map(String::as_str) {
+        Some("ruffman") => Format::Ruffman,
+        #[cfg(feature = "gzip")]
+        Some("gzip") => Format::Gzip,
+        #[cfg(not(feature = "gzip"))]
+        Some("gzip") => fail("gzip support was not compiled in (enable the `gzip` feature)".to_string()),
+        Some(other) => fail(format!("Unknown format: {}", other)),
+        None => fail("--format needs a value".to_string()),
     }
 }
 
-impl PartialOrd for Node {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+// Parse the value of `--tiebreak`
+fn parse_tie_break(value: Option<&String>) -> TieBreak {
+    match value.map(String::as_str) {
+        Some("symbol-asc") => TieBreak::SymbolAsc,
+        Some("symbol-desc") => TieBreak::SymbolDesc,
+        Some("insertion") => TieBreak::Insertion,
+        Some(other) => fail(format!("Unknown tie-break policy: {}", other)),
+        None => fail("--tiebreak needs a value".to_string()),
     }
 }
 
-// Build a frequency table for characters in the input
-fn build_frequency_table(data: &[u8]) -> HashMap<u8, usize> {
-    let mut frequency = HashMap::new();
-    for &byte in data {
-        *frequency.entry(byte).or_insert(0) += 1;
+// Parse the value of `--bit-order`
+fn parse_bit_order(value: Option<&String>) -> BitOrder {
+    match value.map(String::as_str) {
+        Some("msb") => BitOrder::MsbFirst,
+        Some("lsb") => BitOrder::LsbFirst,
+        Some(other) => fail(format!("Unknown bit order: {}", other)),
+        None => fail("--bit-order needs a value".to_string()),
     }
-    frequency
 }
 
-// Build a Huffman tree using the frequency table
-fn build_huffman_tree(frequency: HashMap<u8, usize>) -> Option<Box<Node>> {
-    let mut heap = BinaryHeap::new();
+fn parse_symbol_order(value: Option<&String>) -> SymbolOrder {
+    match value.map(String::as_str) {
+        Some("symbol") => SymbolOrder::Symbol,
+        Some("frequency") => SymbolOrder::Frequency,
+        Some("length") => SymbolOrder::CodeLength,
+        Some(other) => fail(format!("Unknown symbol order: {}", other)),
+        None => fail("--sort-symbols needs a value".to_string()),
+    }
+}
 
-    for (byte, freq) in frequency {
-        heap.push(Box::new(Node::new(freq, Some(byte), None, None)));
+// Parse the value of `--columns`: a single byte, or `\t` for tab
+fn parse_delimiter(value: Option<&String>) -> u8 {
+    match value.map(String::as_str) {
+        Some("\\t") => b'\t',
+        Some(value) if value.len() == 1 => value.as_bytes()[0],
+        Some(value) => fail(format!("Delimiter must be a single byte: {}", value)),
+        None => fail("--columns needs a value".to_string()),
     }
+}
+
+// Parse the value of a flag naming a file
+fn parse_path<'a>(flag: &str, value: Option<&'a String>) -> &'a Path {
+    Path::new(value.unwrap_or_else(|| fail(format!("{} needs a value", flag))))
+}
 
-    while heap.len() > 1 {
-        let left = heap.pop().unwrap();
-        let right = heap.pop().unwrap();
+// Parse a positive compression ratio such as 0.8
+fn parse_ratio(flag: &str, value: Option<&String>) -> f64 {
+    let value = value.unwrap_or_else(|| fail(format!("{} needs a value", flag)));
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|ratio| ratio.is_finite() && *ratio > 0.0)
+        .unwrap_or_else(|| fail(format!("Invalid ratio for {}: {}", flag, value)))
+}
 
-        let combined_freq = left.freq + right.freq;
-        let new_node = Node::new(combined_freq, None, Some(left), Some(right));
+// Parse a `--tolerance` fraction, which may be 0 for no tolerance at all
+fn parse_tolerance(flag: &str, value: Option<&String>) -> f64 {
+    let value = value.unwrap_or_else(|| fail(format!("{} needs a value", flag)));
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|tolerance| tolerance.is_finite() && *tolerance >= 0.0)
+        .unwrap_or_else(|| fail(format!("Invalid tolerance for {}: {}", flag, value)))
+}
 
-        heap.push(Box::new(new_node));
-    }
+// Parse the number of `--quantize` levels
+fn parse_levels(flag: &str, value: Option<&String>) -> u8 {
+    let value = value.unwrap_or_else(|| fail(format!("{} needs a value", flag)));
+    value
+        .parse::<u8>()
+        .ok()
+        .filter(|&levels| levels >= 2)
+        .unwrap_or_else(|| fail(format!("{} takes 2 to 255 levels: {}", flag, value)))
+}
 
-    heap.pop()
+// Bytes of a file `suggest-block-size` tries the block sizes on by default
+const DEFAULT_SAMPLE_SIZE: usize = 16 << 20;
+
+// Parse octal permission bits such as `600` or `0640`
+fn parse_mode(flag: &str, value: Option<&String>) -> u32 {
+    let value = value.unwrap_or_else(|| fail(format!("{} needs a value", flag)));
+    u32::from_str_radix(value, 8)
+        .ok()
+        .filter(|&mode| mode <= 0o7777)
+        .unwrap_or_else(|| fail(format!("Invalid octal mode for {}: {}", flag, value)))
 }
 
-// Generate Huffman codes from the Huffman tree
-fn generate_codes(node: &Option<Box<Node>>, prefix: String, codes: &mut HashMap<u8, String>) {
-    if let Some(n) = node {
-        if let Some(c) = n.char_val {
-            codes.insert(c, prefix);
-        } else {
-            generate_codes(&n.left, format!("{}0", prefix), codes);
-            generate_codes(&n.right, format!("{}1", prefix), codes);
-        }
+// Parse a plain unsigned number
+fn parse_count(flag: &str, value: Option<&String>) -> u64 {
+    let value = value.unwrap_or_else(|| fail(format!("{} needs a value", flag)));
+    value.parse().unwrap_or_else(|_| fail(format!("Invalid number for {}: {}", flag, value)))
+}
+
+// Parse a byte count with an optional K, M or G suffix
+fn parse_size(flag: &str, value: Option<&String>) -> usize {
+    let value = value.unwrap_or_else(|| fail(format!("{} needs a value", flag)));
+    let (digits, multiplier) = match value.as_bytes().last() {
+        Some(b'K' | b'k') => (&value[..value.len() - 1], 1 << 10),
+        Some(b'M' | b'm') => (&value[..value.len() - 1], 1 << 20),
+        Some(b'G' | b'g') => (&value[..value.len() - 1], 1 << 30),
+        _ => (&value[..], 1),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|count| count.checked_mul(multiplier))
+        .unwrap_or_else(|| fail(format!("Invalid size for {}: {}", flag, value)))
+}
+
+// How `--stats` reports are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReportFormat {
+    Text,
+    // One object per line
+    Json,
+}
+
+// Parse the value of `--report-format`
+fn parse_report_format(value: Option<&String>) -> ReportFormat {
+    match value.map(String::as_str) {
+        Some("text") => ReportFormat::Text,
+        Some("json") => ReportFormat::Json,
+        Some(other) => fail(format!("Unknown report format: {}", other)),
+        None => fail("--report-format needs a value".to_string()),
     }
 }
 
-// Compress a file using Huffman encoding
-fn compress_file(input_path: &Path, output_path: &Path) -> io::Result<()> {
-    let input_file = File::open(input_path)?;
-    let mut reader = BufReader::new(input_file);
-    let mut data = Vec::new();
-    reader.read_to_end(&mut data)?;
+// The format for `--stats`: as asked for, otherwise text for a person at a terminal and
+// JSON for a program reading a pipe
+fn report_format(stats: bool, format: Option<ReportFormat>) -> Option<ReportFormat> {
+    match (stats, format) {
+        (true, Some(format)) => Some(format),
+        (true, None) if io::stdout().is_terminal() => Some(ReportFormat::Text),
+        (true, None) => Some(ReportFormat::Json),
+        (false, Some(_)) => fail("--report-format only applies with --stats".to_string()),
+        (false, None) => None,
+    }
+}
 
-    let frequency_table = build_frequency_table(&data);
-    let huffman_tree = build_huffman_tree(frequency_table).unwrap();
+// Print the sizes of one compression or decompression on stdout
+fn print_stats(format: ReportFormat, command: &str, input: &Path, output: &Path, stats: &CompressionStats) {
+    match format {
+        ReportFormat::Text => println!(
+            "{} -> {}: {} bytes, {} compressed ({} of header), ratio {:.3}, {} distinct symbols",
+            input.display(),
+            output.display(),
+            stats.original_size,
+            stats.compressed_size,
+            stats.header_size,
+            stats.ratio(),
+            stats.distinct_symbols
+        ),
+        ReportFormat::Json => println!(
+            "{{\"command\":{},\"input\":{},\"output\":{},\"original_size\":{},\"compressed_size\":{},\
+             \"header_size\":{},\"distinct_symbols\":{},\"ratio\":{}}}",
+            json_string(command),
+            json_string(&input.to_string_lossy()),
+            json_string(&output.to_string_lossy()),
+            stats.original_size,
+            stats.compressed_size,
+            stats.header_size,
+            stats.distinct_symbols,
+            stats.ratio()
+        ),
+    }
+}
 
-    let mut codes = HashMap::new();
-    generate_codes(&Some(huffman_tree), String::new(), &mut codes);
+// `value` as a quoted JSON string
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
 
-    let mut compressed_bits = String::new();
-    for byte in data {
-        compressed_bits.push_str(&codes[&byte]);
+// What `inspect` prints, as one JSON object. The advisory becomes `long_codes`, and the
+// code table, when asked for, a `symbols` array in the order asked for.
+fn inspection_json(inspection: &Inspection, max_code_length: usize, order: Option<SymbolOrder>) -> String {
+    let optional = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+    let mut json = format!(
+        "{{\"compressed_size\":{},\"header_size\":{},\"coded_bytes\":{},\"distinct_symbols\":{},\
+         \"shortest_code\":{},\"longest_code\":{},\"tree_nodes\":{},\"tree_depth\":{},\"contexts\":{},\
+         \"padding_bits\":{},\"average_code_length\":{},\"entropy\":{},\"efficiency\":{},\"comment\":{},\
+         \"created\":{},\"long_codes\":{}",
+        inspection.compressed_size,
+        inspection.header_size,
+        inspection.coded_bytes,
+        inspection.distinct_symbols,
+        inspection.shortest_code,
+        inspection.longest_code,
+        inspection.tree_nodes,
+        inspection.tree_depth,
+        inspection.contexts,
+        inspection.padding_bits,
+        inspection.average_code_length,
+        inspection.entropy,
+        inspection.efficiency(),
+        optional(inspection.comment.as_deref().map(json_string)),
+        optional(inspection.created.map(|created| created.to_string())),
+        inspection.has_long_codes(max_code_length)
+    );
+    if let Some(order) = order {
+        let rows: Vec<String> = inspection
+            .sorted_symbols(order)
+            .iter()
+            .map(|entry| format!("{{\"byte\":{},\"count\":{},\"code\":\"{}\"}}", entry.byte, entry.count, entry.code))
+            .collect();
+        json.push_str(&format!(",\"symbols\":[{}]", rows.join(",")));
     }
+    json.push('}');
+    json
+}
 
-    let mut output_file = File::create(output_path)?;
-    let mut writer = BufWriter::new(output_file);
+// Sizes of the compressed file at `path`, for `--stats`
+fn file_stats(path: &Path) -> Result<CompressionStats, RuffmanError> {
+    read_compression_stats(&fs::read(path)?)
+}
 
-    // Write frequency table to the output for decompression purposes
-    writer.write_all(&serde_json::to_vec(&codes).unwrap())?;
+// Size of a file, if it can be read. Taken before an operation that may replace the file.
+fn file_size(path: &Path) -> Option<u64> {
+    fs::metadata(path).map(|metadata| metadata.len()).ok()
+}
 
-    // Write compressed bits to the output file as bytes
-    let compressed_bytes = convert_bits_to_bytes(&compressed_bits);
-    writer.write_all(&compressed_bytes)?;
+// Seconds since the Unix epoch, 0 if the clock is set before it
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// Append one tab-separated line describing an operation to the `--append-log` file:
+// timestamp, command, input, output, input size, output size and result. Unknown
+// paths and sizes are written as `-`.
+fn append_log(
+    log_path: &Path,
+    command: &str,
+    input: &Path,
+    input_size: Option<u64>,
+    output: Option<&Path>,
+    result: &Result<(), String>,
+) {
+    let timestamp = unix_time();
+    let size = |size: Option<u64>| size.map_or_else(|| "-".to_string(), |size| size.to_string());
+    let outcome = match result {
+        Ok(()) => "ok".to_string(),
+        Err(e) => format!("error: {}", e).replace(['\t', '\n'], " "),
+    };
+    let line = format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+        timestamp,
+        command,
+        input.display(),
+        output.map_or_else(|| "-".to_string(), |output| output.display().to_string()),
+        size(input_size),
+        size(output.and_then(file_size)),
+        outcome
+    );
+
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)
+        .and_then(|mut log| log.write_all(line.as_bytes()));
+    if let Err(e) = written {
+        eprintln!("Warning: could not write to {}: {}", log_path.display(), e);
+    }
+}
 
-    Ok(())
+// An existing directory given as <output_file> is written into, under `name`: the name the
+// result would get next to its input
+fn into_directory(output: &Path, name: Option<PathBuf>) -> PathBuf {
+    if !output.is_dir() {
+        return output.to_path_buf();
+    }
+    match name {
+        Some(name) => output.join(name),
+        None => fail(format!("{} is a directory; name the file to write inside it", output.display())),
+    }
 }
 
-// Decompress a file using Huffman encoding
-fn decompress_file(input_path: &Path, output_path: &Path) -> io::Result<()> {
-    let input_file = File::open(input_path)?;
-    let mut reader = BufReader::new(input_file);
-    let mut compressed_data = Vec::new();
-    reader.read_to_end(&mut compressed_data)?;
+// What compressing one input came to. It is reported separately, so inputs compressed in
+// parallel are still reported in input order.
+struct CompressOutcome {
+    input_size: Option<u64>,
+    output: Option<PathBuf>,
+    result: Result<Vec<Warning>, RuffmanError>,
+    // Left alone by --overwrite-if-newer, since `output` is newer than the input
+    up_to_date: bool,
+}
 
-    // Deserialize the frequency table from the compressed data
-    let codes: HashMap<String, u8> = serde_json::from_slice(&compressed_data[..compressed_data.len() - 8]).unwrap();
+// Compress `input`, or the (offset, length) range of it, to `output`, or in place without
+// one. With `if_newer`, an input whose result is already newer than it is skipped and a
+// stale result is replaced, as make would.
+fn compress_one(
+    input: &Path,
+    output: Option<&Path>,
+    options: &CompressOptions,
+    in_place: &InPlaceOptions,
+    if_newer: bool,
+    range: Option<(u64, Option<u64>)>,
+) -> CompressOutcome {
+    let input_size = match range {
+        Some((offset, length)) => file_size(input).map(|size| length.unwrap_or(size.saturating_sub(offset))),
+        None => file_size(input),
+    };
+    let output = output.map(|output| {
+        let name = input.file_name().filter(|_| input != Path::new("-"));
+        into_directory(output, name.map(|name| compressed_path(Path::new(name), options.format)))
+    });
+    if if_newer && input != Path::new("-") {
+        let target = output.clone().unwrap_or_else(|| compressed_path(input, options.format));
+        if up_to_date(input, &target) {
+            return CompressOutcome {
+                input_size,
+                output: Some(target),
+                result: Ok(Vec::new()),
+                up_to_date: true,
+            };
+        }
+    }
+    let in_place = &InPlaceOptions {
+        force: in_place.force || if_newer,
+        ..*in_place
+    };
+    let outcome = match output.as_deref() {
+        Some(output) if input == Path::new("-") => {
+            compress_reader_to_file(io::stdin().lock(), output, options).map(|warnings| (output.into(), warnings))
+        }
+        Some(output) => match range {
+            Some((offset, length)) => compress_file_range(input, output, offset, length, options),
+            None => compress_file_with(input, output, options),
+        }
+        .map(|warnings| (output.into(), warnings)),
+        // This may run on a worker thread, so it fails this input rather than the process
+        None if input == Path::new("-") => Err(RuffmanError::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "stdin has nowhere to go without an <output_file>",
+        ))),
+        None => compress_in_place(input, options, in_place),
+    };
+    let (output, result) = match outcome {
+        Ok((output, warnings)) => (Some(output), Ok(warnings)),
+        Err(e) => (output, Err(e)),
+    };
+    CompressOutcome {
+        input_size,
+        output,
+        result,
+        up_to_date: false,
+    }
+}
 
-    // Convert the compressed bits back to binary
-    let compressed_bits = convert_bytes_to_bits(&compressed_data[compressed_data.len() - 8..]);
+// Whether `output` exists and was modified no earlier than `input`
+fn up_to_date(input: &Path, output: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    match (modified(input), modified(output)) {
+        (Some(input), Some(output)) => output >= input,
+        _ => false,
+    }
+}
 
-    // Reconstruct the original data using the Huffman codes
-    let mut decompressed_data = Vec::new();
-    let mut temp_code = String::new();
+// Report a compression on stderr, in the log and, with `--stats`, on stdout. Returns whether
+// it succeeded.
+fn report_compressed(
+    input: &Path,
+    outcome: CompressOutcome,
+    quiet: bool,
+    log_path: Option<&Path>,
+    stats: Option<ReportFormat>,
+) -> bool {
+    if outcome.up_to_date {
+        if let Some(output) = &outcome.output {
+            eprintln!("Skipped {}: {} is up to date", input.display(), output.display());
+        }
+        return true;
+    }
+    let result = match outcome.result {
+        Ok(warnings) => {
+            for warning in warnings.iter().filter(|_| !quiet) {
+                eprintln!("Warning: {}: {}", input.display(), warning);
+            }
+            if let (Some(format), Some(output)) = (stats, outcome.output.as_deref()) {
+                match file_stats(output) {
+                    Ok(mut stats) => {
+                        // The header only knows the coded size, which a transform may have changed
+                        if let Some(size) = outcome.input_size {
+                            stats.original_size = size as usize;
+                        }
+                        print_stats(format, "compress", input, output, &stats);
+                    }
+                    Err(e) => eprintln!("Warning: no stats for {}: {}", output.display(), e),
+                }
+            }
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("Error compressing {}: {}", input.display(), e);
+            Err(e.to_string())
+        }
+    };
+    if let Some(log_path) = log_path {
+        append_log(log_path, "compress", input, outcome.input_size, outcome.output.as_deref(), &result);
+    }
+    result.is_ok()
+}
 
-    for bit in compressed_bits.chars() {
-        temp_code.push(bit);
-        if let Some(byte) = codes.get(&temp_code) {
-            decompressed_data.push(*byte);
-            temp_code.clear();
+// Decompress `input` to `output`, either of which may be `-` for stdin or stdout, so
+// ruffman can sit in the middle of a pipeline
+fn decompress_to(input: &Path, output: &Path, options: &DecompressOptions) -> Result<DecompressReport, RuffmanError> {
+    let stdio = Path::new("-");
+    match (input == stdio, output == stdio) {
+        (false, false) => decompress_file_with(input, output, options),
+        (false, true) => decompress_reader_to_writer(fs::File::open(input)?, io::stdout().lock(), options),
+        (true, false) => {
+            decompress_reader_to_writer(io::stdin().lock(), create_output(output, options.output_mode)?, options)
         }
+        (true, true) => decompress_reader_to_writer(io::stdin().lock(), io::stdout().lock(), options),
+    }
+}
+
+// Decompress `input`, `-` for stdin, without writing the result anywhere, and exit with
+// status 1 unless its BLAKE3 hash is `expected`, given in hex
+#[cfg(feature = "blake3")]
+fn check_hash(input: &Path, expected: &str, options: &DecompressOptions) {
+    let expected = blake3::Hash::from_hex(expected)
+        .unwrap_or_else(|_| fail(format!("Invalid BLAKE3 hash for --expect-hash: {}", expected)));
+    let hash = if input == Path::new("-") {
+        ruffman::decompressed_hash(io::stdin().lock(), options)
+    } else {
+        fs::File::open(input).map_err(RuffmanError::from).and_then(|file| ruffman::decompressed_hash(file, options))
+    };
+    let actual = hash.map(blake3::Hash::from).unwrap_or_else(|e| fail(format!("Error decompressing file: {}", e)));
+    // `Hash` compares in constant time
+    if actual != expected {
+        fail(format!("{}: hash mismatch: expected {}, got {}", input.display(), expected.to_hex(), actual.to_hex()));
     }
+    println!("{}: OK", input.display());
+}
+
+#[cfg(not(feature = "blake3"))]
+fn check_hash(_: &Path, _: &str, _: &DecompressOptions) {
+    fail("--expect-hash was not compiled in (enable the `blake3` feature)".to_string());
+}
+
+// Decode the headerless stream in `input` to `output`, with the code lengths in the 256-byte
+// file `lengths_path`
+fn decompress_raw_file(
+    input: &Path,
+    output: &Path,
+    lengths_path: &Path,
+    byte_count: Option<usize>,
+    mode: Option<u32>,
+) -> Result<DecompressReport, RuffmanError> {
+    let lengths: [u8; 256] = fs::read(lengths_path)?.try_into().map_err(|lengths: Vec<u8>| {
+        RuffmanError::Io(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has {} bytes, not one code length per byte value (256)", lengths_path.display(), lengths.len()),
+        ))
+    })?;
+    let decoded = decode_raw(&fs::read(input)?, &lengths, byte_count)?;
+    create_output(output, mode)?.write_all(&decoded)?;
+    Ok(DecompressReport {
+        bytes_written: decoded.len(),
+        damage: None,
+    })
+}
 
-    let output_file = File::create(output_path)?;
-    let mut writer = BufWriter::new(output_file);
-    writer.write_all(&decompressed_data)?;
+// Default for a switch from the environment, so `RUFFMAN_FORCE=1` acts like `--force`
+// until `--no-force` says otherwise. Unset means off.
+fn env_flag(name: &str) -> bool {
+    match std::env::var(name).as_deref() {
+        Err(_) | Ok("" | "0" | "false" | "no") => false,
+        Ok("1" | "true" | "yes") => true,
+        Ok(other) => fail(format!("{} must be 1 or 0, not {}", name, other)),
+    }
+}
 
-    Ok(())
+// The paths listed in an `--input-list` manifest, `-` meaning stdin. Blank lines and
+// lines starting with `#` are skipped.
+fn read_input_list(list: &Path) -> Vec<PathBuf> {
+    let contents = if list == Path::new("-") {
+        let mut contents = String::new();
+        io::stdin().read_to_string(&mut contents).map(|_| contents)
+    } else {
+        fs::read_to_string(list)
+    };
+    let contents = contents.unwrap_or_else(|e| fail(format!("Error reading {}: {}", list.display(), e)));
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(PathBuf::from)
+        .collect()
 }
 
-// Convert a string of bits to bytes
-fn convert_bits_to_bytes(bits: &str) -> Vec<u8> {
-    let mut bytes = Vec::new();
-    for chunk in bits.as_bytes().chunks(8) {
-        let mut byte = 0;
-        for &bit in chunk {
-            byte = (byte << 1) | (bit - b'0');
+// Library events on stderr, for `RUFFMAN_LOG=debug` and the like
+#[cfg(feature = "log")]
+struct StderrLogger;
+
+#[cfg(feature = "log")]
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level(), record.args());
         }
-        bytes.push(byte);
     }
-    bytes
+
+    fn flush(&self) {}
 }
 
-// Convert a byte slice to a string of bits
-fn convert_bytes_to_bits(bytes: &[u8]) -> String {
-    let mut bits = String::new();
-    for &byte in bytes {
-        bits.push_str(&format!("{:08b}", byte));
+// Install `StderrLogger` at the level named by `RUFFMAN_LOG`; unset leaves logging off
+#[cfg(feature = "log")]
+fn init_logging() {
+    let Ok(level) = std::env::var("RUFFMAN_LOG") else {
+        return;
+    };
+    let level = level
+        .parse::<log::LevelFilter>()
+        .unwrap_or_else(|_| fail(format!("RUFFMAN_LOG must be a log level such as info or debug, not {}", level)));
+    static LOGGER: StderrLogger = StderrLogger;
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
     }
-    bits
 }
 
 fn main() {
+    #[cfg(feature = "log")]
+    init_logging();
     let args: Vec<String> = std::env::args().collect();
-    if args.len() < 4 {
-        eprintln!("Usage: {} <compress|decompress> <input_file> <output_file>", args[0]);
-        std::process::exit(1);
+    if args.len() < 2 {
+        usage(&args[0]);
     }
 
     let command = &args[1];
-    let input_path = Path::new(&args[2]);
-    let output_path = Path::new(&args[3]);
 
     match command.as_str() {
         "compress" => {
-            if let Err(e) = compress_file(input_path, output_path) {
-                eprintln!("Error compressing file: {}", e);
+            let mut options = CompressOptions::default();
+            let mut log_path = None;
+            let mut in_place = InPlaceOptions {
+                force: env_flag("RUFFMAN_FORCE"),
+                ..InPlaceOptions::default()
+            };
+            let mut quiet = env_flag("RUFFMAN_QUIET");
+            let mut show_stats = false;
+            let mut report = None;
+            let mut timestamp = true;
+            let mut input_list = None;
+            let mut out_dir = None;
+            let mut jobs: Option<usize> = None;
+            let mut model = None;
+            let mut smooth = false;
+            let mut if_newer = false;
+            let mut offset = None;
+            let mut length = None;
+            let mut paths = Vec::new();
+            let mut rest = args[2..].iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--input-list" => input_list = Some(parse_path(arg, rest.next())),
+                    "--out-dir" => out_dir = Some(parse_path(arg, rest.next())),
+                    "--jobs" => jobs = Some(parse_count(arg, rest.next()) as usize),
+                    "--format" => options.format = parse_format(rest.next()),
+                    "--columns" => options.columns = Some(parse_delimiter(rest.next())),
+                    "--verify-after" => options.verify_after = true,
+                    "--verify-tree" => options.verify_tree = true,
+                    "--framed" => options.framed = true,
+                    "--align" => options.align = Some(parse_size(arg, rest.next())),
+                    "--nibble" => options.nibbles = true,
+                    "--order1" => options.order1 = true,
+                    "--quantize" => options.quantize = Some(parse_levels(arg, rest.next())),
+                    "--text-normalize" | "--normalize-line-endings" => options.normalize_line_endings = true,
+                    "--body-checksum" => options.body_checksum = true,
+                    "--tiebreak" => options.tie_break = parse_tie_break(rest.next()),
+                    "--bit-order" => options.bit_order = parse_bit_order(rest.next()),
+                    "--no-timestamp" => timestamp = false,
+                    "--reuse-tree" => {
+                        options.reuse_code_lengths = Some(read_reused_lengths(parse_path(arg, rest.next())))
+                    }
+                    "--table-reference" => options.table_reference = true,
+                    "--import-model" => {
+                        let csv = parse_path(arg, rest.next());
+                        let imported = fs::read_to_string(csv)
+                            .map_err(|e| e.to_string())
+                            .and_then(|contents| Model::from_csv(&contents).map_err(|e| e.to_string()))
+                            .unwrap_or_else(|e| fail(format!("Error reading {}: {}", csv.display(), e)));
+                        model = Some(imported);
+                    }
+                    "--smooth" => smooth = true,
+                    "--min-ratio" => options.min_ratio = Some(parse_ratio(arg, rest.next())),
+                    "--keep" => in_place.keep = true,
+                    "--force" => in_place.force = true,
+                    "--no-force" => in_place.force = false,
+                    "--overwrite-if-newer" => if_newer = true,
+                    "--quiet" => quiet = true,
+                    "--no-quiet" => quiet = false,
+                    "--io-buffer-size" => options.io_buffer_size = parse_size(arg, rest.next()),
+                    "--stdin-size-hint" => options.input_size_hint = Some(parse_size(arg, rest.next())),
+                    "--offset" => offset = Some(parse_size(arg, rest.next()) as u64),
+                    "--length" => length = Some(parse_size(arg, rest.next()) as u64),
+                    "--append-log" => log_path = Some(parse_path(arg, rest.next())),
+                    "--stats" => show_stats = true,
+                    "--report-format" => report = Some(parse_report_format(rest.next())),
+                    "--comment" => {
+                        let comment = rest.next().unwrap_or_else(|| fail(format!("{} needs a value", arg)));
+                        options.comment = Some(comment.clone());
+                    }
+                    _ if arg.starts_with("--") => usage(&args[0]),
+                    _ => paths.push(Path::new(arg)),
+                }
+            }
+            let stats = report_format(show_stats, report);
+            // The stats come from the header, which a framed file does not start with
+            if options.framed && stats.is_some() {
+                fail("--stats cannot be used with --framed".to_string());
+            }
+            if options.format != Format::Ruffman && stats.is_some() {
+                fail("--stats reads the ruffman header, so it cannot be used with --format gzip".to_string());
+            }
+            if timestamp {
+                options.created = Some(unix_time());
+            }
+            if options.table_reference && (options.reuse_code_lengths.is_none() || model.is_some()) {
+                fail("--table-reference only applies with --reuse-tree".to_string());
+            }
+            // Applied once every option is in, so --smooth can come either side of --import-model
+            let model = match model {
+                Some(model) if smooth => Some(model.smoothed()),
+                None if smooth => fail("--smooth only applies with --import-model".to_string()),
+                model => model,
+            };
+            if let Some(model) = model {
+                let lengths = model.code_lengths().unwrap_or_else(|e| fail(format!("Error in --import-model: {}", e)));
+                options.reuse_code_lengths = Some(lengths);
+            }
+            let native_only = options.columns.is_some()
+                || options.comment.is_some()
+                || options.nibbles
+                || options.quantize.is_some()
+                || options.body_checksum
+                || options.tie_break != TieBreak::default()
+                || options.bit_order != BitOrder::default()
+                || options.order1
+                || options.align.is_some()
+                || options.reuse_code_lengths.is_some();
+            if native_only && options.format != Format::Ruffman {
+                fail(
+                    "--columns, --comment, --nibble, --quantize, --body-checksum, --tiebreak, --bit-order, \
+                     --order1, --align, --reuse-tree and --import-model only apply to the ruffman format"
+                        .to_string(),
+                );
+            }
+
+            if options.order1 && options.reuse_code_lengths.is_some() {
+                fail("--order1 builds its own tables, so it cannot take --reuse-tree or --import-model".to_string());
+            }
+
+            #[cfg(not(feature = "rayon"))]
+            if jobs.is_some() {
+                fail("parallel compression was not compiled in (enable the `rayon` feature)".to_string());
+            }
+
+            // Only a named output can hold part of a file; in place, the rest would be lost
+            let range = (offset.is_some() || length.is_some()).then(|| (offset.unwrap_or(0), length));
+            if range.is_some() && (input_list.is_some() || paths.len() != 2 || paths[0] == Path::new("-")) {
+                fail("--offset and --length need one <input_file> and an <output_file>".to_string());
+            }
+
+            match input_list {
+                Some(list) => {
+                    if !paths.is_empty() {
+                        usage(&args[0]);
+                    }
+                    let inputs = read_input_list(list);
+                    if let Some(dir) = out_dir {
+                        fs::create_dir_all(dir)
+                            .unwrap_or_else(|e| fail(format!("Error creating {}: {}", dir.display(), e)));
+                    }
+
+                    let compress = |input: &PathBuf| {
+                        let output = match (out_dir, input.file_name()) {
+                            (Some(dir), Some(name)) => Some(dir.join(compressed_path(Path::new(name), options.format))),
+                            (Some(_), None) => return None,
+                            (None, _) => None,
+                        };
+                        Some(compress_one(input, output.as_deref(), &options, &in_place, if_newer, None))
+                    };
+                    let outcomes: Vec<Option<CompressOutcome>> = match jobs {
+                        #[cfg(feature = "rayon")]
+                        Some(jobs) => {
+                            use rayon::prelude::*;
+                            // Collecting keeps input order whichever worker finishes first
+                            rayon::ThreadPoolBuilder::new()
+                                .num_threads(jobs)
+                                .build()
+                                .unwrap_or_else(|e| fail(format!("Error starting workers: {}", e)))
+                                .install(|| inputs.par_iter().map(compress).collect())
+                        }
+                        _ => inputs.iter().map(compress).collect(),
+                    };
+
+                    let mut failed = 0;
+                    for (input, outcome) in inputs.iter().zip(outcomes) {
+                        let succeeded = match outcome {
+                            Some(outcome) => report_compressed(input, outcome, quiet, log_path, stats),
+                            None => {
+                                eprintln!("Error compressing {}: not a file name", input.display());
+                                false
+                            }
+                        };
+                        if !succeeded {
+                            failed += 1;
+                        }
+                    }
+                    if failed > 0 {
+                        fail(format!("{} of {} files could not be compressed", failed, inputs.len()));
+                    }
+                }
+                None => {
+                    if paths.is_empty() || paths.len() > 2 || out_dir.is_some() || jobs.is_some() {
+                        usage(&args[0]);
+                    }
+                    let outcome = compress_one(paths[0], paths.get(1).copied(), &options, &in_place, if_newer, range);
+                    if !report_compressed(paths[0], outcome, quiet, log_path, stats) {
+                        std::process::exit(1);
+                    }
+                }
             }
         }
         "decompress" => {
-            if let Err(e) = decompress_file(input_path, output_path) {
-                eprintln!("Error decompressing file: {}", e);
+            let mut options = DecompressOptions::default();
+            let mut log_path = None;
+            let mut in_place = InPlaceOptions {
+                force: env_flag("RUFFMAN_FORCE"),
+                ..InPlaceOptions::default()
+            };
+            let mut quiet = env_flag("RUFFMAN_QUIET");
+            let mut show_stats = false;
+            let mut report = None;
+            let mut lengths = None;
+            let mut raw_size = None;
+            let mut list = false;
+            let mut expect_hash = None;
+            let mut paths = Vec::new();
+            let mut rest = args[2..].iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--list" => list = true,
+                    "--expect-hash" => {
+                        expect_hash = Some(rest.next().unwrap_or_else(|| fail(format!("{} needs a value", arg))))
+                    }
+                    "--recover" => options.recover = true,
+                    "--reuse-tree" => options.code_lengths = Some(read_reused_lengths(parse_path(arg, rest.next()))),
+                    "--lengths" => lengths = Some(parse_path(arg, rest.next())),
+                    "--raw-size" => raw_size = Some(parse_count(arg, rest.next()) as usize),
+                    "--sparse" => options.sparse = true,
+                    "--framed" => options.framed = true,
+                    "--output-permissions" => options.output_mode = Some(parse_mode(arg, rest.next())),
+                    "--keep" => in_place.keep = true,
+                    "--force" => in_place.force = true,
+                    "--no-force" => in_place.force = false,
+                    "--quiet" => quiet = true,
+                    "--no-quiet" => quiet = false,
+                    "--io-buffer-size" => options.io_buffer_size = parse_size(arg, rest.next()),
+                    "--append-log" => log_path = Some(parse_path(arg, rest.next())),
+                    "--stats" => show_stats = true,
+                    "--report-format" => report = Some(parse_report_format(rest.next())),
+                    _ if arg.starts_with("--") => usage(&args[0]),
+                    _ => paths.push(Path::new(arg)),
+                }
+            }
+            if paths.is_empty() || paths.len() > 2 {
+                usage(&args[0]);
+            }
+            let stats = report_format(show_stats, report);
+            if list {
+                if paths.len() != 1 {
+                    usage(&args[0]);
+                }
+                let index = fs::File::open(paths[0])
+                    .map_err(RuffmanError::from)
+                    .and_then(read_stream_index)
+                    .unwrap_or_else(|e| fail(format!("Error reading {}: {}", paths[0].display(), e)));
+                match index {
+                    Some(index) => print!("{}", index),
+                    None => fail(format!("{} has no index; write it with `pipe --index`", paths[0].display())),
+                }
+                return;
+            }
+
+            let input_size = file_size(paths[0]);
+            let name = paths[0].file_name().and_then(|name| decompressed_path(Path::new(name)));
+            let output = paths.get(1).map(|output| into_directory(output, name));
+            if let Some(output) = &output {
+                paths[1] = output;
+            }
+            if raw_size.is_some() && lengths.is_none() {
+                fail("--raw-size only applies with --lengths".to_string());
+            }
+            if options.framed && lengths.is_some() {
+                fail("--framed cannot be used with --lengths".to_string());
+            }
+            if options.framed && stats.is_some() {
+                fail("--stats cannot be used with --framed".to_string());
+            }
+            if let Some(expected) = expect_hash {
+                if paths.len() != 1 || lengths.is_some() || list || stats.is_some() {
+                    fail("--expect-hash takes only an <input_file>, and writes no output".to_string());
+                }
+                check_hash(paths[0], expected, &options);
+                return;
+            }
+            let piped = paths.iter().any(|&path| path == Path::new("-"));
+            if piped && options.sparse {
+                fail("--sparse needs files for both <input_file> and <output_file>".to_string());
+            }
+            if piped && stats.is_some() {
+                fail("--stats needs files for both <input_file> and <output_file>".to_string());
+            }
+            // Read first, since decompressing in place removes the input
+            let input_stats = stats.map(|format| (format, file_stats(paths[0])));
+            let outcome = match (&paths[..], lengths) {
+                (&[input, output], Some(lengths)) => {
+                    decompress_raw_file(input, output, lengths, raw_size, options.output_mode)
+                        .map(|report| (output.into(), report))
+                }
+                (_, Some(_)) => fail("--lengths needs an <output_file>".to_string()),
+                (&[input, output], None) => {
+                    decompress_to(input, output, &options).map(|report| (output.into(), report))
+                }
+                _ => decompress_in_place(paths[0], &options, &in_place),
+            };
+            let (output, result) = match outcome {
+                Ok((output, report)) => match report.damage {
+                    Some(damage) => {
+                        if !quiet {
+                            eprintln!(
+                                "Recovered {} bytes before the data became unreadable: {}",
+                                report.bytes_written, damage
+                            );
+                        }
+                        (Some(output), Err(format!("recovered {} bytes: {}", report.bytes_written, damage)))
+                    }
+                    None => {
+                        match input_stats {
+                            Some((format, Ok(mut stats))) => {
+                                stats.original_size = report.bytes_written;
+                                print_stats(format, command, paths[0], &output, &stats);
+                            }
+                            Some((_, Err(e))) => eprintln!("Warning: no stats for {}: {}", paths[0].display(), e),
+                            None => {}
+                        }
+                        (Some(output), Ok(()))
+                    }
+                },
+                Err(e) => {
+                    eprintln!("Error decompressing file: {}", e);
+                    (paths.get(1).map(|&output| output.into()), Err(e.to_string()))
+                }
+            };
+            if let Some(log_path) = log_path {
+                append_log(log_path, command, paths[0], input_size, output.as_deref(), &result);
+            }
+            // A partial recovery fails too, so scripts can tell it from a clean run
+            if result.is_err() {
+                std::process::exit(1);
+            }
+        }
+        "inspect" => {
+            let mut input = None;
+            let mut order = None;
+            let mut max_code_length = DEFAULT_MAX_CODE_LENGTH;
+            // Text unless asked otherwise, as inspect printed before it had a choice
+            let mut format = ReportFormat::Text;
+            let mut rest = args[2..].iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--max-code-length" => max_code_length = parse_count(arg, rest.next()) as usize,
+                    "--report-format" => format = parse_report_format(rest.next()),
+                    "--symbols" => order = order.or(Some(SymbolOrder::default())),
+                    "--sort-symbols" => order = Some(parse_symbol_order(rest.next())),
+                    _ if input.is_none() && !arg.starts_with("--") => input = Some(arg),
+                    _ => usage(&args[0]),
+                }
+            }
+            let input = input.unwrap_or_else(|| usage(&args[0]));
+            let compressed = std::fs::read(input).unwrap_or_else(|e| fail(format!("Error reading {}: {}", input, e)));
+            match inspect(&compressed) {
+                Ok(inspection) if format == ReportFormat::Json => {
+                    println!("{}", inspection_json(&inspection, max_code_length, order));
+                }
+                Ok(inspection) => {
+                    print!("{}", inspection);
+                    if inspection.has_long_codes(max_code_length) {
+                        println!(
+                            "advisory:            codes up to {} bits, above {}: the distribution is close to \
+                             degenerate; a transform such as RLE or MTF, or a different coder, may do better",
+                            inspection.longest_code, max_code_length
+                        );
+                    }
+                    if let Some(order) = order {
+                        println!();
+                        print!("{}", inspection.symbol_table(order));
+                    }
+                }
+                Err(e) => fail(format!("Error inspecting file: {}", e)),
+            }
+        }
+        "histogram" => {
+            let mut input = None;
+            let mut csv_path = None;
+            let mut smooth = false;
+            let mut rest = args[2..].iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--csv" => csv_path = Some(parse_path(arg, rest.next())),
+                    "--smooth" => smooth = true,
+                    _ if input.is_none() && !arg.starts_with("--") => input = Some(Path::new(arg)),
+                    _ => usage(&args[0]),
+                }
+            }
+            let input = input.unwrap_or_else(|| usage(&args[0]));
+            let data = fs::read(input).unwrap_or_else(|e| fail(format!("Error reading {}: {}", input.display(), e)));
+            let model = Model::from_data(&data);
+            let csv = if smooth { model.smoothed() } else { model }.to_csv();
+            match csv_path {
+                Some(path) => {
+                    fs::write(path, csv).unwrap_or_else(|e| fail(format!("Error writing {}: {}", path.display(), e)))
+                }
+                None => print!("{}", csv),
+            }
+        }
+        "compare-trees" => {
+            if args.len() != 4 {
+                usage(&args[0]);
+            }
+            let lengths: Vec<[u8; 256]> = args[2..]
+                .iter()
+                .map(|path| {
+                    let data = fs::read(path).unwrap_or_else(|e| fail(format!("Error reading {}: {}", path, e)));
+                    code_lengths(&data)
+                })
+                .collect();
+            for (path, lengths) in args[2..].iter().zip(&lengths) {
+                println!(
+                    "{}: {} distinct bytes, longest code {} bits",
+                    path,
+                    lengths.iter().filter(|&&length| length > 0).count(),
+                    lengths.iter().max().unwrap()
+                );
+            }
+            let (a, b) = (&lengths[0], &lengths[1]);
+            let same = a.iter().zip(b).filter(|(a, b)| a == b).count();
+            let only_one = a.iter().zip(b).filter(|&(&a, &b)| (a == 0) != (b == 0)).count();
+            println!("same length: {} of 256 bytes", same);
+            println!("coded in only one: {} bytes", only_one);
+            println!("distance: {:.4}", code_length_distance(a, b));
+        }
+        "benchmark-corpus" => {
+            let mut dir = None;
+            let mut csv = false;
+            let mut save_baseline = None;
+            let mut baseline = None;
+            let mut tolerance = None;
+            let mut rest = args[2..].iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--csv" => csv = true,
+                    "--save-baseline" => save_baseline = Some(parse_path(arg, rest.next())),
+                    "--baseline" => baseline = Some(parse_path(arg, rest.next())),
+                    "--tolerance" => tolerance = Some(parse_tolerance(arg, rest.next())),
+                    _ if dir.is_none() && !arg.starts_with("--") => dir = Some(Path::new(arg)),
+                    _ => usage(&args[0]),
+                }
+            }
+            let dir = dir.unwrap_or_else(|| usage(&args[0]));
+            if tolerance.is_some() && baseline.is_none() {
+                fail("--tolerance only applies with --baseline".to_string());
+            }
+            // Read before the run, so a bad file fails fast
+            let baseline = baseline.map(|path| {
+                fs::read_to_string(path)
+                    .map_err(RuffmanError::from)
+                    .and_then(|json| Baseline::from_json(&json))
+                    .unwrap_or_else(|e| fail(format!("Error reading {}: {}", path.display(), e)))
+            });
+
+            let report = benchmark_corpus(dir).unwrap_or_else(|e| fail(format!("Error reading corpus: {}", e)));
+
+            if csv {
+                print!("{}", render_csv(&report));
+            } else {
+                print!("{}", render_table(&report));
+            }
+            for (path, e) in &report.skipped {
+                eprintln!("Skipped {}: {}", path.display(), e);
+            }
+
+            let current = Baseline::from_report(&report, dir);
+            if let Some(path) = save_baseline {
+                fs::write(path, current.to_json() + "\n")
+                    .unwrap_or_else(|e| fail(format!("Error writing {}: {}", path.display(), e)));
+            }
+            if let Some(baseline) = baseline {
+                let tolerance = tolerance.unwrap_or(DEFAULT_BASELINE_TOLERANCE);
+                let comparison = compare_baseline(&current, &baseline, tolerance);
+                println!();
+                print!("{}", render_comparison(&comparison));
+                if comparison.regressed() {
+                    fail("compression ratio regressed against the baseline".to_string());
+                }
+            }
+        }
+        "scan" => {
+            let mut dir = None;
+            let mut deep = false;
+            for arg in &args[2..] {
+                match arg.as_str() {
+                    "--deep" => deep = true,
+                    _ if dir.is_none() && !arg.starts_with("--") => dir = Some(Path::new(arg)),
+                    _ => usage(&args[0]),
+                }
+            }
+            let dir = dir.unwrap_or_else(|| usage(&args[0]));
+
+            let report =
+                scan_dir(dir, deep).unwrap_or_else(|e| fail(format!("Error reading {}: {}", dir.display(), e)));
+            let (mut valid, mut invalid, mut corrupt) = (0, 0, 0);
+            for entry in &report.entries {
+                match &entry.status {
+                    ScanStatus::Valid => {
+                        valid += 1;
+                        println!("valid    {}", entry.path.display());
+                    }
+                    ScanStatus::Invalid => {
+                        invalid += 1;
+                        println!("invalid  {}", entry.path.display());
+                    }
+                    ScanStatus::Corrupt(e) => {
+                        corrupt += 1;
+                        println!("corrupt  {}: {}", entry.path.display(), e);
+                    }
+                }
+            }
+            for (path, e) in &report.skipped {
+                eprintln!("Skipped {}: {}", path.display(), e);
+            }
+            println!("{} valid, {} invalid, {} corrupt", valid, invalid, corrupt);
+            // Files that are not ruffman files at all are only reported; damaged ones are the finding
+            if corrupt > 0 || !report.skipped.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        "stress" => {
+            let mut iterations = 1000;
+            let mut seed = None;
+            let mut rest = args[2..].iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--iterations" => iterations = parse_count(arg, rest.next()),
+                    "--seed" => seed = Some(parse_count(arg, rest.next())),
+                    _ => usage(&args[0]),
+                }
+            }
+            let seed = seed.unwrap_or_else(|| {
+                SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0)
+            });
+
+            match stress_round_trips(iterations, seed) {
+                Ok(()) => println!("{} round trips passed (seed {})", iterations, seed),
+                Err(failure) => fail(format!(
+                    "Round trip failed at {}\nRerun with: {} stress --seed {} --iterations 1",
+                    failure, args[0], failure.seed
+                )),
+            }
+        }
+        "--selfcheck" => {
+            if args.len() != 2 {
+                usage(&args[0]);
+            }
+            match self_check() {
+                Ok(passed) => println!("selfcheck passed ({} checks)", passed),
+                Err(failure) => fail(format!("selfcheck failed: {}", failure)),
+            }
+        }
+        "suggest-block-size" => {
+            let mut input = None;
+            let mut sample_size = DEFAULT_SAMPLE_SIZE;
+            let mut rest = args[2..].iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--sample" => sample_size = parse_size(arg, rest.next()),
+                    _ if input.is_none() && !arg.starts_with("--") => input = Some(Path::new(arg)),
+                    _ => usage(&args[0]),
+                }
+            }
+            let input = input.unwrap_or_else(|| usage(&args[0]));
+            // Only the start of the file is tried, which is enough when it is like the rest
+            let mut sample = Vec::new();
+            fs::File::open(input)
+                .and_then(|file| file.take(sample_size as u64).read_to_end(&mut sample))
+                .unwrap_or_else(|e| fail(format!("Error reading {}: {}", input.display(), e)));
+            let suggestion = suggest_block_size(&sample, &BLOCK_SIZE_CANDIDATES);
+            print!("{}", render_block_sizes(&suggestion, sample.len()));
+        }
+        "transcode-from-gzip" => {
+            let mut paths = Vec::new();
+            let mut show_stats = false;
+            let mut report = None;
+            let mut rest = args[2..].iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--stats" => show_stats = true,
+                    "--report-format" => report = Some(parse_report_format(rest.next())),
+                    _ if arg.starts_with("--") => usage(&args[0]),
+                    _ => paths.push(Path::new(arg)),
+                }
+            }
+            let [input, output] = paths[..] else { usage(&args[0]) };
+            let stats = report_format(show_stats, report);
+            #[cfg(feature = "gzip")]
+            match ruffman::transcode_from_gzip(input, output, &CompressOptions::default()) {
+                Ok(warnings) => {
+                    for warning in &warnings {
+                        eprintln!("Warning: {}: {}", input.display(), warning);
+                    }
+                    if let Some(format) = stats {
+                        match file_stats(output) {
+                            Ok(stats) => print_stats(format, command, input, output, &stats),
+                            Err(e) => eprintln!("Warning: no stats for {}: {}", output.display(), e),
+                        }
+                    }
+                }
+                Err(e) => fail(format!("Error transcoding {}: {}", input.display(), e)),
+            }
+            #[cfg(not(feature = "gzip"))]
+            {
+                let _ = (input, output, stats);
+                fail("gzip support was not compiled in (enable the `gzip` feature)".to_string());
+            }
+        }
+        "pipe" => {
+            let mut block_size = DEFAULT_BLOCK_SIZE;
+            let mut index = false;
+            let mut chunk_checksums = false;
+            let mut dictionary_size = None;
+            let mut block_options = false;
+            let mut rest = args[2..].iter();
+            while let Some(arg) = rest.next() {
+                block_options |= arg != "--dictionary-size";
+                match arg.as_str() {
+                    "--dictionary-size" => dictionary_size = Some(parse_size(arg, rest.next()).max(1)),
+                    "--block-size" => block_size = parse_size(arg, rest.next()).max(1),
+                    "--index" => index = true,
+                    // The checksums live in the index
+                    "--chunk-checksum" => (index, chunk_checksums) = (true, true),
+                    _ => usage(&args[0]),
+                }
+            }
+            let (input, output) = (io::stdin().lock(), io::stdout().lock());
+            if let Some(size) = dictionary_size {
+                if block_options {
+                    fail("--dictionary-size codes lines, not blocks, so it takes no other options".to_string());
+                }
+                if let Err(e) = compress_messages(input, output, size) {
+                    fail(format!("Error compressing stream: {}", e));
+                }
+                return;
+            }
+            let result = if index {
+                compress_stream_indexed_with(input, output, block_size, chunk_checksums).map(|_| ())
+            } else {
+                compress_stream(input, output, block_size)
+            };
+            if let Err(e) = result {
+                fail(format!("Error compressing stream: {}", e));
             }
         }
+        "unpipe" => match &args[2..] {
+            [] => {
+                if let Err(e) = decompress_stream(io::stdin().lock(), io::stdout().lock()) {
+                    fail(format!("Error decompressing stream: {}", e));
+                }
+            }
+            [flag, size] if flag == "--dictionary-size" => {
+                let size = parse_size(flag, Some(size)).max(1);
+                if let Err(e) = decompress_messages(io::stdin().lock(), io::stdout().lock(), size) {
+                    fail(format!("Error decompressing stream: {}", e));
+                }
+            }
+            [flag, path] if flag == "--recover" => {
+                let damaged = fs::File::open(path)
+                    .map_err(RuffmanError::from)
+                    .and_then(|input| decompress_stream_recover(input, io::stdout().lock()))
+                    .unwrap_or_else(|e| fail(format!("Error decompressing stream: {}", e)));
+                for member in &damaged {
+                    eprintln!(
+                        "Skipped block {} ({} bytes at offset {}): {}",
+                        member.number + 1,
+                        member.entry.original_size,
+                        member.entry.offset,
+                        member.error
+                    );
+                }
+                if !damaged.is_empty() {
+                    fail(format!("{} damaged blocks left out", damaged.len()));
+                }
+            }
+            _ => usage(&args[0]),
+        },
         _ => {
             eprintln!("Unknown command: {}", command);
             std::process::exit(1);
         }
     }
 }
-
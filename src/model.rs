@@ -0,0 +1,150 @@
+use std::fmt::Write;
+
+use crate::canonical::codes_to_lengths;
+use crate::error::RuffmanError;
+use crate::huffman::build_codes;
+
+// First line of the CSV of a smoothed model
+const SMOOTHED_MARKER: &str = "# smoothed";
+
+/// Byte frequencies counted over some training data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Model {
+    counts: [usize; 256],
+    smoothed: bool,
+}
+
+impl Model {
+    /// An empty model
+    pub fn new() -> Self {
+        Model {
+            counts: [0; 256],
+            smoothed: false,
+        }
+    }
+
+    /// Count the bytes of a buffer
+    pub fn from_data(data: &[u8]) -> Self {
+        let mut model = Model::new();
+        model.add(data);
+        model
+    }
+
+    /// Wrap existing per-byte counts
+    pub fn from_counts(counts: [usize; 256]) -> Self {
+        Model { counts, smoothed: false }
+    }
+
+    /// Add the bytes of a buffer to the counts
+    pub fn add(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.counts[byte as usize] += 1;
+        }
+    }
+
+    /// Number of times `byte` was seen
+    pub fn count(&self, byte: u8) -> usize {
+        self.counts[byte as usize]
+    }
+
+    /// Per-byte counts, indexed by byte value
+    pub fn counts(&self) -> &[usize; 256] {
+        &self.counts
+    }
+
+    /// The model with one added to every count (add-one smoothing), so that every byte has
+    /// a code and any input can be coded with its `code_lengths`; bytes the training data
+    /// never had get long ones. A model that is already smoothed comes back unchanged.
+    pub fn smoothed(&self) -> Model {
+        if self.smoothed {
+            return self.clone();
+        }
+        Model {
+            counts: self.counts.map(|count| count.saturating_add(1)),
+            smoothed: true,
+        }
+    }
+
+    /// Whether the counts include the floor added by `smoothed`
+    pub fn is_smoothed(&self) -> bool {
+        self.smoothed
+    }
+
+    /// Huffman code length of every byte the model has seen, 0 for the rest, for
+    /// `CompressOptions::reuse_code_lengths`. Fails with `RuffmanError::FrequencyOverflow`
+    /// if the counts, e.g. from a crafted CSV, add up to more than `usize::MAX`.
+    pub fn code_lengths(&self) -> Result<[u8; 256], RuffmanError> {
+        let frequency = (0..=255u8).filter(|&byte| self.count(byte) > 0).map(|byte| (byte, self.count(byte))).collect();
+        Ok(codes_to_lengths(&build_codes(frequency)?))
+    }
+
+    /// The counts as CSV, a `byte,count` header and then one row per byte value. A smoothed
+    /// model starts with a `# smoothed` line so it is not smoothed twice.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        if self.smoothed {
+            writeln!(csv, "{}", SMOOTHED_MARKER).unwrap();
+        }
+        csv.push_str("byte,count\n");
+        for (byte, count) in self.counts.iter().enumerate() {
+            writeln!(csv, "{},{}", byte, count).unwrap();
+        }
+        csv
+    }
+
+    /// Read counts written by `to_csv`, or edited from it. The header is optional, blank
+    /// lines are skipped and bytes without a row count 0; each byte may appear only once.
+    pub fn from_csv(csv: &str) -> Result<Self, RuffmanError> {
+        let mut model = Model::new();
+        let mut seen = [false; 256];
+        let mut lines = csv.lines().enumerate().peekable();
+        if lines.next_if(|(_, line)| line.trim() == SMOOTHED_MARKER).is_some() {
+            model.smoothed = true;
+        }
+        let header_index = model.smoothed as usize;
+        for (index, line) in lines {
+            let line_number = index + 1;
+            let invalid = |message: String| RuffmanError::InvalidModel {
+                line: line_number,
+                message,
+            };
+            let line = line.trim();
+            if line.is_empty() || (index == header_index && line == "byte,count") {
+                continue;
+            }
+            let (byte, count) = line
+                .split_once(',')
+                .ok_or_else(|| invalid("expected byte,count".to_string()))?;
+            let byte: u8 = byte
+                .trim()
+                .parse()
+                .map_err(|_| invalid(format!("byte must be 0 to 255, not {}", byte.trim())))?;
+            let count: usize = count
+                .trim()
+                .parse()
+                .map_err(|_| invalid(format!("count must be a non-negative integer, not {}", count.trim())))?;
+            if std::mem::replace(&mut seen[byte as usize], true) {
+                return Err(invalid(format!("byte {} appears twice", byte)));
+            }
+            model.counts[byte as usize] = count;
+        }
+        Ok(model)
+    }
+}
+
+impl Default for Model {
+    fn default() -> Self {
+        Model::new()
+    }
+}
+
+/// Combine models counted over separate shards, as if the shards had been counted together.
+/// Counts saturate at `usize::MAX` rather than wrapping. The result is smoothed if either is.
+pub fn merge_models(a: &Model, b: &Model) -> Model {
+    let mut merged = a.clone();
+    merged.smoothed |= b.smoothed;
+    for (count, &other) in merged.counts.iter_mut().zip(&b.counts) {
+        *count = count.saturating_add(other);
+    }
+    merged
+}
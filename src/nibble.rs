@@ -0,0 +1,19 @@
+use crate::error::RuffmanError;
+
+// Split every byte into two 4-bit symbols, high nibble first
+pub(crate) fn split_nibbles(data: &[u8]) -> Vec<u8> {
+    let mut nibbles = Vec::with_capacity(data.len() * 2);
+    for &byte in data {
+        nibbles.push(byte >> 4);
+        nibbles.push(byte & 0x0f);
+    }
+    nibbles
+}
+
+// Undo `split_nibbles`
+pub(crate) fn join_nibbles(nibbles: &[u8]) -> Result<Vec<u8>, RuffmanError> {
+    if !nibbles.len().is_multiple_of(2) || nibbles.iter().any(|&nibble| nibble > 0x0f) {
+        return Err(RuffmanError::CorruptData("invalid nibble stream".to_string()));
+    }
+    Ok(nibbles.chunks(2).map(|pair| (pair[0] << 4) | pair[1]).collect())
+}
@@ -0,0 +1,23 @@
+use crate::error::RuffmanError;
+
+// Map every byte onto one of `levels` equal-width buckets and keep only the bucket index
+pub(crate) fn quantize(data: &[u8], levels: u8) -> Vec<u8> {
+    data.iter().map(|&byte| (byte as u32 * levels as u32 / 256) as u8).collect()
+}
+
+// Undo `quantize` as far as possible: every bucket index becomes the middle of its bucket,
+// at most half a bucket (`256 / levels / 2`, rounded up) away from the original byte
+pub(crate) fn dequantize(indices: &[u8], levels: u8) -> Result<Vec<u8>, RuffmanError> {
+    let levels = levels as u32;
+    // Smallest byte that lands in bucket `index`
+    let bucket_start = |index: u32| (index * 256).div_ceil(levels);
+    let representatives: Vec<u8> = (0..levels)
+        .map(|index| ((bucket_start(index) + bucket_start(index + 1) - 1) / 2) as u8)
+        .collect();
+
+    indices
+        .iter()
+        .map(|&index| representatives.get(index as usize).copied())
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| RuffmanError::CorruptData("quantized value out of range".to_string()))
+}
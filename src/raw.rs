@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+use crate::bits::BitReader;
+use crate::codec::Header;
+use crate::error::RuffmanError;
+use crate::huffman::Symbol;
+
+/// Decode a headerless Huffman bit stream, most significant bit first, such as one written
+/// by another tool, given the code length of every byte (0 for none). The codes are
+/// rebuilt canonically, as in `lengths_to_codes`, so the producer must assign them the
+/// same way.
+///
+/// With `byte_count`, decoding stops after that many bytes. Without it, decoding runs to
+/// the end of `body` and drops a final incomplete code as padding; padding bits that
+/// happen to form whole codes then decode as extra bytes, so pass the count when known.
+pub fn decode_raw(body: &[u8], lengths: &[u8; 256], byte_count: Option<usize>) -> Result<Vec<u8>, RuffmanError> {
+    let codes = Header::new(lengths, 0, 0).decoding_table().map_err(|_| RuffmanError::InvalidCodeLengths)?;
+    let longest_code = lengths.iter().copied().max().unwrap_or(0) as usize;
+    decode_with_table(body, &codes, longest_code, byte_count)
+}
+
+// `decode_raw` with the codes already rebuilt, mapping each one back to its symbol; bytes
+// for files, any `Symbol` for `SymbolCodec`
+pub(crate) fn decode_with_table<S: Symbol>(
+    body: &[u8],
+    codes: &HashMap<String, S>,
+    longest_code: usize,
+    byte_count: Option<usize>,
+) -> Result<Vec<S>, RuffmanError> {
+    let mut bits = BitReader::new(body);
+    // Every code is at least a bit long, so a count the body cannot hold, as a caller or a
+    // crafted file can claim, reserves no more than the body could decode to
+    let mut decoded = Vec::with_capacity(byte_count.unwrap_or(body.len()).min(body.len().saturating_mul(8)));
+    let mut code = String::new();
+    let mut index = 0;
+    while byte_count != Some(decoded.len()) {
+        let Some(bit) = bits.read_bit()? else {
+            break;
+        };
+        code.push(if bit { '1' } else { '0' });
+        index += 1;
+        if let Some(&byte) = codes.get(&code) {
+            decoded.push(byte);
+            code.clear();
+        } else if code.len() >= longest_code {
+            return Err(RuffmanError::CorruptDataAt {
+                offset: (index - code.len()) / 8,
+                message: "bits match no code".to_string(),
+            });
+        }
+    }
+
+    match byte_count {
+        Some(expected) if decoded.len() != expected => Err(RuffmanError::LengthMismatch {
+            expected,
+            actual: decoded.len(),
+        }),
+        // Padding never fills a whole byte
+        None if code.len() >= 8 => Err(RuffmanError::CorruptDataAt {
+            offset: (body.len() * 8 - code.len()) / 8,
+            message: "stream ends in the middle of a code".to_string(),
+        }),
+        _ => Ok(decoded),
+    }
+}
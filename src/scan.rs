@@ -0,0 +1,102 @@
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+
+use crate::benchmark::collect_files;
+use crate::codec::{parse, parse_table_reference, MAGIC};
+use crate::coder::{DICTIONARY_MODE, HUFFMAN_MODE, TABLE_REFERENCE_MODE};
+use crate::error::RuffmanError;
+use crate::stream::{decompress_stream, decompress_stream_recover, read_stream_index};
+
+/// What `scan` made of a file
+#[derive(Debug)]
+pub enum ScanStatus {
+    /// A ruffman file whose header checks out, and with `deep` its whole body too
+    Valid,
+    /// Not a ruffman file: it does not start with the magic
+    Invalid,
+    /// Starts like a ruffman file but is damaged, for the reason given
+    Corrupt(RuffmanError),
+}
+
+/// One file of a `scan_dir` report
+#[derive(Debug)]
+pub struct ScanEntry {
+    pub path: PathBuf,
+    pub status: ScanStatus,
+}
+
+/// Every file scanned, in path order, and the files that could not be read
+#[derive(Debug)]
+pub struct ScanReport {
+    pub entries: Vec<ScanEntry>,
+    pub skipped: Vec<(PathBuf, io::Error)>,
+}
+
+/// Classify `data` without decompressing it: the magic, the mode byte and the header, with
+/// the sizes and code lengths it claims, must all be sound. With `deep`, every member of the
+/// stream is decoded as well, which checks body checksums, and the checksums an indexed
+/// stream keeps for its members. Dictionary messages can only be decoded with their
+/// dictionary, so only their magic and mode are checked, and files that refer to their
+/// code table only have their header checked.
+pub fn scan(data: &[u8], deep: bool) -> ScanStatus {
+    if !data.starts_with(&MAGIC) {
+        return ScanStatus::Invalid;
+    }
+    let checked = match data.get(MAGIC.len()) {
+        Some(&HUFFMAN_MODE) => check_huffman(data, deep),
+        Some(&DICTIONARY_MODE) => Ok(()),
+        Some(&TABLE_REFERENCE_MODE) => parse_table_reference(data).map(|_| ()),
+        Some(&mode) => Err(RuffmanError::UnsupportedMode(mode)),
+        None => Err(RuffmanError::CorruptDataAt {
+            offset: MAGIC.len(),
+            message: "missing mode byte".to_string(),
+        }),
+    };
+    match checked {
+        Ok(()) => ScanStatus::Valid,
+        Err(e) => ScanStatus::Corrupt(e),
+    }
+}
+
+fn check_huffman(data: &[u8], deep: bool) -> Result<(), RuffmanError> {
+    let (header, _) = parse(data)?;
+    header.decoding_tables()?;
+    if !deep {
+        return Ok(());
+    }
+    // Through the index where there is one, so members it has checksums for are checked
+    // against them
+    if read_stream_index(Cursor::new(data))?.is_some() {
+        let damaged = decompress_stream_recover(Cursor::new(data), io::sink())?;
+        if let Some(member) = damaged.into_iter().next() {
+            return Err(member.error);
+        }
+        return Ok(());
+    }
+    decompress_stream(data, io::sink())
+}
+
+/// `scan` every file below `dir`, or `dir` itself if it is a file
+pub fn scan_dir(dir: &Path, deep: bool) -> io::Result<ScanReport> {
+    let mut paths = Vec::new();
+    if dir.is_dir() {
+        collect_files(dir, &mut paths)?;
+        paths.sort();
+    } else {
+        paths.push(dir.to_path_buf());
+    }
+
+    let mut entries = Vec::new();
+    let mut skipped = Vec::new();
+    for path in paths {
+        match fs::read(&path) {
+            Ok(data) => {
+                let status = scan(&data, deep);
+                entries.push(ScanEntry { path, status });
+            }
+            Err(e) => skipped.push((path, e)),
+        }
+    }
+    Ok(ScanReport { entries, skipped })
+}
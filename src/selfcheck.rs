@@ -0,0 +1,66 @@
+use std::fmt;
+
+use crate::bits::BitOrder;
+use crate::codec::{compress, compress_with, decompress_to_vec, CompressOptions};
+use crate::error::RuffmanError;
+
+// `compress(b"abracadabra")` as every build must produce it. A mismatch means the format
+// differs on this platform, e.g. an integer written in host byte order.
+const ABRACADABRA: [u8; 61] = [
+    82, 85, 70, 70, 0, 49, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 97, 1, 98, 3, 99, 3, 100, 3, 114, 3, 23, 0, 0, 0, 0, 0, 0,
+    0, 11, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 78, 172, 156,
+];
+
+/// A built-in check that did not pass
+#[derive(Debug)]
+pub struct SelfCheckFailure {
+    pub check: &'static str,
+    /// Why decompression failed, or `None` if the output was wrong
+    pub error: Option<RuffmanError>,
+}
+
+impl fmt::Display for SelfCheckFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.error {
+            Some(e) => write!(f, "{}: {}", self.check, e),
+            None => write!(f, "{}: unexpected output", self.check),
+        }
+    }
+}
+
+/// Run the built-in round trips and the known-output check, so a binary can be confirmed
+/// to work on the platform it runs on. Returns the number of checks passed.
+pub fn self_check() -> Result<usize, SelfCheckFailure> {
+    let text = b"the quick brown fox jumps over the lazy dog\r\n".repeat(20);
+    let all_bytes: Vec<u8> = (0..=255u8).cycle().take(1024).collect();
+    let records = b"1,alpha,3.5\n2,beta,4.25\n3,gamma,5\n".repeat(10);
+
+    let cases: [(&'static str, &[u8], CompressOptions); 9] = [
+        ("empty input", b"", CompressOptions::default()),
+        ("single symbol", &[7; 100], CompressOptions::default()),
+        ("text", &text, CompressOptions::default()),
+        ("every byte value", &all_bytes, CompressOptions::default()),
+        ("body checksum", &text, CompressOptions { body_checksum: true, ..CompressOptions::default() }),
+        ("nibbles", &all_bytes, CompressOptions { nibbles: true, ..CompressOptions::default() }),
+        ("columns", &records, CompressOptions { columns: Some(b','), ..CompressOptions::default() }),
+        ("order-1 model", &text, CompressOptions { order1: true, ..CompressOptions::default() }),
+        ("lsb bit order", &text, CompressOptions { bit_order: BitOrder::LsbFirst, ..CompressOptions::default() }),
+    ];
+    for (check, data, options) in &cases {
+        match decompress_to_vec(&compress_with(data, options)) {
+            Ok(decompressed) if decompressed == *data => {}
+            Ok(_) => return Err(SelfCheckFailure { check, error: None }),
+            Err(e) => return Err(SelfCheckFailure { check, error: Some(e) }),
+        }
+    }
+
+    let check = "known output";
+    if compress(b"abracadabra") != ABRACADABRA {
+        return Err(SelfCheckFailure { check, error: None });
+    }
+    match decompress_to_vec(&ABRACADABRA) {
+        Ok(decompressed) if decompressed == b"abracadabra" => Ok(cases.len() + 1),
+        Ok(_) => Err(SelfCheckFailure { check, error: None }),
+        Err(e) => Err(SelfCheckFailure { check, error: Some(e) }),
+    }
+}
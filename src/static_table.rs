@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+
+use crate::bits::BitWriter;
+use crate::canonical::lengths_to_codes;
+use crate::codec::{Header, PADDING};
+use crate::error::RuffmanError;
+use crate::raw::decode_with_table;
+
+/// Code lengths both ends of a protocol agree on ahead of time, e.g. from a `const` array or
+/// a file read by a build script, with the canonical codes worked out from them once.
+/// Frames coded with `compress_static` then carry no header at all.
+#[derive(Debug, Clone)]
+pub struct StaticTable {
+    lengths: [u8; 256],
+    codes: HashMap<u8, String>,
+    decoding: HashMap<String, u8>,
+    // Its first bits pad the last byte of a frame without completing a code
+    longest: String,
+}
+
+impl StaticTable {
+    /// Build the codes for the given length of every byte, 0 for bytes without a code. Codes
+    /// are assigned canonically, as in `lengths_to_codes`.
+    pub fn new(lengths: &[u8; 256]) -> Result<Self, RuffmanError> {
+        let decoding =
+            Header::new(lengths, 0, 0).decoding_table().map_err(|_| RuffmanError::InvalidCodeLengths)?;
+        let codes = lengths_to_codes(*lengths);
+        // Codes of one length all differ, so this picks the same code every time
+        let longest = codes
+            .values()
+            .max_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)))
+            .cloned()
+            .unwrap_or_default();
+        Ok(StaticTable {
+            lengths: *lengths,
+            codes,
+            decoding,
+            longest,
+        })
+    }
+
+    /// The code lengths the table was built from
+    pub fn code_lengths(&self) -> &[u8; 256] {
+        &self.lengths
+    }
+}
+
+/// Code `data` with `table` alone: no magic, header or length, only the codes, most
+/// significant bit first. The last byte is padded with the start of the table's longest
+/// code, which never decodes as a byte, so when that code is at least 8 bits long, as in
+/// any table covering every byte, `decompress_static` needs no count.
+///
+/// Panics if `data` holds a byte the table has no code for.
+pub fn compress_static(data: &[u8], table: &StaticTable) -> Vec<u8> {
+    let bit_count: u64 = data.iter().map(|&byte| table.lengths[byte as usize] as u64).sum();
+    let mut frame = BitWriter::new(Vec::with_capacity(bit_count.div_ceil(8) as usize));
+    for byte in data {
+        let code = table.codes.get(byte).unwrap_or_else(|| panic!("byte {:#04x} has no code in the table", byte));
+        frame.write_code(code).expect("writing to a Vec cannot fail");
+    }
+
+    let pad = ((8 - bit_count % 8) % 8) as usize;
+    let padding = match pad {
+        0 => PADDING,
+        // Strictly shorter than the code, so no code matches it
+        _ if table.longest.len() > pad => u8::from_str_radix(&table.longest[..pad], 2).expect("codes are binary"),
+        _ => PADDING,
+    };
+    frame.finish_with(padding).expect("writing to a Vec cannot fail")
+}
+
+/// Decode a frame from `compress_static` with the same table. With `byte_count`, decoding
+/// stops after that many bytes; pass it when the table's longest code is shorter than 8
+/// bits, since padding can then decode as extra bytes.
+pub fn decompress_static(
+    frame: &[u8],
+    table: &StaticTable,
+    byte_count: Option<usize>,
+) -> Result<Vec<u8>, RuffmanError> {
+    decode_with_table(frame, &table.decoding, table.longest.len(), byte_count)
+}
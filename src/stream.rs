@@ -0,0 +1,314 @@
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::codec::{compress, decompress_to_vec, parse_header, HEADER_LEN_START, HEADER_START};
+use crate::coder::codec_for;
+use crate::error::RuffmanError;
+
+/// Bytes of input `compress_stream` codes into each member when not told otherwise
+pub const DEFAULT_BLOCK_SIZE: usize = 1 << 20;
+
+// Opens the index after the last member and closes the trailer after it
+const INDEX_MAGIC: [u8; 4] = *b"RIDX";
+
+// Opens an index whose entries end in a checksum of their member
+const CHECKSUMMED_INDEX_MAGIC: [u8; 4] = *b"RIDC";
+
+// Offset, compressed size and original size of a member, each a little-endian u64
+const MEMBER_ENTRY_SIZE: usize = 24;
+
+// The same followed by the little-endian CRC-32 of the member's bytes
+const CHECKSUMMED_ENTRY_SIZE: usize = MEMBER_ENTRY_SIZE + 4;
+
+// Index offset and magic at the very end of an indexed stream
+const TRAILER_SIZE: u64 = 12;
+
+// Bytes of trailing padding checked at a time
+const PADDING_BLOCK_SIZE: u64 = 64 * 1024;
+
+/// Where `compress_stream_indexed` put each member, in stream order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamIndex {
+    pub members: Vec<MemberEntry>,
+}
+
+/// A table of the members, numbered from 1, with their offsets and sizes and a closing total line
+impl fmt::Display for StreamIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>8}  {:>12}  {:>12}  {:>12}", "member", "offset", "original", "compressed")?;
+        for (number, member) in self.members.iter().enumerate() {
+            writeln!(
+                f,
+                "{:>8}  {:>12}  {:>12}  {:>12}",
+                number + 1,
+                member.offset,
+                member.original_size,
+                member.compressed_size
+            )?;
+        }
+        let original: u64 = self.members.iter().map(|member| member.original_size).sum();
+        let compressed: u64 = self.members.iter().map(|member| member.compressed_size).sum();
+        writeln!(f, "{:>8}  {:>12}  {:>12}  {:>12}", "total", "", original, compressed)
+    }
+}
+
+/// One member of a stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemberEntry {
+    /// Offset of the member from the start of the stream
+    pub offset: u64,
+    pub compressed_size: u64,
+    /// Number of input bytes the member holds
+    pub original_size: u64,
+    /// CRC-32 of the member's compressed bytes, if the index was written with checksums
+    pub checksum: Option<u32>,
+}
+
+/// A member `decompress_stream_recover` had to skip
+#[derive(Debug)]
+pub struct DamagedMember {
+    /// Position of the member in the stream, from 0
+    pub number: usize,
+    pub entry: MemberEntry,
+    pub error: RuffmanError,
+}
+
+/// Compress `reader` to `writer` a block at a time, so neither the input length nor more
+/// than one block of it needs to be known up front. Each block of up to `block_size` bytes
+/// becomes a complete compressed file of its own, written as soon as the block is full;
+/// `decompress_stream` reads the members back in order. Empty input still produces one
+/// (empty) member, so the output is never mistaken for a missing stream.
+pub fn compress_stream<R: Read, W: Write>(reader: R, writer: W, block_size: usize) -> Result<(), RuffmanError> {
+    write_members(reader, writer, block_size, false, |_| {})
+}
+
+/// Like `compress_stream`, then append a footer indexing the members, so a tool that can
+/// seek finds every member's offset and sizes with `read_stream_index` without scanning.
+/// `decompress_stream` stops at the footer and single-member readers ignore it.
+pub fn compress_stream_indexed<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    block_size: usize,
+) -> Result<StreamIndex, RuffmanError> {
+    compress_stream_indexed_with(reader, writer, block_size, false)
+}
+
+/// Like `compress_stream_indexed`, also storing a CRC-32 of every member in the index when
+/// `chunk_checksums` is set, so `decompress_stream_recover` can tell exactly which members
+/// were damaged and skip only those
+pub fn compress_stream_indexed_with<R: Read, W: Write>(
+    reader: R,
+    mut writer: W,
+    block_size: usize,
+    chunk_checksums: bool,
+) -> Result<StreamIndex, RuffmanError> {
+    let mut members = Vec::new();
+    write_members(reader, &mut writer, block_size, chunk_checksums, |member| members.push(member))?;
+    let index = StreamIndex { members };
+
+    let (magic, entry_size) = if chunk_checksums {
+        (CHECKSUMMED_INDEX_MAGIC, CHECKSUMMED_ENTRY_SIZE)
+    } else {
+        (INDEX_MAGIC, MEMBER_ENTRY_SIZE)
+    };
+    let index_offset = index.members.last().map_or(0, |last| last.offset + last.compressed_size);
+    let mut footer = Vec::with_capacity(INDEX_MAGIC.len() * 2 + 16 + index.members.len() * entry_size);
+    footer.extend_from_slice(&magic);
+    footer.extend_from_slice(&(index.members.len() as u64).to_le_bytes());
+    for member in &index.members {
+        footer.extend_from_slice(&member.offset.to_le_bytes());
+        footer.extend_from_slice(&member.compressed_size.to_le_bytes());
+        footer.extend_from_slice(&member.original_size.to_le_bytes());
+        if let Some(checksum) = member.checksum {
+            footer.extend_from_slice(&checksum.to_le_bytes());
+        }
+    }
+    footer.extend_from_slice(&index_offset.to_le_bytes());
+    footer.extend_from_slice(&INDEX_MAGIC);
+    writer.write_all(&footer)?;
+    writer.flush()?;
+    Ok(index)
+}
+
+/// Read the footer of a stream written by `compress_stream_indexed` from its last bytes,
+/// without touching the members. Returns `None` if the stream has no footer.
+pub fn read_stream_index<R: Read + Seek>(mut reader: R) -> Result<Option<StreamIndex>, RuffmanError> {
+    let end = reader.seek(SeekFrom::End(0))?;
+    if end < TRAILER_SIZE {
+        return Ok(None);
+    }
+    let mut trailer = [0u8; TRAILER_SIZE as usize];
+    reader.seek(SeekFrom::Start(end - TRAILER_SIZE))?;
+    reader.read_exact(&mut trailer)?;
+    if trailer[8..] != INDEX_MAGIC {
+        return Ok(None);
+    }
+    let index_offset = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+
+    // The index runs from its offset up to the trailer, so its size must match its count
+    let corrupt = |message: &str| RuffmanError::CorruptDataAt {
+        offset: index_offset as usize,
+        message: message.to_string(),
+    };
+    let index_size = (end - TRAILER_SIZE)
+        .checked_sub(index_offset)
+        .ok_or_else(|| corrupt("index offset past the end"))?;
+    if index_size < 12 {
+        return Err(corrupt("truncated index"));
+    }
+    let mut index = vec![0u8; index_size as usize];
+    reader.seek(SeekFrom::Start(index_offset))?;
+    reader.read_exact(&mut index)?;
+    let entry_size = match index[..4].try_into().unwrap() {
+        INDEX_MAGIC => MEMBER_ENTRY_SIZE,
+        CHECKSUMMED_INDEX_MAGIC => CHECKSUMMED_ENTRY_SIZE,
+        _ => return Err(corrupt("index does not start with its magic")),
+    };
+    let count = u64::from_le_bytes(index[4..12].try_into().unwrap());
+    let entries = &index[12..];
+    if count.checked_mul(entry_size as u64) != Some(entries.len() as u64) {
+        return Err(corrupt("index size does not match its member count"));
+    }
+
+    let field = |entry: &[u8], at: usize| u64::from_le_bytes(entry[at..at + 8].try_into().unwrap());
+    let members = entries
+        .chunks(entry_size)
+        .map(|entry| MemberEntry {
+            offset: field(entry, 0),
+            compressed_size: field(entry, 8),
+            original_size: field(entry, 16),
+            checksum: entry
+                .get(MEMBER_ENTRY_SIZE..CHECKSUMMED_ENTRY_SIZE)
+                .map(|crc| u32::from_le_bytes(crc.try_into().unwrap())),
+        })
+        .collect();
+    Ok(Some(StreamIndex { members }))
+}
+
+// Compress `reader` to `writer` as members of up to `block_size` input bytes, describing
+// each member to `on_member` once it has been written
+fn write_members<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    block_size: usize,
+    checksums: bool,
+    mut on_member: impl FnMut(MemberEntry),
+) -> Result<(), RuffmanError> {
+    let block_size = block_size.max(1);
+    let mut block = Vec::with_capacity(block_size);
+    let mut offset = 0;
+    let mut written_any = false;
+    loop {
+        block.clear();
+        reader.by_ref().take(block_size as u64).read_to_end(&mut block)?;
+        if block.is_empty() && written_any {
+            break;
+        }
+        let member = compress(&block);
+        writer.write_all(&member)?;
+        // Pipelines should see each block as soon as it is ready
+        writer.flush()?;
+        on_member(MemberEntry {
+            offset,
+            compressed_size: member.len() as u64,
+            original_size: block.len() as u64,
+            checksum: checksums.then(|| crc32fast::hash(&member)),
+        });
+        offset += member.len() as u64;
+        written_any = true;
+        if block.len() < block_size {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Decompress the members written by `compress_stream` from `reader` to `writer`, one
+/// member in memory at a time, stopping at an index footer if there is one. A single file
+/// from `compress` is a stream of one member; zero bytes after the last member, such as
+/// padding from `CompressOptions::align`, are skipped.
+pub fn decompress_stream<R: Read, W: Write>(mut reader: R, mut writer: W) -> Result<(), RuffmanError> {
+    let mut member = Vec::new();
+    loop {
+        member.clear();
+        if read_up_to(&mut reader, &mut member, HEADER_START as u64)? == 0
+            || member.starts_with(&INDEX_MAGIC)
+            || member.starts_with(&CHECKSUMMED_INDEX_MAGIC)
+        {
+            break;
+        }
+        if member[0] == 0 {
+            skip_padding(&mut reader, &member)?;
+            break;
+        }
+        // Catch something that is not a stream of members before trusting its lengths
+        codec_for(&member)?;
+        if member.len() < HEADER_START {
+            return Err(RuffmanError::CorruptData("stream ends partway through a member".to_string()));
+        }
+        let header_len = u32::from_le_bytes(member[HEADER_LEN_START..HEADER_START].try_into().unwrap()) as u64;
+        read_up_to(&mut reader, &mut member, header_len)?;
+        let (header, _) = parse_header(&member)?;
+        read_up_to(&mut reader, &mut member, header.original_bit_count.div_ceil(8))?;
+
+        writer.write_all(&decompress_to_vec(&member)?)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+// Check that `read`, the bytes already taken after the last member, and the rest of
+// `reader` are all zero, a block at a time
+fn skip_padding<R: Read>(reader: &mut R, read: &[u8]) -> Result<(), RuffmanError> {
+    let mut block = read.to_vec();
+    loop {
+        if block.iter().any(|&byte| byte != 0) {
+            return Err(RuffmanError::CorruptData("data after padding at the end of a stream".to_string()));
+        }
+        block.clear();
+        if read_up_to(reader, &mut block, PADDING_BLOCK_SIZE)? == 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// Decompress an indexed stream member by member as the index lays it out, skipping any
+/// member that fails its checksum or does not decode instead of stopping there. Returns the
+/// skipped members, whose data is left out of `writer`. Without checksums in the index, a
+/// damaged member is only caught when it no longer decodes.
+pub fn decompress_stream_recover<R: Read + Seek, W: Write>(
+    mut reader: R,
+    mut writer: W,
+) -> Result<Vec<DamagedMember>, RuffmanError> {
+    let index = read_stream_index(&mut reader)?
+        .ok_or_else(|| RuffmanError::CorruptData("stream has no index to recover from".to_string()))?;
+    let mut damaged = Vec::new();
+    let mut member = Vec::new();
+    for (number, &entry) in index.members.iter().enumerate() {
+        member.clear();
+        reader.seek(SeekFrom::Start(entry.offset))?;
+        read_up_to(&mut reader, &mut member, entry.compressed_size)?;
+        let decoded = match entry.checksum {
+            Some(checksum) if crc32fast::hash(&member) != checksum => Err(RuffmanError::CorruptDataAt {
+                offset: entry.offset as usize,
+                message: "member does not match its checksum".to_string(),
+            }),
+            _ => decompress_to_vec(&member),
+        };
+        match decoded {
+            Ok(decoded) => writer.write_all(&decoded)?,
+            Err(error) => {
+                warn!("skipping stream member {} at offset {}: {}", number, entry.offset, error);
+                damaged.push(DamagedMember { number, entry, error });
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(damaged)
+}
+
+// Append up to `count` bytes from `reader` to `buffer`, fewer only at end of input. The
+// buffer grows with what arrives, so a damaged length cannot allocate it all at once.
+fn read_up_to(reader: &mut impl Read, buffer: &mut Vec<u8>, count: u64) -> io::Result<usize> {
+    reader.by_ref().take(count).read_to_end(buffer)
+}
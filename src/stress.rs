@@ -0,0 +1,118 @@
+use std::fmt;
+
+use crate::codec::{compress_with, decompress_to_vec, CompressOptions};
+use crate::error::RuffmanError;
+
+/// A round trip that did not reproduce its input
+#[derive(Debug)]
+pub struct StressFailure {
+    /// Zero-based index of the failing iteration
+    pub iteration: u64,
+    /// Seed that generates the failing case first, for `stress_round_trips(1, seed)`
+    pub seed: u64,
+    pub size: usize,
+    /// Why decompression failed, or `None` if it returned different data
+    pub error: Option<RuffmanError>,
+}
+
+impl fmt::Display for StressFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "iteration {} (seed {}, {} bytes): ", self.iteration, self.seed, self.size)?;
+        match &self.error {
+            Some(e) => write!(f, "{}", e),
+            None => write!(f, "decompressed data differs from the input"),
+        }
+    }
+}
+
+/// Round-trip `iterations` random buffers of varied sizes, distributions and options,
+/// stopping at the first one that does not come back unchanged. The same seed always
+/// produces the same cases.
+pub fn stress_round_trips(iterations: u64, seed: u64) -> Result<(), StressFailure> {
+    let mut case_seed = seed;
+    for iteration in 0..iterations {
+        let mut rng = SplitMix64(case_seed);
+        let (data, options) = random_case(&mut rng);
+
+        let compressed = compress_with(&data, &options);
+        let error = match decompress_to_vec(&compressed) {
+            Ok(decompressed) if decompressed == data => None,
+            Ok(_) => Some(None),
+            Err(e) => Some(Some(e)),
+        };
+        if let Some(error) = error {
+            return Err(StressFailure {
+                iteration,
+                seed: case_seed,
+                size: data.len(),
+                error,
+            });
+        }
+
+        // Each case's seed comes from the previous one, so any case can be rerun on its own
+        case_seed = SplitMix64(case_seed).next();
+    }
+    Ok(())
+}
+
+// Small, seedable generator; quality is plenty for picking test cases
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform enough in 0..bound for small bounds
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+// A buffer and the native-format options to compress it with
+fn random_case(rng: &mut SplitMix64) -> (Vec<u8>, CompressOptions) {
+    let max_size = [16, 256, 4096, 65536][rng.below(4) as usize];
+    let size = rng.below(max_size + 1) as usize;
+
+    let mut data = Vec::with_capacity(size);
+    match rng.below(4) {
+        // Uniform bytes
+        0 => data.extend((0..size).map(|_| rng.next() as u8)),
+        // A few symbols, each rarer than the last
+        1 => {
+            let symbols: Vec<u8> = (0..=rng.below(8)).map(|_| rng.next() as u8).collect();
+            while data.len() < size {
+                let mut index = 0;
+                while index + 1 < symbols.len() && rng.below(2) == 0 {
+                    index += 1;
+                }
+                data.push(symbols[index]);
+            }
+        }
+        // Runs of repeated bytes
+        2 => {
+            while data.len() < size {
+                let byte = rng.next() as u8;
+                let run = (rng.below(64) as usize + 1).min(size - data.len());
+                data.extend(std::iter::repeat_n(byte, run));
+            }
+        }
+        // Comma-separated lines of text
+        _ => {
+            const TEXT: &[u8] = b"abcdefghij0123456789,,,\n";
+            data.extend((0..size).map(|_| TEXT[rng.below(TEXT.len() as u64) as usize]));
+        }
+    }
+
+    let options = CompressOptions {
+        columns: (rng.below(4) == 0).then_some(b','),
+        nibbles: rng.below(4) == 0,
+        body_checksum: rng.below(2) == 0,
+        ..CompressOptions::default()
+    };
+    (data, options)
+}
@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::io;
+
+use crate::bits::BitWriter;
+use crate::error::RuffmanError;
+use crate::huffman::{build_codes, build_frequency_table, Symbol, COUNTED};
+use crate::raw::decode_with_table;
+
+/// Huffman codes over symbols of any width, such as 16-bit samples, built from the
+/// frequencies in some data. It codes bare bit streams without a header: the file format
+/// stores bytes, and `compress` is the `u8` case with a header around it.
+#[derive(Debug, Clone)]
+pub struct SymbolCodec<S: Symbol> {
+    codes: HashMap<S, String>,
+    decoding: HashMap<String, S>,
+    longest_code: usize,
+}
+
+impl<S: Symbol> SymbolCodec<S> {
+    /// Build the codes for the symbols in `data`, breaking ties by symbol as `compress`
+    /// does. Empty data gives `RuffmanError::EmptyInput`.
+    pub fn new(data: &[S]) -> Result<Self, RuffmanError> {
+        if data.is_empty() {
+            return Err(RuffmanError::EmptyInput);
+        }
+        let codes = build_codes(build_frequency_table(data)).expect(COUNTED);
+        let decoding = codes.iter().map(|(&symbol, code)| (code.clone(), symbol)).collect();
+        let longest_code = codes.values().map(String::len).max().unwrap_or(0);
+        Ok(SymbolCodec { codes, decoding, longest_code })
+    }
+
+    /// The code of `symbol` as '0' and '1' characters, or `None` if it did not occur
+    pub fn code(&self, symbol: S) -> Option<&str> {
+        self.codes.get(&symbol).map(String::as_str)
+    }
+
+    /// Every symbol with a code and its code
+    pub fn codes(&self) -> &HashMap<S, String> {
+        &self.codes
+    }
+
+    /// Code `data`, most significant bit first, padding the last byte with zero bits.
+    /// A symbol without a code fails with an `InvalidInput` error.
+    pub fn encode(&self, data: &[S]) -> Result<Vec<u8>, RuffmanError> {
+        let mut bits = BitWriter::new(Vec::new());
+        for symbol in data {
+            let code = self.codes.get(symbol).ok_or_else(|| {
+                RuffmanError::Io(io::Error::new(io::ErrorKind::InvalidInput, format!("{:?} has no code", symbol)))
+            })?;
+            bits.write_code(code)?;
+        }
+        Ok(bits.finish()?)
+    }
+
+    /// Decode `symbol_count` symbols from a body written by `encode`
+    pub fn decode(&self, body: &[u8], symbol_count: usize) -> Result<Vec<S>, RuffmanError> {
+        decode_with_table(body, &self.decoding, self.longest_code, Some(symbol_count))
+    }
+}
@@ -0,0 +1,37 @@
+use std::io::{self, Write};
+
+/// A writer that passes everything written to it on to each of its sinks, so output
+/// produced once can be stored and sent at the same time. `Tee<Box<dyn Write>>` or
+/// `Tee<&mut dyn Write>` mixes sinks of different types.
+#[derive(Debug)]
+pub struct Tee<W> {
+    sinks: Vec<W>,
+}
+
+impl<W: Write> Tee<W> {
+    pub fn new(sinks: Vec<W>) -> Self {
+        Tee { sinks }
+    }
+
+    /// Give the sinks back
+    pub fn into_inner(self) -> Vec<W> {
+        self.sinks
+    }
+}
+
+impl<W: Write> Write for Tee<W> {
+    // Every sink gets the whole buffer, so a short write to one cannot leave them out of step
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for sink in &mut self.sinks {
+            sink.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for sink in &mut self.sinks {
+            sink.flush()?;
+        }
+        Ok(())
+    }
+}
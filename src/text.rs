@@ -0,0 +1,17 @@
+use std::borrow::Cow;
+
+// Replace every CRLF with LF. Lossy: the original line endings cannot be recovered.
+pub(crate) fn normalize_line_endings(data: &[u8]) -> Cow<'_, [u8]> {
+    if !data.windows(2).any(|pair| pair == b"\r\n") {
+        return Cow::Borrowed(data);
+    }
+    let mut normalized = Vec::with_capacity(data.len());
+    let mut bytes = data.iter().peekable();
+    while let Some(&byte) = bytes.next() {
+        if byte == b'\r' && bytes.peek() == Some(&&b'\n') {
+            continue;
+        }
+        normalized.push(byte);
+    }
+    Cow::Owned(normalized)
+}
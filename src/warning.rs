@@ -0,0 +1,32 @@
+use std::fmt;
+
+/// Non-fatal conditions noticed while compressing, left to the caller to report
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// The input already looks like ruffman or gzip output
+    AlreadyCompressed,
+    /// The output is larger than the input
+    Expanded { original_size: usize, compressed_size: usize },
+    /// The input has bytes the code lengths given for reuse have no code for, so fresh
+    /// ones were built
+    CodeLengthsNotReused,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::AlreadyCompressed => write!(f, "input appears to be compressed already"),
+            Warning::CodeLengthsNotReused => {
+                write!(f, "input has bytes the reused code table cannot code; built a new one")
+            }
+            Warning::Expanded {
+                original_size,
+                compressed_size,
+            } => write!(
+                f,
+                "output is larger than the input ({} bytes -> {} bytes)",
+                original_size, compressed_size
+            ),
+        }
+    }
+}
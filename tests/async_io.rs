@@ -0,0 +1,51 @@
+//! The tokio entry points give the same files as the blocking ones
+#![cfg(feature = "tokio")]
+
+use std::fs;
+use std::future::Future;
+
+use ruffman::{
+    compress_file_async, compress_file_with, decompress_file_async, CompressOptions, DecompressOptions, RuffmanError,
+};
+
+// A current-thread runtime is all these need: the coding itself runs on blocking tasks
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(future)
+}
+
+#[test]
+fn async_files_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.txt");
+    let (compressed, blocking, output) =
+        (dir.path().join("async.huff"), dir.path().join("blocking.huff"), dir.path().join("output.txt"));
+    let data = include_bytes!("../README.md");
+    fs::write(&input, data).unwrap();
+    let options = CompressOptions { body_checksum: true, ..CompressOptions::default() };
+
+    let report = block_on(async {
+        compress_file_async(&input, &compressed, &options).await?;
+        decompress_file_async(&compressed, &output, &DecompressOptions::default()).await
+    })
+    .unwrap();
+    assert_eq!(report.bytes_written, data.len());
+    assert_eq!(fs::read(&output).unwrap(), data);
+
+    compress_file_with(&input, &blocking, &options).unwrap();
+    assert_eq!(fs::read(&compressed).unwrap(), fs::read(&blocking).unwrap());
+}
+
+#[test]
+fn async_errors_come_back_as_results() {
+    let dir = tempfile::tempdir().unwrap();
+    let damaged = dir.path().join("damaged.huff");
+    fs::write(&damaged, b"not a ruffman file").unwrap();
+    let result = block_on(decompress_file_async(&damaged, &dir.path().join("output"), &DecompressOptions::default()));
+    assert!(result.is_err());
+    let missing = block_on(compress_file_async(
+        &dir.path().join("missing"),
+        &dir.path().join("missing.huff"),
+        &CompressOptions::default(),
+    ));
+    assert!(matches!(missing, Err(RuffmanError::Io(_))));
+}
@@ -0,0 +1,41 @@
+use std::fs;
+
+use ruffman::benchmark::{benchmark_corpus, render_csv, render_table, suggest_block_size, BLOCK_SIZE_CANDIDATES};
+
+#[test]
+fn corpus_report_has_aggregate_row() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "abracadabra ".repeat(100)).unwrap();
+    fs::create_dir(dir.path().join("nested")).unwrap();
+    fs::write(dir.path().join("nested").join("b.txt"), "mississippi ".repeat(50)).unwrap();
+
+    let report = benchmark_corpus(dir.path()).unwrap();
+    assert_eq!(report.entries.len(), 2);
+    assert!(report.skipped.is_empty());
+    assert_eq!(report.total.stats.original_size, 1200 + 600);
+    let compressed: usize = report.entries.iter().map(|entry| entry.stats.compressed_size).sum();
+    assert_eq!(report.total.stats.compressed_size, compressed);
+
+    let table = render_table(&report);
+    assert!(table.lines().last().unwrap().starts_with("TOTAL"));
+    let csv = render_csv(&report);
+    assert!(csv.lines().last().unwrap().starts_with("TOTAL,1800,"));
+}
+
+#[test]
+fn the_suggested_block_size_is_one_of_the_candidates() {
+    let dir = tempfile::tempdir().unwrap();
+    let sample_file = dir.path().join("sample");
+    // Text, then bytes with nothing in common with it, so block sizes make a difference
+    let mut sample = include_bytes!("../README.md").to_vec();
+    sample.extend((0..20_000u32).map(|i| (i * 7 % 64) as u8 + 128));
+    fs::write(&sample_file, &sample).unwrap();
+    let sample = fs::read(&sample_file).unwrap();
+
+    for candidates in [&[1 << 10, 4 << 10, 16 << 10][..], &BLOCK_SIZE_CANDIDATES[..]] {
+        let suggestion = suggest_block_size(&sample, candidates);
+        assert!(candidates.contains(&suggestion.best), "{} is not a candidate", suggestion.best);
+        let tried: Vec<usize> = suggestion.trials.iter().map(|trial| trial.block_size).collect();
+        assert_eq!(tried, candidates);
+    }
+}
@@ -0,0 +1,72 @@
+//! `BitWriter` and `BitReader` give back exactly the bits they were given, in either bit order
+
+use ruffman::{BitOrder, BitReader, BitWriter};
+
+// A reproducible run of values, each with a length from 1 to 32 bits
+fn fields(seed: u64, count: usize) -> Vec<(u32, u8)> {
+    let mut state = seed;
+    (0..count)
+        .map(|_| {
+            state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            let len = (state >> 59) as u8 % 32 + 1;
+            let value = (state >> 16) as u32 & (u32::MAX >> (32 - len));
+            (value, len)
+        })
+        .collect()
+}
+
+#[test]
+fn fields_of_every_length_round_trip_in_both_orders() {
+    for order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+        for seed in 0..20 {
+            let mut fields = fields(seed, 200);
+            // Every length at least once, whatever the generator gives
+            fields.extend((1..=32).map(|len| ((u32::MAX >> (32 - len)) ^ 1, len)));
+            let mut writer = BitWriter::with_bit_order(Vec::new(), order);
+            for &(value, len) in &fields {
+                writer.write_bits(value, len).unwrap();
+            }
+            let total: usize = fields.iter().map(|&(_, len)| len as usize).sum();
+            assert_eq!(writer.pending_bits() as usize, total % 8);
+            let bytes = writer.finish().unwrap();
+            assert_eq!(bytes.len(), total.div_ceil(8));
+
+            let mut reader = BitReader::with_bit_order(&bytes[..], order);
+            for &(value, len) in &fields {
+                assert_eq!(reader.read_bits(len).unwrap(), Some(value), "{:?}, {} bits", order, len);
+            }
+            // Only the zero padding of the last byte is left
+            let padding = (8 - total % 8) % 8;
+            assert_eq!(reader.read_bits(padding as u8).unwrap(), Some(0));
+            assert_eq!(reader.read_bit().unwrap(), None);
+        }
+    }
+}
+
+#[test]
+fn single_bits_round_trip_in_both_orders() {
+    let bits: Vec<bool> = fields(7, 300).iter().map(|&(value, _)| value & 1 == 1).collect();
+    for order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+        let mut writer = BitWriter::with_bit_order(Vec::new(), order);
+        for &bit in &bits {
+            writer.write_bit(bit).unwrap();
+        }
+        let bytes = writer.finish_with(0xff).unwrap();
+        let mut reader = BitReader::with_bit_order(&bytes[..], order);
+        let read: Vec<bool> = (0..bits.len()).map(|_| reader.read_bit().unwrap().unwrap()).collect();
+        assert_eq!(read, bits);
+        // `finish_with` pads with ones here
+        while let Some(bit) = reader.read_bit().unwrap() {
+            assert!(bit);
+        }
+    }
+}
+
+#[test]
+fn bit_orders_fill_bytes_from_opposite_ends() {
+    for (order, expected) in [(BitOrder::MsbFirst, 0b1100_0000), (BitOrder::LsbFirst, 0b0000_0011)] {
+        let mut writer = BitWriter::with_bit_order(Vec::new(), order);
+        writer.write_bits(0b11, 2).unwrap();
+        assert_eq!(writer.finish().unwrap(), [expected]);
+    }
+}
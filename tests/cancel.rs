@@ -0,0 +1,60 @@
+//! Cancelling a long file operation from another thread stops it with
+//! `RuffmanError::Cancelled` and leaves no output behind
+
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+use ruffman::{
+    compress_file_with, decompress_file_with, CancellationToken, CompressOptions, DecompressOptions, RuffmanError,
+};
+
+// Large enough that coding it takes far longer than the cancelling thread needs to react
+fn large_input() -> Vec<u8> {
+    include_bytes!("../README.md").repeat(60)
+}
+
+#[test]
+fn cancelling_while_the_output_is_written_removes_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let (input, output) = (dir.path().join("input.txt"), dir.path().join("output.huff"));
+    fs::write(&input, large_input()).unwrap();
+    let cancel = CancellationToken::new();
+
+    let result = thread::scope(|scope| {
+        let (canceller, written) = (cancel.clone(), &output);
+        // The output only exists once the body is being coded into it
+        scope.spawn(move || {
+            while !written.exists() && !canceller.is_cancelled() {
+                thread::yield_now();
+            }
+            canceller.cancel();
+        });
+        let options = CompressOptions { cancel: Some(cancel.clone()), ..CompressOptions::default() };
+        let result = compress_file_with(&input, &output, &options);
+        cancel.cancel();
+        result
+    });
+    assert!(matches!(result, Err(RuffmanError::Cancelled)), "{:?}", result);
+    assert!(!output.exists());
+}
+
+#[test]
+fn cancelling_while_decoding_writes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let (input, output) = (dir.path().join("input.huff"), dir.path().join("output.txt"));
+    fs::write(&input, ruffman::compress(&large_input())).unwrap();
+    let cancel = CancellationToken::new();
+
+    let result = thread::scope(|scope| {
+        let canceller = cancel.clone();
+        scope.spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            canceller.cancel();
+        });
+        let options = DecompressOptions { cancel: Some(cancel.clone()), ..DecompressOptions::default() };
+        decompress_file_with(&input, &output, &options)
+    });
+    assert!(matches!(result, Err(RuffmanError::Cancelled)), "{:?}", result);
+    assert!(!output.exists());
+}
@@ -0,0 +1,148 @@
+use ruffman::{
+    code_lengths, codes_to_lengths, compress, compress_with, compress_with_trace, decompress_to_vec, lengths_to_codes,
+    lengths_to_packed_codes, packed_codes, read_code_lengths, BitWriter, CompressOptions, TieBreak,
+};
+
+// Inputs with flat, skewed, single-symbol and full-alphabet distributions
+fn samples() -> Vec<Vec<u8>> {
+    vec![
+        b"abracadabra".to_vec(),
+        b"aaaaaaaaaaaaaaaaaaab".to_vec(),
+        b"zzzz".to_vec(),
+        (0..=255u8).collect(),
+        (0..4000u32).map(|i| (i % 7 + i % 3) as u8).collect(),
+        include_bytes!("../src/canonical.rs").to_vec(),
+    ]
+}
+
+#[test]
+fn lengths_round_trip_through_codes() {
+    for data in samples() {
+        let lengths = code_lengths(&data);
+        assert_eq!(codes_to_lengths(&lengths_to_codes(lengths)), lengths);
+    }
+}
+
+#[test]
+fn canonical_codes_round_trip_through_lengths() {
+    for data in samples() {
+        let codes = lengths_to_codes(code_lengths(&data));
+        assert_eq!(lengths_to_codes(codes_to_lengths(&codes)), codes);
+    }
+}
+
+// Equal frequencies let the tie-break policy change which byte gets which length
+#[test]
+fn round_trip_holds_for_every_tie_break() {
+    let data = b"aabbccddeeffg";
+    let mut seen = Vec::new();
+    for tie_break in [TieBreak::SymbolAsc, TieBreak::SymbolDesc, TieBreak::Insertion] {
+        let options = CompressOptions {
+            tie_break,
+            ..CompressOptions::default()
+        };
+        let lengths = read_code_lengths(&compress_with(data, &options)).unwrap();
+        let codes = lengths_to_codes(lengths);
+        assert_eq!(codes_to_lengths(&codes), lengths);
+        assert_eq!(lengths_to_codes(codes_to_lengths(&codes)), codes);
+        seen.push(lengths);
+    }
+    assert_ne!(seen[0], seen[1], "the policies should give different lengths for this input");
+}
+
+#[test]
+fn codes_are_canonical() {
+    let mut lengths = [0u8; 256];
+    lengths[b'a' as usize] = 1;
+    lengths[b'b' as usize] = 2;
+    lengths[b'c' as usize] = 3;
+    lengths[b'd' as usize] = 3;
+    let codes = lengths_to_codes(lengths);
+    assert_eq!(codes[&b'a'], "0");
+    assert_eq!(codes[&b'b'], "10");
+    assert_eq!(codes[&b'c'], "110");
+    assert_eq!(codes[&b'd'], "111");
+}
+
+#[test]
+fn packed_codes_encode_as_the_string_codes_do() {
+    for data in samples() {
+        let packed = packed_codes(&data).unwrap();
+        let mut traced = String::new();
+        let compressed = compress_with_trace(&data, |_, code| traced.push_str(code));
+
+        let mut bits = String::new();
+        let mut writer = BitWriter::new(Vec::new());
+        for &byte in &data {
+            let &(_, code, len) = packed.iter().find(|&&(symbol, _, _)| symbol == byte).unwrap();
+            bits.extend((0..len).rev().map(|shift| if (code >> shift) & 1 == 1 { '1' } else { '0' }));
+            writer.write_bits(code, len).unwrap();
+        }
+        assert_eq!(bits, traced);
+        // The body is the last part of the file
+        let body = writer.finish().unwrap();
+        assert!(compressed.ends_with(&body));
+    }
+}
+
+// One byte for every length from 1 to 39, and two of length 40, fill the code space exactly
+fn long_code_lengths() -> [u8; 256] {
+    let mut lengths = [0u8; 256];
+    for len in 1..=40u8 {
+        lengths[len as usize] = len;
+    }
+    lengths[41] = 40;
+    lengths
+}
+
+#[test]
+fn codes_too_long_for_a_u32_are_not_packed() {
+    assert!(lengths_to_packed_codes(&long_code_lengths()).is_none());
+    let mut lengths = long_code_lengths();
+    lengths[33..].fill(0);
+    lengths[33] = 32;
+    assert!(lengths_to_packed_codes(&lengths).is_some());
+}
+
+#[test]
+fn codes_too_long_for_a_u32_are_still_coded() {
+    let data: Vec<u8> = (1..=41u8).cycle().take(1000).collect();
+    let options = CompressOptions {
+        reuse_code_lengths: Some(long_code_lengths()),
+        ..CompressOptions::default()
+    };
+    let compressed = compress_with(&data, &options);
+    assert_eq!(read_code_lengths(&compressed).unwrap(), long_code_lengths());
+    assert_eq!(decompress_to_vec(&compressed).unwrap(), data);
+    assert_ne!(compressed, compress(&data));
+}
+
+// A prefix code satisfies the Kraft inequality, with equality once it has two codes or more
+#[test]
+fn code_lengths_satisfy_the_kraft_inequality() {
+    for data in samples() {
+        let lengths = code_lengths(&data);
+        let kraft: f64 = lengths.iter().filter(|&&length| length > 0).map(|&length| 0.5f64.powi(length as i32)).sum();
+        assert!(kraft <= 1.0, "Kraft sum {} for {:?}", kraft, &data[..data.len().min(20)]);
+        if lengths.iter().filter(|&&length| length > 0).count() > 1 {
+            assert_eq!(kraft, 1.0);
+        }
+    }
+}
+
+// Four bytes seen once tie, and each policy picks different ones to get the longer codes,
+// at the same total size
+#[test]
+fn tie_break_policies_give_different_codes_that_each_round_trip() {
+    let data = b"abcdee";
+    let compress_by = |tie_break| compress_with(data, &CompressOptions { tie_break, ..CompressOptions::default() });
+    let (ascending, descending) = (compress_by(TieBreak::SymbolAsc), compress_by(TieBreak::SymbolDesc));
+    let (ascending_lengths, descending_lengths) =
+        (read_code_lengths(&ascending).unwrap(), read_code_lengths(&descending).unwrap());
+    assert_eq!(ascending_lengths[b'a' as usize..=b'e' as usize], [3, 3, 2, 2, 2]);
+    assert_eq!(descending_lengths[b'a' as usize..=b'e' as usize], [2, 2, 3, 3, 2]);
+    assert_eq!(ascending.len(), descending.len());
+    for compressed in [ascending, descending, compress_by(TieBreak::Insertion)] {
+        assert_eq!(decompress_to_vec(&compressed).unwrap(), data);
+    }
+}
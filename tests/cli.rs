@@ -0,0 +1,749 @@
+//! The command line as scripts use it: its exit status, and the files it reads and writes
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn ruffman(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ruffman")).args(args).output().unwrap()
+}
+
+fn path(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[test]
+fn recovering_a_truncated_file_writes_a_prefix_and_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let data = b"the quick brown fox jumps over the lazy dog\n".repeat(50);
+    let compressed = ruffman::compress(&data);
+    let input = dir.path().join("input.huff");
+    fs::write(&input, &compressed[..compressed.len() - 100]).unwrap();
+    let output = dir.path().join("output");
+
+    let run = ruffman(&["decompress", "--recover", path(&input), path(&output)]);
+    assert_eq!(run.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&run.stderr).contains("Recovered"));
+    let recovered = fs::read(&output).unwrap();
+    assert!(!recovered.is_empty() && recovered.len() < data.len());
+    assert_eq!(recovered, data[..recovered.len()]);
+}
+
+#[test]
+fn decompressing_a_damaged_file_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.huff");
+    fs::write(&input, b"not a ruffman file").unwrap();
+    let run = ruffman(&["decompress", path(&input), path(&dir.path().join("output"))]);
+    assert_eq!(run.status.code(), Some(1));
+}
+
+#[test]
+fn decompressing_an_intact_file_succeeds() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.huff");
+    fs::write(&input, ruffman::compress(b"hello, hello")).unwrap();
+    let output = dir.path().join("output");
+    let run = ruffman(&["decompress", path(&input), path(&output)]);
+    assert!(run.status.success());
+    assert_eq!(fs::read(&output).unwrap(), b"hello, hello");
+}
+
+#[test]
+fn failed_compression_exits_with_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input");
+    fs::write(&input, b"abc").unwrap();
+    let output = dir.path().join("output.huff");
+    // Three distinct bytes can never reach a tenth of their size with a header
+    let run = ruffman(&["compress", "--min-ratio", "0.1", path(&input), path(&output)]);
+    assert_eq!(run.status.code(), Some(1));
+    assert!(!output.exists());
+}
+
+#[test]
+fn verified_compression_succeeds() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input");
+    fs::write(&input, b"abracadabra".repeat(10)).unwrap();
+    let output = dir.path().join("output.huff");
+    let run = ruffman(&["compress", "--verify-after", path(&input), path(&output)]);
+    assert!(run.status.success());
+    assert_eq!(ruffman::decompress_to_vec(&fs::read(&output).unwrap()).unwrap(), b"abracadabra".repeat(10));
+}
+
+#[test]
+fn input_list_compresses_every_listed_file_into_out_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut list = String::from("# inputs\n\n");
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        let input = dir.path().join(name);
+        fs::write(&input, name.repeat(20)).unwrap();
+        list.push_str(path(&input));
+        list.push('\n');
+    }
+    let manifest = dir.path().join("files.txt");
+    fs::write(&manifest, list).unwrap();
+    let out_dir = dir.path().join("out");
+
+    let run = ruffman(&["compress", "--quiet", "--input-list", path(&manifest), "--out-dir", path(&out_dir)]);
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        let compressed = fs::read(out_dir.join(format!("{}.huff", name))).unwrap();
+        assert_eq!(ruffman::decompress_to_vec(&compressed).unwrap(), name.repeat(20).as_bytes());
+    }
+}
+
+// The same rule as for a single file: any failure fails the command
+#[test]
+fn input_list_with_a_failure_exits_with_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("a.txt");
+    fs::write(&input, "aaaa").unwrap();
+    let manifest = dir.path().join("files.txt");
+    fs::write(&manifest, format!("{}\n{}\n", path(&dir.path().join("missing")), path(&input))).unwrap();
+    let out_dir = dir.path().join("out");
+
+    let run = ruffman(&["compress", "--quiet", "--input-list", path(&manifest), "--out-dir", path(&out_dir)]);
+    assert_eq!(run.status.code(), Some(1));
+    assert!(out_dir.join("a.txt.huff").exists());
+}
+
+// Workers finish in any order, but the reports follow the list, and a bad entry (here
+// stdin with no output to go to) fails only itself
+#[cfg(feature = "rayon")]
+#[test]
+fn parallel_compression_decodes_every_file_and_reports_in_input_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut entries = Vec::new();
+    for i in 0..8 {
+        let input = dir.path().join(format!("{}.txt", i));
+        match i {
+            2 | 5 => {}
+            6 => {
+                entries.push("-".to_string());
+                continue;
+            }
+            // Bigger files first, so later ones tend to finish earlier
+            _ => fs::write(&input, format!("file {} of eight\n", i).repeat(4000 / (i + 1))).unwrap(),
+        }
+        entries.push(path(&input).to_string());
+    }
+    let manifest = dir.path().join("files.txt");
+    fs::write(&manifest, entries.join("\n")).unwrap();
+
+    let run = ruffman(&["compress", "--jobs", "4", "--keep", "--input-list", path(&manifest)]);
+    assert_eq!(run.status.code(), Some(1));
+    let stderr = String::from_utf8(run.stderr).unwrap();
+    let failed: Vec<&str> = stderr
+        .lines()
+        .filter_map(|line| line.strip_prefix("Error compressing ")?.split(": ").next())
+        .collect();
+    let expected = [entries[2].as_str(), entries[5].as_str(), "-"];
+    assert_eq!(failed, expected, "{}", stderr);
+    assert!(stderr.contains("3 of 8 files could not be compressed"));
+    for i in [0, 1, 3, 4, 7] {
+        let input = dir.path().join(format!("{}.txt", i));
+        let compressed = fs::read(dir.path().join(format!("{}.txt.huff", i))).unwrap();
+        assert_eq!(ruffman::decompress_to_vec(&compressed).unwrap(), fs::read(&input).unwrap());
+    }
+}
+
+fn ruffman_piped(args: &[&str], input: &Path) -> Output {
+    let stdin = fs::File::open(input).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_ruffman")).args(args).stdin(stdin).output().unwrap()
+}
+
+#[test]
+fn dictionary_pipe_round_trips_and_a_wrong_size_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("app.log");
+    let lines: String = (0..50).map(|i| format!("GET /item/{} 200\n", i)).collect();
+    fs::write(&input, &lines).unwrap();
+    let packed = dir.path().join("app.log.msg");
+
+    let run = ruffman_piped(&["pipe", "--dictionary-size", "4K"], &input);
+    assert!(run.status.success());
+    fs::write(&packed, run.stdout).unwrap();
+
+    let run = ruffman_piped(&["unpipe", "--dictionary-size", "4K"], &packed);
+    assert!(run.status.success());
+    assert_eq!(run.stdout, lines.as_bytes());
+
+    let run = ruffman_piped(&["unpipe", "--dictionary-size", "64"], &packed);
+    assert_eq!(run.status.code(), Some(1));
+}
+
+#[test]
+fn a_table_reference_needs_the_reused_file_to_decompress() {
+    let dir = tempfile::tempdir().unwrap();
+    let (v1, v2) = (dir.path().join("v1.txt"), dir.path().join("v2.txt"));
+    fs::write(&v1, "version one of the report, with some text".repeat(10)).unwrap();
+    fs::write(&v2, "version two of the report, with more text".repeat(10)).unwrap();
+    let (v1_huff, v2_huff) = (dir.path().join("v1.txt.huff"), dir.path().join("v2.txt.huff"));
+    let restored = dir.path().join("v2.out");
+
+    assert!(ruffman(&["compress", "--no-timestamp", path(&v1), path(&v1_huff)]).status.success());
+    let compress = ["compress", "--reuse-tree", path(&v1_huff), "--table-reference", path(&v2), path(&v2_huff)];
+    assert!(ruffman(&compress).status.success());
+
+    assert_eq!(ruffman(&["decompress", path(&v2_huff), path(&restored)]).status.code(), Some(1));
+    let decompress = ["decompress", "--reuse-tree", path(&v1_huff), path(&v2_huff), path(&restored)];
+    assert!(ruffman(&decompress).status.success());
+    assert_eq!(fs::read(&restored).unwrap(), fs::read(&v2).unwrap());
+}
+
+#[test]
+fn recovery_reads_a_damaged_file_with_the_table_it_refers_to() {
+    let dir = tempfile::tempdir().unwrap();
+    let (v1, v2) = (dir.path().join("v1.txt"), dir.path().join("v2.txt"));
+    fs::write(&v1, "version one of the report".repeat(10)).unwrap();
+    fs::write(&v2, "version two of the report".repeat(10)).unwrap();
+    let (v1_huff, v2_huff) = (dir.path().join("v1.txt.huff"), dir.path().join("v2.txt.huff"));
+    let restored = dir.path().join("v2.out");
+    assert!(ruffman(&["compress", "--no-timestamp", path(&v1), path(&v1_huff)]).status.success());
+    let compress = ["compress", "--reuse-tree", path(&v1_huff), "--table-reference", path(&v2), path(&v2_huff)];
+    assert!(ruffman(&compress).status.success());
+    let damaged = fs::read(&v2_huff).unwrap();
+    fs::write(&v2_huff, &damaged[..damaged.len() - 4]).unwrap();
+
+    let recover = ["decompress", "--recover", "--reuse-tree", path(&v1_huff), path(&v2_huff), path(&restored)];
+    let run = ruffman(&recover);
+    // A partial recovery still exits 1, but with what decoded rather than a missing table
+    assert_eq!(run.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&run.stderr).contains("Recovered"), "{}", String::from_utf8_lossy(&run.stderr));
+    let recovered = fs::read(&restored).unwrap();
+    assert!(!recovered.is_empty() && fs::read(&v2).unwrap().starts_with(&recovered));
+}
+
+#[test]
+fn a_raw_size_beyond_the_input_fails_cleanly() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut lengths = [0u8; 256];
+    lengths[b'a' as usize] = 1;
+    lengths[b'b' as usize] = 1;
+    let (lengths_path, input, output) = (dir.path().join("lengths.bin"), dir.path().join("in"), dir.path().join("out"));
+    fs::write(&lengths_path, lengths).unwrap();
+    fs::write(&input, [0b0101_0101]).unwrap();
+
+    let args = ["decompress", "--lengths", path(&lengths_path), "--raw-size", "99999999999999999"];
+    let run = ruffman(&[&args[..], &[path(&input), path(&output)]].concat());
+    assert_eq!(run.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&run.stderr).contains("length mismatch"));
+}
+
+// Bits a usual umask clears are left out, so the mode comes back as given
+#[cfg(unix)]
+#[test]
+fn output_permissions_apply_to_files_and_piped_input() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let compressed = dir.path().join("secret.huff");
+    fs::write(&compressed, ruffman::compress(b"top secret")).unwrap();
+    let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+    let from_file = dir.path().join("from_file");
+    let run = ruffman(&["decompress", "--output-permissions", "600", path(&compressed), path(&from_file)]);
+    assert!(run.status.success());
+    assert_eq!(mode(&from_file), 0o600);
+
+    let from_stdin = dir.path().join("from_stdin");
+    let run = ruffman_piped(&["decompress", "--output-permissions", "640", "-", path(&from_stdin)], &compressed);
+    assert!(run.status.success());
+    assert_eq!(mode(&from_stdin), 0o640);
+    assert_eq!(fs::read(&from_stdin).unwrap(), b"top secret");
+}
+
+#[cfg(unix)]
+#[test]
+fn create_output_sets_the_mode_of_new_files_only() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let created = dir.path().join("created");
+    ruffman::create_output(&created, Some(0o600)).unwrap();
+    assert_eq!(fs::metadata(&created).unwrap().permissions().mode() & 0o777, 0o600);
+
+    let existing = dir.path().join("existing");
+    fs::write(&existing, "old contents").unwrap();
+    let before = fs::metadata(&existing).unwrap().permissions().mode();
+    ruffman::create_output(&existing, Some(0o600)).unwrap();
+    assert_eq!(fs::metadata(&existing).unwrap().permissions().mode(), before);
+    assert!(fs::read(&existing).unwrap().is_empty());
+}
+
+#[test]
+fn each_operation_appends_a_log_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let (input, compressed, restored) =
+        (dir.path().join("report.txt"), dir.path().join("report.huff"), dir.path().join("report.out"));
+    let log = dir.path().join("ops.log");
+    fs::write(&input, "a line worth logging\n".repeat(40)).unwrap();
+
+    assert!(ruffman(&["compress", "--append-log", path(&log), path(&input), path(&compressed)]).status.success());
+    assert!(ruffman(&["decompress", "--append-log", path(&log), path(&compressed), path(&restored)]).status.success());
+
+    let log = fs::read_to_string(&log).unwrap();
+    let lines: Vec<Vec<&str>> = log.lines().map(|line| line.split('\t').collect()).collect();
+    assert_eq!(lines.len(), 2, "{}", log);
+    let compressed_size = fs::metadata(&compressed).unwrap().len().to_string();
+    let expected = [
+        ["compress", path(&input), path(&compressed), "840", &compressed_size, "ok"],
+        ["decompress", path(&compressed), path(&restored), &compressed_size, "840", "ok"],
+    ];
+    for (fields, expected) in lines.iter().zip(expected) {
+        assert!(fields[0].parse::<u64>().unwrap() > 1_600_000_000, "timestamp {}", fields[0]);
+        assert_eq!(fields[1..], expected);
+    }
+}
+
+fn ruffman_with_env(args: &[&str], env: &[(&str, &str)]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ruffman"));
+    command.env_remove("RUFFMAN_FORCE").env_remove("RUFFMAN_QUIET");
+    command.envs(env.iter().copied()).args(args).output().unwrap()
+}
+
+#[test]
+fn environment_defaults_give_way_to_flags() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("notes.txt");
+    let target = dir.path().join("notes.txt.huff");
+    let write_input = || fs::write(&input, "notes to compress in place\n".repeat(20)).unwrap();
+    let stale = || fs::write(&target, b"stale").unwrap();
+
+    // An existing result is only replaced when forced
+    write_input();
+    stale();
+    assert_eq!(ruffman_with_env(&["compress", path(&input)], &[]).status.code(), Some(1));
+    assert_eq!(fs::read(&target).unwrap(), b"stale");
+    assert!(ruffman_with_env(&["compress", path(&input)], &[("RUFFMAN_FORCE", "1")]).status.success());
+    assert!(fs::read(&target).unwrap().starts_with(b"RUFF"));
+    write_input();
+    stale();
+    let run = ruffman_with_env(&["compress", "--no-force", path(&input)], &[("RUFFMAN_FORCE", "1")]);
+    assert_eq!(run.status.code(), Some(1));
+    assert_eq!(fs::read(&target).unwrap(), b"stale");
+    assert_eq!(ruffman_with_env(&["compress", path(&input)], &[("RUFFMAN_FORCE", "maybe")]).status.code(), Some(1));
+
+    // Compressing a compressed file warns unless quiet
+    let compressed = dir.path().join("already.huff");
+    fs::write(&compressed, ruffman::compress(b"compressed once")).unwrap();
+    let twice = dir.path().join("twice.huff");
+    let compress_twice = ["compress", path(&compressed), path(&twice)];
+    let stderr = |run: Output| String::from_utf8_lossy(&run.stderr).into_owned();
+    assert!(stderr(ruffman_with_env(&compress_twice, &[])).contains("Warning"));
+    assert_eq!(stderr(ruffman_with_env(&compress_twice, &[("RUFFMAN_QUIET", "1")])), "");
+    let loud = ["compress", "--no-quiet", path(&compressed), path(&twice)];
+    assert!(stderr(ruffman_with_env(&loud, &[("RUFFMAN_QUIET", "1")])).contains("Warning"));
+}
+
+// `pipe` feeds `unpipe` directly, as in a shell pipeline, in blocks small enough that the
+// stream has many members
+#[test]
+fn pipe_output_unpipes_through_a_pipeline() {
+    use std::process::Stdio;
+
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("docs.tar");
+    let data = [&include_bytes!("../README.md")[..], include_bytes!("../src/main.rs")].concat();
+    fs::write(&input, &data).unwrap();
+
+    let mut pipe = Command::new(env!("CARGO_BIN_EXE_ruffman"))
+        .args(["pipe", "--block-size", "4K"])
+        .stdin(fs::File::open(&input).unwrap())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let unpipe = Command::new(env!("CARGO_BIN_EXE_ruffman"))
+        .arg("unpipe")
+        .stdin(pipe.stdout.take().unwrap())
+        .output()
+        .unwrap();
+    assert!(pipe.wait().unwrap().success());
+    assert!(unpipe.status.success(), "{}", String::from_utf8_lossy(&unpipe.stderr));
+    assert_eq!(unpipe.stdout, data);
+}
+
+// `decompress a.huff - | compress - b.huff`: the decoded data is far more than a pipe holds,
+// so the decoder has to wait on the compressor reading its writes
+#[test]
+fn decompressing_to_stdout_feeds_another_ruffman() {
+    use std::process::Stdio;
+
+    let dir = tempfile::tempdir().unwrap();
+    let (first, second) = (dir.path().join("a.huff"), dir.path().join("b.huff"));
+    let data = include_bytes!("../README.md").repeat(6);
+    fs::write(&first, ruffman::compress(&data)).unwrap();
+
+    let mut decompress = Command::new(env!("CARGO_BIN_EXE_ruffman"))
+        .args(["decompress", path(&first), "-"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let compress = Command::new(env!("CARGO_BIN_EXE_ruffman"))
+        .args(["compress", "--nibble", "-", path(&second)])
+        .stdin(decompress.stdout.take().unwrap())
+        .output()
+        .unwrap();
+    assert!(decompress.wait().unwrap().success());
+    assert!(compress.status.success(), "{}", String::from_utf8_lossy(&compress.stderr));
+    assert_eq!(ruffman::decompress_to_vec(&fs::read(&second).unwrap()).unwrap(), data);
+}
+
+#[test]
+fn a_stdin_size_hint_does_not_change_the_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.txt");
+    fs::write(&input, include_bytes!("../README.md")).unwrap();
+    let compress = |name: &str, hint: &[&str]| {
+        let output = dir.path().join(name);
+        let args = [&["compress", "--no-timestamp"], hint, &["-", path(&output)]].concat();
+        assert!(ruffman_piped(&args, &input).status.success());
+        fs::read(output).unwrap()
+    };
+    let unhinted = compress("unhinted.huff", &[]);
+    assert_eq!(ruffman::decompress_to_vec(&unhinted).unwrap(), include_bytes!("../README.md"));
+    for hint in ["1", "4K", "100M"] {
+        assert_eq!(compress("hinted.huff", &["--stdin-size-hint", hint]), unhinted, "hint {}", hint);
+    }
+}
+
+#[test]
+fn selfcheck_passes() {
+    let run = ruffman(&["--selfcheck"]);
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    assert!(String::from_utf8_lossy(&run.stdout).starts_with("selfcheck passed"));
+}
+
+#[test]
+fn list_shows_every_member_of_an_indexed_stream() {
+    let dir = tempfile::tempdir().unwrap();
+    let (input, stream) = (dir.path().join("input.txt"), dir.path().join("stream.huff"));
+    fs::write(&input, "0123456789abcdef".repeat(220)).unwrap();
+    let run = ruffman_piped(&["pipe", "--index", "--block-size", "1000"], &input);
+    assert!(run.status.success());
+    fs::write(&stream, &run.stdout).unwrap();
+
+    let run = ruffman(&["decompress", "--list", path(&stream)]);
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    let listing = String::from_utf8(run.stdout).unwrap();
+    let rows: Vec<Vec<&str>> = listing.lines().skip(1).map(|line| line.split_whitespace().collect()).collect();
+    assert_eq!(rows.len(), 5, "{}", listing);
+    for (number, (row, original)) in rows.iter().zip(["1000", "1000", "1000", "520"]).enumerate() {
+        assert_eq!((row[0], row[2]), ((number + 1).to_string().as_str(), original), "{}", listing);
+    }
+    let compressed: u64 = rows[..4].iter().map(|row| row[3].parse::<u64>().unwrap()).sum();
+    assert_eq!(rows[4], ["total", "3520", &compressed.to_string()]);
+
+    let plain = dir.path().join("plain.huff");
+    fs::write(&plain, ruffman::compress(b"no index here")).unwrap();
+    assert_eq!(ruffman(&["decompress", "--list", path(&plain)]).status.code(), Some(1));
+}
+
+#[test]
+fn compare_trees_measures_how_far_apart_two_files_are() {
+    let dir = tempfile::tempdir().unwrap();
+    let (english, copy, similar, binary) =
+        (dir.path().join("a.txt"), dir.path().join("b.txt"), dir.path().join("c.txt"), dir.path().join("d.bin"));
+    let text = include_str!("../README.md");
+    fs::write(&english, text).unwrap();
+    fs::write(&copy, text).unwrap();
+    fs::write(&similar, text.to_uppercase()).unwrap();
+    fs::write(&binary, (0..=255u8).cycle().take(4096).collect::<Vec<u8>>()).unwrap();
+    let distance = |a: &Path, b: &Path| {
+        let run = ruffman(&["compare-trees", path(a), path(b)]);
+        assert!(run.status.success());
+        let stdout = String::from_utf8(run.stdout).unwrap();
+        stdout.lines().find_map(|line| line.strip_prefix("distance: ")).unwrap().parse::<f64>().unwrap()
+    };
+
+    assert_eq!(distance(&english, &copy), 0.0);
+    let (near, far) = (distance(&english, &similar), distance(&english, &binary));
+    assert!(0.0 < near && near < far, "uppercase {}, binary {}", near, far);
+    assert_eq!(distance(&binary, &english), far);
+}
+
+#[test]
+fn a_directory_as_the_output_receives_the_result() {
+    let dir = tempfile::tempdir().unwrap();
+    let (input, out_dir, back_dir) = (dir.path().join("report.txt"), dir.path().join("out"), dir.path().join("back"));
+    fs::create_dir(&out_dir).unwrap();
+    fs::create_dir(&back_dir).unwrap();
+    fs::write(&input, "quarterly figures\n".repeat(30)).unwrap();
+
+    assert!(ruffman(&["compress", path(&input), path(&out_dir)]).status.success());
+    let compressed = out_dir.join("report.txt.huff");
+    assert!(compressed.is_file());
+    assert!(input.exists());
+
+    assert!(ruffman(&["decompress", path(&compressed), path(&back_dir)]).status.success());
+    assert_eq!(fs::read(back_dir.join("report.txt")).unwrap(), fs::read(&input).unwrap());
+}
+
+#[test]
+fn the_sorted_symbol_dump_is_ordered_and_the_same_every_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.huff");
+    // Several bytes share a count, so the order of ties shows
+    fs::write(&input, ruffman::compress(b"aaaabbbbccddeeffgh".repeat(20).as_slice())).unwrap();
+    // (byte, count, length) of each row under the header
+    let dump = |order: &str| {
+        let run = ruffman(&["inspect", "--sort-symbols", order, path(&input)]);
+        assert!(run.status.success());
+        let stdout = String::from_utf8(run.stdout).unwrap();
+        let table = stdout.split_once("\n\n").unwrap().1.to_string();
+        let rows: Vec<(u8, usize, usize)> = table
+            .lines()
+            .skip(1)
+            .map(|row| {
+                let fields: Vec<&str> = row.split_whitespace().collect();
+                (fields[0].parse().unwrap(), fields[1].parse().unwrap(), fields[2].parse().unwrap())
+            })
+            .collect();
+        (table, rows)
+    };
+
+    for order in ["symbol", "frequency", "length"] {
+        let (table, rows) = dump(order);
+        assert_eq!(rows.len(), 8);
+        assert_eq!(dump(order).0, table, "{} differs between runs", order);
+        for pair in rows.windows(2) {
+            let ((byte, count, length), (next_byte, next_count, next_length)) = (pair[0], pair[1]);
+            let in_order = match order {
+                "symbol" => byte < next_byte,
+                "frequency" => count > next_count || (count == next_count && byte < next_byte),
+                _ => length < next_length || (length == next_length && byte < next_byte),
+            };
+            assert!(in_order, "{} sort: {:?} before {:?}", order, pair[0], pair[1]);
+        }
+    }
+}
+
+#[test]
+fn json_reports_are_valid_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let (input, output) = (dir.path().join("notes.txt"), dir.path().join("notes.txt.huff"));
+    let text = "notes to be measured, with a \"quote\"\n".repeat(40);
+    fs::write(&input, &text).unwrap();
+
+    let run = ruffman(&["compress", "--stats", "--report-format", "json", path(&input), path(&output)]);
+    assert!(run.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&run.stdout).unwrap();
+    assert_eq!(report["command"], "compress");
+    assert_eq!(report["input"], path(&input));
+    assert_eq!(report["original_size"], text.len());
+    assert_eq!(report["compressed_size"], fs::metadata(&output).unwrap().len());
+    assert!(report["ratio"].as_f64().unwrap() < 1.0);
+
+    let run = ruffman(&["inspect", "--report-format", "json", "--sort-symbols", "frequency", path(&output)]);
+    assert!(run.status.success());
+    let inspection: serde_json::Value = serde_json::from_slice(&run.stdout).unwrap();
+    assert_eq!(inspection["coded_bytes"], text.len());
+    assert_eq!(inspection["comment"], serde_json::Value::Null);
+    let symbols = inspection["symbols"].as_array().unwrap();
+    assert_eq!(symbols.len(), inspection["distinct_symbols"].as_u64().unwrap() as usize);
+    assert_eq!(symbols[0]["byte"], b' ');
+}
+
+// The stats come from the ruffman header, so a gzip result is refused before any work
+#[cfg(feature = "gzip")]
+#[test]
+fn stats_are_refused_for_gzip_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let (input, output) = (dir.path().join("input"), dir.path().join("input.gz"));
+    fs::write(&input, "gzip me").unwrap();
+    let run = ruffman(&["compress", "--format", "gzip", "--stats", path(&input), path(&output)]);
+    assert_eq!(run.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&run.stderr).contains("--format gzip"));
+    assert!(!output.exists());
+}
+
+#[test]
+fn compression_time_is_recorded_unless_turned_off() {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.txt");
+    fs::write(&input, "stamped or not\n".repeat(10)).unwrap();
+    let now = || SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let created = |output: &Path| {
+        let run = ruffman(&["inspect", path(output)]);
+        assert!(run.status.success());
+        let stdout = String::from_utf8(run.stdout).unwrap();
+        let line = stdout.lines().find_map(|line| line.strip_prefix("created:"));
+        let listed = line.map(|line| line.split_whitespace().next().unwrap().parse::<u64>().unwrap());
+        assert_eq!(listed, ruffman::inspect(&fs::read(output).unwrap()).unwrap().created);
+        listed
+    };
+
+    let (stamped, unstamped) = (dir.path().join("stamped.huff"), dir.path().join("unstamped.huff"));
+    let before = now();
+    assert!(ruffman(&["compress", path(&input), path(&stamped)]).status.success());
+    let after = now();
+    let stamp = created(&stamped).unwrap();
+    assert!(before <= stamp && stamp <= after, "{} not in {}..={}", stamp, before, after);
+
+    assert!(ruffman(&["compress", "--no-timestamp", path(&input), path(&unstamped)]).status.success());
+    assert_eq!(created(&unstamped), None);
+}
+
+// Files that are not ruffman files are listed but pass; a damaged one fails the scan
+#[test]
+fn scan_lists_each_file_and_fails_on_corrupt_ones() {
+    let dir = tempfile::tempdir().unwrap();
+    let compressed = ruffman::compress(b"an archive in a bucket");
+    fs::write(dir.path().join("good.huff"), &compressed).unwrap();
+    fs::write(dir.path().join("readme.txt"), "plain text").unwrap();
+
+    let run = ruffman(&["scan", path(dir.path())]);
+    assert!(run.status.success());
+    let stdout = String::from_utf8(run.stdout).unwrap();
+    assert!(stdout.contains(&format!("valid    {}", path(&dir.path().join("good.huff")))));
+    assert!(stdout.contains(&format!("invalid  {}", path(&dir.path().join("readme.txt")))));
+    assert!(stdout.ends_with("1 valid, 1 invalid, 0 corrupt\n"));
+
+    fs::write(dir.path().join("cut.huff"), &compressed[..12]).unwrap();
+    let run = ruffman(&["scan", path(dir.path())]);
+    assert_eq!(run.status.code(), Some(1));
+    let stdout = String::from_utf8(run.stdout).unwrap();
+    assert!(stdout.contains(&format!("corrupt  {}: ", path(&dir.path().join("cut.huff")))));
+    assert!(stdout.ends_with("1 valid, 1 invalid, 1 corrupt\n"));
+}
+
+// Fibonacci counts are the worst case for Huffman: each symbol is only just less frequent
+// than the two after it together, so 26 symbols make a tree 25 levels deep
+#[test]
+fn a_degenerate_distribution_gets_the_code_length_advisory() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("fibonacci.huff");
+    let (mut counts, mut next) = (vec![1usize, 1], 2);
+    while counts.len() < 26 {
+        counts.push(next);
+        next += counts[counts.len() - 2];
+    }
+    let data: Vec<u8> = counts.iter().zip(b'a'..).flat_map(|(&count, byte)| vec![byte; count]).collect();
+    fs::write(&input, ruffman::compress(&data)).unwrap();
+
+    let inspection = ruffman::inspect(&fs::read(&input).unwrap()).unwrap();
+    assert_eq!(inspection.longest_code, 25);
+    let advisory = |args: &[&str]| {
+        let run = ruffman(&[&["inspect"], args, &[path(&input)]].concat());
+        assert!(run.status.success());
+        String::from_utf8(run.stdout).unwrap().lines().find(|line| line.starts_with("advisory:")).map(str::to_string)
+    };
+    let shown = advisory(&[]).expect("an advisory with the default limit of 24 bits");
+    assert!(shown.contains("codes up to 25 bits, above 24"), "{}", shown);
+    assert_eq!(advisory(&["--max-code-length", "25"]), None);
+}
+
+// Checking a hash decodes the file without leaving anything behind, whatever the result
+#[cfg(feature = "blake3")]
+#[test]
+fn an_expected_hash_is_checked_without_writing_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let data = b"contents whose hash is known in advance\n".repeat(20);
+    let input = dir.path().join("known.huff");
+    fs::write(&input, ruffman::compress(&data)).unwrap();
+    let listing = || {
+        let mut names: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        names.sort();
+        names
+    };
+    let before = listing();
+
+    let right = blake3::hash(&data).to_hex();
+    let run = ruffman(&["decompress", "--expect-hash", right.as_str(), path(&input)]);
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    assert_eq!(run.stdout, format!("{}: OK\n", path(&input)).into_bytes());
+    assert_eq!(listing(), before);
+
+    let wrong = blake3::hash(b"something else").to_hex();
+    let run = ruffman(&["decompress", "--expect-hash", wrong.as_str(), path(&input)]);
+    assert_eq!(run.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&run.stderr).contains("hash mismatch"));
+    assert!(run.stdout.is_empty());
+    assert_eq!(listing(), before);
+}
+
+// A baseline that recorded a better ratio than the corpus now gets fails the run
+#[test]
+fn a_baseline_with_a_better_ratio_fails_the_benchmark() {
+    let dir = tempfile::tempdir().unwrap();
+    let corpus = dir.path().join("samples");
+    fs::create_dir(&corpus).unwrap();
+    fs::write(corpus.join("a.txt"), "abracadabra ".repeat(200)).unwrap();
+    fs::write(corpus.join("b.txt"), "mississippi ".repeat(100)).unwrap();
+    let (saved, doctored) = (dir.path().join("baseline.json"), dir.path().join("doctored.json"));
+
+    let run = ruffman(&["benchmark-corpus", path(&corpus), "--save-baseline", path(&saved)]);
+    assert!(run.status.success());
+    assert!(ruffman(&["benchmark-corpus", path(&corpus), "--baseline", path(&saved)]).status.success());
+
+    let mut baseline: serde_json::Value = serde_json::from_str(&fs::read_to_string(&saved).unwrap()).unwrap();
+    let entry = baseline["files"].as_array_mut().unwrap().iter_mut().find(|entry| entry["path"] == "b.txt").unwrap();
+    entry["ratio"] = serde_json::json!(entry["ratio"].as_f64().unwrap() / 2.0);
+    fs::write(&doctored, baseline.to_string()).unwrap();
+
+    let run = ruffman(&["benchmark-corpus", path(&corpus), "--baseline", path(&doctored)]);
+    assert_eq!(run.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&run.stderr).contains("regressed against the baseline"));
+    // Against the baseline the ratio doubled, which a tolerance of 150% allows
+    let run = ruffman(&["benchmark-corpus", path(&corpus), "--baseline", path(&doctored), "--tolerance", "1.5"]);
+    assert!(run.status.success());
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn gzip_files_transcode_to_the_native_format() {
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let (gz, output) = (dir.path().join("archive.log.gz"), dir.path().join("archive.log.huff"));
+    let data = b"2024-01-01 INFO request served\n".repeat(100);
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&data).unwrap();
+    fs::write(&gz, encoder.finish().unwrap()).unwrap();
+
+    let run = ruffman(&["transcode-from-gzip", "--stats", "--report-format", "json", path(&gz), path(&output)]);
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    assert_eq!(ruffman::decompress_to_vec(&fs::read(&output).unwrap()).unwrap(), data);
+    let report: serde_json::Value = serde_json::from_slice(&run.stdout).unwrap();
+    assert_eq!(report["command"], "transcode-from-gzip");
+    assert_eq!(report["original_size"], data.len());
+
+    // Not gzip at all, whatever the name says
+    let fake = dir.path().join("fake.gz");
+    fs::write(&fake, &data).unwrap();
+    let run = ruffman(&["transcode-from-gzip", path(&fake), path(&dir.path().join("fake.huff"))]);
+    assert_eq!(run.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&run.stderr).contains("Error transcoding"));
+}
+
+#[test]
+fn overwrite_if_newer_skips_fresh_results_and_redoes_stale_ones() {
+    use std::time::{Duration, SystemTime};
+
+    let dir = tempfile::tempdir().unwrap();
+    let (input, output) = (dir.path().join("data.txt"), dir.path().join("data.txt.huff"));
+    fs::write(&input, "data that changes now and then\n".repeat(20)).unwrap();
+    let touch = |file: &Path, time: SystemTime| fs::File::options().write(true).open(file).unwrap().set_modified(time);
+    let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+    touch(&input, an_hour_ago).unwrap();
+    // In place, where an existing result would otherwise need --force
+    let compress_if_newer = || ruffman(&["compress", "--overwrite-if-newer", "--keep", path(&input)]);
+
+    // A result newer than its input is left alone, whatever it holds
+    fs::write(&output, "placeholder").unwrap();
+    let run = compress_if_newer();
+    assert!(run.status.success());
+    assert!(String::from_utf8_lossy(&run.stderr).contains("is up to date"));
+    assert_eq!(fs::read(&output).unwrap(), b"placeholder");
+
+    // An older one is replaced
+    touch(&output, an_hour_ago - Duration::from_secs(60)).unwrap();
+    let run = compress_if_newer();
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    assert!(!String::from_utf8_lossy(&run.stderr).contains("up to date"));
+    assert_eq!(ruffman::decompress_to_vec(&fs::read(&output).unwrap()).unwrap(), fs::read(&input).unwrap());
+}
@@ -0,0 +1,338 @@
+//! What the compression entry points and options give back, beyond a round trip
+
+use ruffman::{
+    compress, compress_file_range, compress_file_with, compress_into, compress_reader_to_writer, compress_to_writer,
+    compress_with, compress_with_trace, compress_with_warnings, compressed_size, decompress_file_with,
+    decompress_reader_to_writer, decompress_to_vec, read_compression_stats, read_frame, BitWriter, Codec,
+    CompressOptions, DecompressOptions, HuffmanCodec, RuffmanError, Tee, Warning, MAGIC,
+};
+
+const TEXT: &[u8] = b"she sells sea shells by the sea shore";
+
+#[test]
+fn trace_fires_once_per_byte_with_the_body_bits() {
+    let mut traced = Vec::new();
+    let mut bits = String::new();
+    let compressed = compress_with_trace(TEXT, |byte, code| {
+        traced.push(byte);
+        bits.push_str(code);
+    });
+    assert_eq!(traced, TEXT);
+    assert_eq!(compressed, compress(TEXT));
+
+    let mut body = BitWriter::new(Vec::new());
+    for bit in bits.chars() {
+        body.write_bit(bit == '1').unwrap();
+    }
+    let body = body.finish().unwrap();
+    let header_size = read_compression_stats(&compressed).unwrap().header_size;
+    assert_eq!(compressed[header_size..], body[..]);
+    assert_eq!(decompress_to_vec(&compressed).unwrap(), TEXT);
+}
+
+// Each column of this table draws on a few bytes of its own, which one table for the whole
+// file cannot tell apart
+fn csv() -> Vec<u8> {
+    let mut csv = Vec::new();
+    for i in 0..2000u32 {
+        let colour = ["red", "green", "blue"][i as usize % 3];
+        let row = format!("{},{},{}\n", i % 7, colour, "xyz".repeat(1 + i as usize % 2));
+        csv.extend_from_slice(row.as_bytes());
+    }
+    csv
+}
+
+#[test]
+fn columns_beat_one_table_on_csv() {
+    let data = csv();
+    let plain = compress(&data);
+    let columns = compress_with(&data, &CompressOptions { columns: Some(b','), ..CompressOptions::default() });
+    assert!(columns.len() < plain.len(), "columns {} bytes, plain {} bytes", columns.len(), plain.len());
+    assert_eq!(decompress_to_vec(&columns).unwrap(), data);
+}
+
+// Run by `already_compressed_input_is_a_warning_not_a_message` in a process of its own, so
+// anything the library prints can be told apart from other tests' output
+#[test]
+fn warn_about_compressed_input() {
+    if std::env::var_os("RUFFMAN_WARNING_CHILD").is_none() {
+        return;
+    }
+    let compressed = compress(TEXT);
+    let (_, warnings) = compress_with_warnings(&compressed, &CompressOptions::default());
+    assert!(warnings.contains(&Warning::AlreadyCompressed), "{:?}", warnings);
+
+    let dir = tempfile::tempdir().unwrap();
+    let (input, output) = (dir.path().join("input.huff"), dir.path().join("twice.huff"));
+    std::fs::write(&input, &compressed).unwrap();
+    let warnings = compress_file_with(&input, &output, &CompressOptions::default()).unwrap();
+    assert!(warnings.contains(&Warning::AlreadyCompressed), "{:?}", warnings);
+}
+
+#[test]
+fn already_compressed_input_is_a_warning_not_a_message() {
+    let child = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "warn_about_compressed_input", "--nocapture", "--test-threads=1"])
+        .env("RUFFMAN_WARNING_CHILD", "1")
+        .output()
+        .unwrap();
+    assert!(child.status.success(), "{}", String::from_utf8_lossy(&child.stdout));
+    assert!(String::from_utf8_lossy(&child.stdout).contains("1 passed"));
+    assert_eq!(String::from_utf8_lossy(&child.stderr), "");
+}
+
+// Bytes made of two skewed halves drawn apart: whole bytes need a table of 256 codes for
+// what 16 nibble codes describe as well
+#[test]
+fn nibbles_beat_bytes_when_the_halves_are_independent() {
+    let mut state = 12345u32;
+    let mut nibble = || {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        [0, 0, 0, 0, 1, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15][(state >> 16) as usize % 20]
+    };
+    let data: Vec<u8> = (0..2000).map(|_| (nibble() << 4) | nibble()).collect();
+    let bytes = compress(&data);
+    let nibbles = compress_with(&data, &CompressOptions { nibbles: true, ..CompressOptions::default() });
+    assert!(nibbles.len() < bytes.len(), "nibbles {} bytes, bytes {} bytes", nibbles.len(), bytes.len());
+    assert_eq!(decompress_to_vec(&nibbles).unwrap(), data);
+}
+
+#[test]
+fn buffer_sizes_do_not_change_the_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.txt");
+    let data = include_bytes!("../README.md");
+    std::fs::write(&input, data).unwrap();
+    let default = dir.path().join("default.huff");
+    compress_file_with(&input, &default, &CompressOptions::default()).unwrap();
+    let default = std::fs::read(&default).unwrap();
+
+    for io_buffer_size in [1, 7, 4096, 1 << 20] {
+        let (output, restored) = (dir.path().join("sized.huff"), dir.path().join("sized.txt"));
+        compress_file_with(&input, &output, &CompressOptions { io_buffer_size, ..CompressOptions::default() }).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), default, "io_buffer_size {}", io_buffer_size);
+        let options = DecompressOptions { io_buffer_size, ..DecompressOptions::default() };
+        decompress_file_with(&output, &restored, &options).unwrap();
+        assert_eq!(std::fs::read(&restored).unwrap(), data);
+    }
+}
+
+#[test]
+fn a_worse_ratio_than_the_threshold_writes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let (noise, text, output) = (dir.path().join("noise.bin"), dir.path().join("text.txt"), dir.path().join("out"));
+    let mut state = 99u32;
+    let random: Vec<u8> = (0..8192)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect();
+    std::fs::write(&noise, &random).unwrap();
+    std::fs::write(&text, b"a".repeat(8000)).unwrap();
+    let options = CompressOptions { min_ratio: Some(0.8), ..CompressOptions::default() };
+
+    match compress_file_with(&noise, &output, &options) {
+        Err(RuffmanError::NotWorthCompressing { ratio, threshold }) => assert!(ratio > threshold && threshold == 0.8),
+        other => panic!("expected NotWorthCompressing, got {:?}", other),
+    }
+    assert!(!output.exists());
+    assert_eq!(std::fs::read(&noise).unwrap(), random);
+
+    compress_file_with(&text, &output, &options).unwrap();
+    assert_eq!(decompress_to_vec(&std::fs::read(&output).unwrap()).unwrap(), b"a".repeat(8000));
+}
+
+// Callers that pick a codec at run time go through the trait object
+#[test]
+fn huffman_round_trips_through_the_codec_trait() {
+    let codec: &dyn Codec = &HuffmanCodec;
+    let encoded = codec.encode(TEXT);
+    assert_eq!(encoded, compress(TEXT));
+    assert_eq!(encoded[MAGIC.len()], codec.mode());
+    assert_eq!(codec.decode(&encoded).unwrap(), TEXT);
+    assert!(codec.decode(b"RUFF").is_err());
+}
+
+#[test]
+fn a_tee_gives_every_sink_the_same_output() {
+    let mut tee = Tee::new(vec![Vec::new(), Vec::new()]);
+    compress_to_writer(TEXT, &CompressOptions::default(), &mut tee).unwrap();
+    let sinks = tee.into_inner();
+    assert_eq!(sinks[0], compress(TEXT));
+    assert_eq!(sinks[1], sinks[0]);
+
+    // Sinks of different types go behind `dyn Write`
+    let (mut stored, mut sent) = (Vec::new(), std::io::Cursor::new(Vec::new()));
+    let sinks: Vec<&mut dyn std::io::Write> = vec![&mut stored, &mut sent];
+    compress_to_writer(TEXT, &CompressOptions::default(), Tee::new(sinks)).unwrap();
+    assert_eq!(stored, sent.into_inner());
+    assert_eq!(decompress_to_vec(&stored).unwrap(), TEXT);
+}
+
+// Only CRLF pairs change; a lone CR or LF is text like any other byte
+#[test]
+fn normalizing_mixed_line_endings_only_changes_crlf() {
+    let mixed = b"dos line\r\nunix line\nold mac line\rdos again\r\n\r\nend";
+    let options = CompressOptions { normalize_line_endings: true, ..CompressOptions::default() };
+    let normalized = decompress_to_vec(&compress_with(mixed, &options)).unwrap();
+    assert_eq!(normalized, b"dos line\nunix line\nold mac line\rdos again\n\nend");
+    // It is never applied unless asked for
+    assert_eq!(decompress_to_vec(&compress(mixed)).unwrap(), mixed);
+}
+
+// Every byte comes back within half a level of itself, for every number of levels
+#[test]
+fn quantization_error_is_at_most_half_a_level() {
+    let data: Vec<u8> = (0..=255).collect();
+    for levels in 2..=255u8 {
+        let options = CompressOptions { quantize: Some(levels), ..CompressOptions::default() };
+        let decoded = decompress_to_vec(&compress_with(&data, &options)).unwrap();
+        let bound = 256u32.div_ceil(levels as u32).div_ceil(2);
+        for (&original, &decoded) in data.iter().zip(&decoded) {
+            let error = original.abs_diff(decoded) as u32;
+            assert!(error <= bound, "{} levels: {} came back as {}", levels, original, decoded);
+        }
+        let distinct: std::collections::HashSet<u8> = decoded.iter().copied().collect();
+        assert_eq!(distinct.len(), levels as usize);
+        // Decoded values are already on their levels, so they survive quantizing again
+        assert_eq!(decompress_to_vec(&compress_with(&decoded, &options)).unwrap(), decoded);
+    }
+}
+
+#[test]
+fn readers_and_writers_round_trip_through_cursors() {
+    let data = TEXT.repeat(50);
+    let mut compressed = std::io::Cursor::new(Vec::new());
+    let stats = compress_reader_to_writer(std::io::Cursor::new(&data), &mut compressed).unwrap();
+    let compressed = compressed.into_inner();
+    assert_eq!(compressed, compress(&data));
+    assert_eq!((stats.original_size, stats.compressed_size), (data.len(), compressed.len()));
+
+    let mut restored = std::io::Cursor::new(Vec::new());
+    let report = decompress_reader_to_writer(&compressed[..], &mut restored, &DecompressOptions::default()).unwrap();
+    assert_eq!(report.bytes_written, data.len());
+    assert_eq!(restored.into_inner(), data);
+}
+
+#[test]
+fn compressed_size_is_exact() {
+    let uniform: Vec<u8> = (0..=255).collect();
+    for data in [&b""[..], b"x", b"zzzzzzzz", TEXT, &uniform, include_bytes!("../README.md")] {
+        assert_eq!(compressed_size(data), compress(data).len(), "{} bytes", data.len());
+    }
+}
+
+// Files are coded straight into the output, patching the header afterwards for a body
+// checksum, yet must come out exactly as the buffered `compress_with` builds them
+#[test]
+fn files_written_incrementally_match_buffered_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let (input, output) = (dir.path().join("input.txt"), dir.path().join("output.huff"));
+    let data = include_bytes!("../README.md");
+    std::fs::write(&input, data).unwrap();
+    let option_sets = [
+        CompressOptions::default(),
+        CompressOptions { body_checksum: true, ..CompressOptions::default() },
+        CompressOptions { framed: true, align: Some(512), body_checksum: true, ..CompressOptions::default() },
+        CompressOptions { order1: true, comment: Some("incremental".to_string()), ..CompressOptions::default() },
+        CompressOptions { columns: Some(b' '), ..CompressOptions::default() },
+    ];
+    for options in option_sets {
+        compress_file_with(&input, &output, &options).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), compress_with(data, &options), "{:?}", options);
+    }
+}
+
+// English is far more predictable given the letter before, and on a few copies of the
+// README that outweighs the extra tables the header carries
+#[test]
+fn order1_beats_order0_on_text() {
+    let text = include_bytes!("../README.md").repeat(3);
+    let order0 = compress(&text);
+    let order1 = compress_with(&text, &CompressOptions { order1: true, ..CompressOptions::default() });
+    assert_eq!(decompress_to_vec(&order1).unwrap(), text);
+    let stats = read_compression_stats(&order1).unwrap();
+    assert!(stats.header_size > read_compression_stats(&order0).unwrap().header_size);
+    let (ratio0, ratio1) = (order0.len() as f64 / text.len() as f64, order1.len() as f64 / text.len() as f64);
+    assert!(ratio1 < ratio0 * 0.9, "order-1 {:.3}, order-0 {:.3}", ratio1, ratio0);
+}
+
+// Padding makes the output a whole number of blocks, and it is the padded size that the
+// caller stores, so that is the size warnings give
+#[test]
+fn aligned_output_is_a_multiple_of_the_alignment_and_decodes() {
+    let dir = tempfile::tempdir().unwrap();
+    let (input, output) = (dir.path().join("input.txt"), dir.path().join("output.huff"));
+    std::fs::write(&input, TEXT).unwrap();
+    for framed in [false, true] {
+        for align in [512, 4096] {
+            let options = CompressOptions { framed, align: Some(align), ..CompressOptions::default() };
+            let warnings = compress_file_with(&input, &output, &options).unwrap();
+            let written = std::fs::read(&output).unwrap();
+            assert_eq!(written.len() % align, 0, "framed {}, align {}", framed, align);
+            assert_eq!(written, compress_with(TEXT, &options));
+            let blob = match framed {
+                true => read_frame(&mut &written[..]).unwrap().unwrap(),
+                false => written.clone(),
+            };
+            assert_eq!(decompress_to_vec(&blob).unwrap(), TEXT);
+
+            let expanded = Warning::Expanded { original_size: TEXT.len(), compressed_size: written.len() };
+            assert_eq!(warnings, [expanded]);
+            assert_eq!(compress_with_warnings(TEXT, &options).1, warnings);
+        }
+    }
+}
+
+// A blob written between records of some other container decodes from its offset, with
+// the records after it left alone
+#[test]
+fn a_blob_in_the_middle_of_a_buffer_decodes_from_its_offset() {
+    let options = CompressOptions { body_checksum: true, ..CompressOptions::default() };
+    let mut buffer = b"record one|".to_vec();
+    let offset = buffer.len();
+    let written = compress_into(TEXT, &options, &mut buffer).unwrap();
+    buffer.extend_from_slice(b"|record three");
+    assert_eq!(buffer[offset..offset + written], compress_with(TEXT, &options));
+    assert_eq!(decompress_to_vec(&buffer[offset..]).unwrap(), TEXT);
+    assert!(buffer.ends_with(b"|record three"));
+
+    // The same through a writer positioned inside a buffer that is already full
+    let mut reserved = std::io::Cursor::new(vec![0xee; 1000]);
+    reserved.set_position(300);
+    let written = compress_into(TEXT, &options, &mut reserved).unwrap();
+    let reserved = reserved.into_inner();
+    assert_eq!(decompress_to_vec(&reserved[300..]).unwrap(), TEXT);
+    assert!(reserved[..300].iter().chain(&reserved[300 + written..]).all(|&byte| byte == 0xee));
+}
+
+#[test]
+fn a_file_range_compresses_exactly_those_bytes() {
+    let dir = tempfile::tempdir().unwrap();
+    let (input, output) = (dir.path().join("records.bin"), dir.path().join("record.huff"));
+    let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8 ^ (i / 1000) as u8).collect();
+    std::fs::write(&input, &data).unwrap();
+    let options = CompressOptions::default();
+
+    for (offset, length, expected) in [
+        (3000, Some(2500), &data[3000..5500]),
+        (9000, None, &data[9000..]),
+        (9000, Some(1000), &data[9000..]),
+        (0, Some(1), &data[..1]),
+    ] {
+        compress_file_range(&input, &output, offset, length, &options).unwrap();
+        let compressed = std::fs::read(&output).unwrap();
+        assert_eq!(compressed, compress_with(expected, &options), "{}, {:?}", offset, length);
+        assert_eq!(decompress_to_vec(&compressed).unwrap(), expected);
+    }
+
+    std::fs::remove_file(&output).unwrap();
+    for (offset, length) in [(9000, Some(1001)), (10_001, None), (u64::MAX, Some(1))] {
+        match compress_file_range(&input, &output, offset, length, &options) {
+            Err(RuffmanError::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput),
+            other => panic!("{}, {:?} gave {:?}", offset, length, other),
+        }
+        assert!(!output.exists());
+    }
+}
@@ -0,0 +1,259 @@
+use ruffman::{
+    compress, compress_with, decompress_file_with, decompress_into_slice, decompress_to_vec, read_compression_stats,
+    CompressOptions, DecompressOptions, RuffmanError,
+};
+
+const TEXT: &[u8] = b"it was the best of times, it was the worst of times";
+
+#[test]
+fn decompress_into_exactly_sized_slice() {
+    let compressed = compress(TEXT);
+    let mut out = vec![0u8; TEXT.len()];
+    assert_eq!(decompress_into_slice(&compressed, &mut out).unwrap(), TEXT.len());
+    assert_eq!(out, TEXT);
+}
+
+#[test]
+fn decompress_into_larger_slice_leaves_the_rest() {
+    let compressed = compress(TEXT);
+    let mut out = vec![0xAAu8; TEXT.len() + 8];
+    assert_eq!(decompress_into_slice(&compressed, &mut out).unwrap(), TEXT.len());
+    assert_eq!(&out[..TEXT.len()], TEXT);
+    assert_eq!(out[TEXT.len()..], [0xAA; 8]);
+}
+
+#[test]
+fn decompress_into_undersized_slice_fails_up_front() {
+    let compressed = compress(TEXT);
+    let mut out = vec![0u8; TEXT.len() - 1];
+    match decompress_into_slice(&compressed, &mut out) {
+        Err(RuffmanError::OutputTooSmall { needed, available }) => {
+            assert_eq!((needed, available), (TEXT.len(), TEXT.len() - 1));
+        }
+        other => panic!("expected OutputTooSmall, got {:?}", other),
+    }
+    // Checked against the header before decoding, so nothing was written
+    assert!(out.iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn decompress_transformed_into_undersized_slice_fails() {
+    let options = CompressOptions {
+        nibbles: true,
+        ..CompressOptions::default()
+    };
+    let compressed = compress_with(TEXT, &options);
+    let mut out = vec![0u8; TEXT.len() - 1];
+    assert!(matches!(
+        decompress_into_slice(&compressed, &mut out),
+        Err(RuffmanError::OutputTooSmall { .. })
+    ));
+}
+
+#[test]
+fn truncated_file_recovers_a_correct_prefix() {
+    let data = TEXT.repeat(20);
+    let compressed = compress(&data);
+    let truncated = &compressed[..compressed.len() - 40];
+    let recovery = ruffman::decompress_recover(truncated).unwrap();
+    assert!(recovery.error.is_some());
+    assert!(!recovery.data.is_empty());
+    assert!(recovery.data.len() < data.len());
+    assert_eq!(recovery.data, data[..recovery.data.len()]);
+}
+
+#[test]
+fn corrupt_body_fails_its_checksum_before_decoding() {
+    let data = TEXT.repeat(4);
+    let options = CompressOptions {
+        body_checksum: true,
+        ..CompressOptions::default()
+    };
+    let mut compressed = compress_with(&data, &options);
+    let last = compressed.len() - 1;
+    compressed[last] ^= 0x80;
+    assert!(matches!(
+        ruffman::decompress_to_vec(&compressed),
+        Err(RuffmanError::BodyChecksumMismatch { .. })
+    ));
+    // The checksum fails before any byte is decoded, so nothing reaches the slice either
+    let mut out = vec![0u8; data.len()];
+    assert!(matches!(
+        decompress_into_slice(&compressed, &mut out),
+        Err(RuffmanError::BodyChecksumMismatch { .. })
+    ));
+    assert!(out.iter().all(|&byte| byte == 0));
+
+    // Without a checksum the same flip is only noticed, if at all, by decoding
+    let mut unchecked = compress(&data);
+    let last = unchecked.len() - 1;
+    unchecked[last] ^= 0x80;
+    assert_ne!(ruffman::decompress_to_vec(&unchecked).ok(), Some(data));
+}
+
+// Only "00" and "01" are codes, so a run of one bits can never match
+fn half_table() -> [u8; 256] {
+    let mut lengths = [0u8; 256];
+    lengths[b'a' as usize] = 2;
+    lengths[b'b' as usize] = 2;
+    lengths
+}
+
+#[test]
+fn bits_that_match_no_code_fail_at_the_first_code() {
+    let data = b"ab".repeat(100_000);
+    let options = CompressOptions {
+        reuse_code_lengths: Some(half_table()),
+        ..CompressOptions::default()
+    };
+    let mut compressed = compress_with(&data, &options);
+    let body_len = data.len() * 2 / 8;
+    let body_start = compressed.len() - body_len;
+    compressed[body_start..].fill(0xFF);
+    match ruffman::decompress_to_vec(&compressed) {
+        Err(RuffmanError::CorruptDataAt { offset, .. }) => assert_eq!(offset, body_start),
+        other => panic!("expected CorruptDataAt, got {:?}", other.map(|data| data.len())),
+    }
+}
+
+#[test]
+fn raw_bits_that_match_no_code_fail_at_the_first_code() {
+    let body = vec![0xFF; 1 << 20];
+    match ruffman::decode_raw(&body, &half_table(), None) {
+        Err(RuffmanError::CorruptDataAt { offset, .. }) => assert_eq!(offset, 0),
+        other => panic!("expected CorruptDataAt, got {:?}", other.map(|data| data.len())),
+    }
+}
+
+// A claimed count far beyond what the body holds must fail, not reserve memory for it
+#[test]
+fn raw_byte_count_beyond_the_body_is_a_mismatch() {
+    let body = [0b0001_0001; 4];
+    for byte_count in [1 << 40, usize::MAX] {
+        match ruffman::decode_raw(&body, &half_table(), Some(byte_count)) {
+            Err(RuffmanError::LengthMismatch { expected, actual: 16 }) => assert_eq!(expected, byte_count),
+            other => panic!("expected LengthMismatch, got {:?}", other.map(|data| data.len())),
+        }
+        let table = ruffman::StaticTable::new(&half_table()).unwrap();
+        let decoded = ruffman::decompress_static(&body, &table, Some(byte_count));
+        assert!(matches!(decoded, Err(RuffmanError::LengthMismatch { actual: 16, .. })));
+    }
+}
+
+// The code table's entry count is the u64 after the header length, at offset 9
+#[test]
+fn code_table_with_more_entries_than_byte_values_is_rejected() {
+    for count in [257u64, 1 << 40, u64::MAX] {
+        let mut compressed = compress(TEXT);
+        compressed[9..17].copy_from_slice(&count.to_le_bytes());
+        let decoded = ruffman::decompress_to_vec(&compressed).map(|_| ());
+        let read = ruffman::read_code_lengths(&compressed).map(|_| ());
+        for result in [decoded, read] {
+            match result {
+                Err(RuffmanError::CorruptDataAt { offset: 9, message }) => assert!(message.contains("entries")),
+                other => panic!("entry count {} gave {:?}", count, other),
+            }
+        }
+    }
+}
+
+// The checksum is taken as the body is written; a damaged body reports it next to a CRC
+// of the bytes actually there, and both match one-shot CRCs of the same bytes
+#[test]
+fn the_body_checksum_is_a_crc_of_the_whole_body() {
+    let data = include_bytes!("../README.md");
+    let compressed = compress_with(data, &CompressOptions { body_checksum: true, ..CompressOptions::default() });
+    let body_start = read_compression_stats(&compressed).unwrap().header_size;
+    assert_eq!(decompress_to_vec(&compressed).unwrap(), data);
+
+    let mut damaged = compressed.clone();
+    damaged[body_start + 10] ^= 0x40;
+    match decompress_to_vec(&damaged) {
+        Err(RuffmanError::BodyChecksumMismatch { expected, actual }) => {
+            assert_eq!(expected, crc32fast::hash(&compressed[body_start..]));
+            assert_eq!(actual, crc32fast::hash(&damaged[body_start..]));
+        }
+        other => panic!("expected a checksum mismatch, got {:?}", other),
+    }
+}
+
+// Blocks of zeros become holes, so the file takes far less space than its length. File
+// systems without holes would allocate every block, so this only runs on Unix.
+#[cfg(unix)]
+#[test]
+fn sparse_output_leaves_holes_for_zeros() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let (input, output) = (dir.path().join("disk.img.huff"), dir.path().join("disk.img"));
+    let mut data = vec![0u8; 1 << 20];
+    data[..13].copy_from_slice(b"boot sector\n\x01");
+    data[1 << 19] = 0xff;
+    std::fs::write(&input, compress(&data)).unwrap();
+
+    let options = DecompressOptions { sparse: true, ..DecompressOptions::default() };
+    decompress_file_with(&input, &output, &options).unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), data);
+    let metadata = std::fs::metadata(&output).unwrap();
+    assert_eq!(metadata.len(), data.len() as u64);
+    // `blocks` counts 512-byte units
+    assert!(metadata.blocks() * 512 < data.len() as u64 / 4, "{} blocks allocated", metadata.blocks());
+}
+
+// Missing bytes are reported as truncation, even when the body has a checksum that would
+// fail too, so users know to fetch the file again rather than suspect corruption
+#[test]
+fn a_truncated_body_is_not_a_checksum_mismatch() {
+    let data = TEXT.repeat(20);
+    let compressed = compress_with(&data, &CompressOptions { body_checksum: true, ..CompressOptions::default() });
+    let truncated = &compressed[..compressed.len() - 3];
+    match decompress_to_vec(truncated) {
+        Err(RuffmanError::TruncatedBody { expected, actual }) => assert_eq!(actual + 3, expected),
+        other => panic!("expected TruncatedBody, got {:?}", other),
+    }
+    let unchecked = compress(&data);
+    let truncated = &unchecked[..unchecked.len() - 3];
+    assert!(matches!(decompress_to_vec(truncated), Err(RuffmanError::TruncatedBody { .. })));
+}
+
+#[cfg(feature = "tempfile")]
+#[test]
+fn a_temporary_file_holds_the_data_until_dropped() {
+    let (path, written) = ruffman::decompress_to_tempfile(&compress(TEXT)).unwrap();
+    assert_eq!(written, TEXT.len());
+    assert_eq!(std::fs::read(&path).unwrap(), TEXT);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o077, 0);
+    }
+    let kept = path.to_path_buf();
+    drop(path);
+    assert!(!kept.exists());
+}
+
+// A header bincode cannot read gets one error whose message says what may be wrong,
+// without bincode's own wording
+#[test]
+fn an_unreadable_header_is_reported_as_an_unsupported_encoding() {
+    let compressed = compress(TEXT);
+    let entries = u64::from_le_bytes(compressed[9..17].try_into().unwrap()) as usize;
+    // The tag of the first optional field, after the table and the two counts
+    let tag = 9 + 8 + 2 * entries + 16;
+    let mut bad_tag = compressed.clone();
+    bad_tag[tag] = 7;
+    // A header length that ends the header in the middle of the counts
+    let mut cut_short = compressed.clone();
+    cut_short[5..9].copy_from_slice(&(8 + 2 * entries as u32 + 4).to_le_bytes());
+
+    for damaged in [bad_tag, cut_short] {
+        match decompress_to_vec(&damaged) {
+            Err(error @ RuffmanError::UnsupportedHeaderEncoding) => {
+                let message = error.to_string();
+                assert!(message.contains("incompatible version of ruffman"), "{}", message);
+                assert!(!message.to_lowercase().contains("bincode") && !message.contains("tag"), "{}", message);
+            }
+            other => panic!("expected UnsupportedHeaderEncoding, got {:?}", other),
+        }
+    }
+}
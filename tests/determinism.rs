@@ -0,0 +1,95 @@
+//! Identical input and options must give identical bytes, run after run. Each `HashMap`
+//! gets its own random hash keys, so two runs in one process still iterate differently.
+
+use ruffman::{compress_stream, compress_with, BitOrder, CompressOptions, TieBreak};
+
+fn inputs() -> Vec<Vec<u8>> {
+    vec![
+        Vec::new(),
+        b"a".to_vec(),
+        b"abracadabra".to_vec(),
+        // Many equal frequencies, where tie-breaking decides the tree
+        (0..=255u8).collect(),
+        (0..=255u8).cycle().take(10_000).collect(),
+        b"1,alpha,3.5\n2,beta,4.25\n3,gamma,5\n".repeat(30),
+        include_bytes!("../src/codec.rs").to_vec(),
+    ]
+}
+
+fn option_sets() -> Vec<CompressOptions> {
+    let mut sets = Vec::new();
+    for tie_break in [TieBreak::SymbolAsc, TieBreak::SymbolDesc, TieBreak::Insertion] {
+        for order1 in [false, true] {
+            sets.push(CompressOptions {
+                tie_break,
+                order1,
+                ..CompressOptions::default()
+            });
+        }
+    }
+    sets.push(CompressOptions {
+        nibbles: true,
+        ..CompressOptions::default()
+    });
+    sets.push(CompressOptions {
+        columns: Some(b','),
+        body_checksum: true,
+        ..CompressOptions::default()
+    });
+    sets.push(CompressOptions {
+        bit_order: BitOrder::LsbFirst,
+        framed: true,
+        ..CompressOptions::default()
+    });
+    sets
+}
+
+#[test]
+fn compressing_twice_gives_identical_bytes() {
+    for data in inputs() {
+        for options in option_sets() {
+            let first = compress_with(&data, &options);
+            let second = compress_with(&data, &options);
+            assert_eq!(first, second, "{} bytes with {:?}", data.len(), options);
+        }
+    }
+}
+
+#[test]
+fn compressing_on_another_thread_gives_identical_bytes() {
+    for data in inputs() {
+        let here = compress_with(&data, &CompressOptions::default());
+        let copy = data.clone();
+        let there = std::thread::spawn(move || compress_with(&copy, &CompressOptions::default())).join().unwrap();
+        assert_eq!(here, there);
+    }
+}
+
+#[test]
+fn streaming_twice_gives_identical_bytes() {
+    for data in inputs() {
+        let run = || {
+            let mut output = Vec::new();
+            compress_stream(&data[..], &mut output, 1000).unwrap();
+            output
+        };
+        assert_eq!(run(), run());
+    }
+}
+
+#[test]
+fn verifying_trees_changes_nothing() {
+    for data in [b"abracadabra".to_vec(), (0..=255u8).collect(), include_bytes!("../src/huffman.rs").to_vec()] {
+        for order1 in [false, true] {
+            let options = CompressOptions {
+                order1,
+                ..CompressOptions::default()
+            };
+            let verified = CompressOptions {
+                verify_tree: true,
+                ..options.clone()
+            };
+            assert_eq!(compress_with(&data, &verified), compress_with(&data, &options));
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use ruffman::{compress_messages, decompress_messages, Codec, Dictionary, DictionaryCodec};
+
+fn log_lines() -> String {
+    (0..200).map(|i| format!("INFO request {} served in {} ms\n", i, i % 17)).collect()
+}
+
+#[test]
+fn later_messages_code_smaller_than_the_first() {
+    let encoder = DictionaryCodec::new(4096);
+    let decoder = DictionaryCodec::new(4096);
+    let mut sizes = Vec::new();
+    for line in log_lines().lines() {
+        let encoded = encoder.encode(line.as_bytes());
+        assert_eq!(decoder.decode(&encoded).unwrap(), line.as_bytes());
+        sizes.push(encoded.len());
+    }
+    assert!(sizes.last().unwrap() < sizes.first().unwrap(), "sizes {:?}", sizes);
+}
+
+#[test]
+fn messages_round_trip_through_frames() {
+    let input = log_lines();
+    let mut framed = Vec::new();
+    compress_messages(input.as_bytes(), &mut framed, 4096).unwrap();
+    let mut output = Vec::new();
+    decompress_messages(framed.as_slice(), &mut output, 4096).unwrap();
+    assert_eq!(output, input.as_bytes());
+}
+
+#[test]
+fn a_different_size_fails() {
+    let input = log_lines();
+    let mut framed = Vec::new();
+    compress_messages(input.as_bytes(), &mut framed, 4096).unwrap();
+    let mut output = Vec::new();
+    assert!(decompress_messages(framed.as_slice(), &mut output, 64).is_err());
+}
+
+// The window is not allocated up front, so a limit far beyond any stream is fine
+#[test]
+fn a_huge_window_limit_allocates_nothing_up_front() {
+    let dictionary = Dictionary::new(usize::MAX);
+    assert!(dictionary.is_empty());
+    let (encoder, decoder) = (DictionaryCodec::new(usize::MAX), DictionaryCodec::new(usize::MAX));
+    for line in log_lines().lines().take(20) {
+        assert_eq!(decoder.decode(&encoder.encode(line.as_bytes())).unwrap(), line.as_bytes());
+    }
+    assert_eq!(encoder.dictionary().len(), log_lines().lines().take(20).map(str::len).sum::<usize>());
+}
@@ -0,0 +1,145 @@
+//! The on-disk format must not depend on the host: every integer is little-endian and
+//! fixed-width, so a file written anywhere decodes the same everywhere.
+
+use ruffman::{
+    code_lengths, compress, compress_file, compress_with, decompress_file, decompress_recover, decompress_to_vec,
+    lengths_to_codes, read_compression_stats, self_check, BitOrder, CompressOptions, RuffmanError, MAGIC,
+};
+
+// Written by `compress(b"abracadabra")`; never regenerate it, since it pins the layout
+const ABRACADABRA: &[u8] = include_bytes!("fixtures/abracadabra.huff");
+
+#[test]
+fn fixture_decodes_to_known_string() {
+    assert_eq!(decompress_to_vec(ABRACADABRA).unwrap(), b"abracadabra");
+}
+
+#[test]
+fn compress_reproduces_fixture() {
+    assert_eq!(compress(b"abracadabra"), ABRACADABRA);
+}
+
+#[test]
+fn header_fields_are_little_endian() {
+    assert_eq!(ABRACADABRA[..4], MAGIC);
+    // Header length as a u32, then the code table's entry count as a u64
+    let header_len = u32::from_le_bytes(ABRACADABRA[5..9].try_into().unwrap()) as usize;
+    assert_eq!(header_len, 49);
+    assert_eq!(u64::from_le_bytes(ABRACADABRA[9..17].try_into().unwrap()), 5);
+    // Five (byte, length) pairs, then the bit and byte counts
+    assert_eq!(ABRACADABRA[17..27], [b'a', 1, b'b', 3, b'c', 3, b'd', 3, b'r', 3]);
+    assert_eq!(u64::from_le_bytes(ABRACADABRA[27..35].try_into().unwrap()), 23);
+    assert_eq!(u64::from_le_bytes(ABRACADABRA[35..43].try_into().unwrap()), 11);
+    assert_eq!(ABRACADABRA.len(), 9 + header_len + 3);
+}
+
+// Only the bytes that occur are stored, as (byte, length) pairs behind their count, which
+// is far less than a dense table of one length for each of the 256 byte values
+#[test]
+fn sparse_alphabet_stores_a_small_table() {
+    let sparse: Vec<u8> = (0..3000u32).map(|i| b"etaoinshrdlu"[(i % 12) as usize]).collect();
+    let compressed = compress(&sparse);
+    assert_eq!(u64::from_le_bytes(compressed[9..17].try_into().unwrap()), 12);
+    assert_eq!(decompress_to_vec(&compressed).unwrap(), sparse);
+
+    let dense: Vec<u8> = (0..3000u32).map(|i| (i % 256) as u8).collect();
+    let sparse_header = read_compression_stats(&compressed).unwrap().header_size;
+    let dense_header = read_compression_stats(&compress(&dense)).unwrap().header_size;
+    assert_eq!(dense_header - sparse_header, 2 * (256 - 12));
+}
+
+// There is one form of Huffman header and it is already sparse, so a file of ten distinct
+// bytes has the ordinary mode byte and ten table entries, with no flag choosing a variant
+#[test]
+fn a_ten_symbol_file_stores_ten_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let (input, compressed, output) =
+        (dir.path().join("digits"), dir.path().join("digits.huff"), dir.path().join("digits.out"));
+    let digits: Vec<u8> = (0..5000u32).map(|i| b"0123456789"[(i % 17 % 10) as usize]).collect();
+    std::fs::write(&input, &digits).unwrap();
+    compress_file(&input, &compressed).unwrap();
+    decompress_file(&compressed, &output).unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), digits);
+
+    let file = std::fs::read(&compressed).unwrap();
+    assert_eq!(file[4], 0);
+    assert_eq!(u64::from_le_bytes(file[9..17].try_into().unwrap()), 10);
+    let stored: Vec<u8> = file[17..37].iter().step_by(2).copied().collect();
+    assert_eq!(stored, b"0123456789");
+    let lengths = code_lengths(&digits);
+    assert!(file[17..37].chunks(2).all(|entry| entry[1] == lengths[entry[0] as usize]));
+}
+
+#[test]
+fn self_check_passes() {
+    assert_eq!(self_check().unwrap(), 10);
+}
+
+// The table was once a `HashMap<u8, String>` of codes, in whatever order the map iterated;
+// sorted (byte, length) pairs are smaller and always serialize the same
+#[test]
+fn the_sorted_length_table_is_smaller_than_a_code_map() {
+    use bincode::Options;
+
+    let data: Vec<u8> = (0..=255u8).chain(include_bytes!("../README.md").iter().copied()).collect();
+    let lengths = code_lengths(&data);
+    assert!(lengths.iter().all(|&length| length > 0));
+    let options = || bincode::DefaultOptions::new().with_fixint_encoding().with_little_endian();
+    let codes = options().serialize(&lengths_to_codes(lengths)).unwrap();
+    let pairs: Vec<(u8, u8)> = (0..=255u8).map(|byte| (byte, lengths[byte as usize])).collect();
+    let table = options().serialize(&pairs).unwrap();
+    assert_eq!(table.len(), 8 + 2 * 256);
+    assert!(table.len() < codes.len(), "sorted {} bytes, map {} bytes", table.len(), codes.len());
+
+    // The header starts with exactly that table, behind the magic, mode and header length
+    let compressed = compress(&data);
+    assert_eq!(compressed[9..9 + table.len()], table[..]);
+    assert!(read_compression_stats(&compressed).unwrap().header_size < 9 + codes.len());
+}
+
+// Counts beyond 32 bits are stored and read whole, on any platform: a header claiming one,
+// with the body it would need missing, is reported with the full size it expects
+#[test]
+fn counts_above_u32_max_are_read_as_64_bits() {
+    let mut claimed = ABRACADABRA.to_vec();
+    let bit_count: u64 = 5 << 32;
+    claimed[27..35].copy_from_slice(&bit_count.to_le_bytes());
+    claimed[35..43].copy_from_slice(&(bit_count / 4).to_le_bytes());
+    match decompress_to_vec(&claimed) {
+        Err(RuffmanError::TruncatedBody { expected, actual }) => {
+            assert_eq!(expected as u64, bit_count / 8);
+            assert_eq!(actual, 3);
+        }
+        other => panic!("expected TruncatedBody, got {:?}", other),
+    }
+    // Salvaging reads only as far as the body goes, padding bits included
+    let recovery = decompress_recover(&claimed).unwrap();
+    assert!(recovery.data.starts_with(b"abracadabra") && recovery.data.len() <= 24);
+}
+
+// The bit order is a u32 variant tag after the tie-break policy: for a default header with
+// `n` codes it sits at 9 + (8 + 2n) + 16 + five one-byte fields + 4, i.e. 42 + 2n
+#[test]
+fn the_stored_bit_order_decides_how_the_body_is_read() {
+    let data = b"a body read in the wrong bit order will not come out the same".repeat(4);
+    let compress_in = |bit_order| compress_with(&data, &CompressOptions { bit_order, ..CompressOptions::default() });
+    let (msb, lsb) = (compress_in(BitOrder::MsbFirst), compress_in(BitOrder::LsbFirst));
+    let entries = u64::from_le_bytes(msb[9..17].try_into().unwrap()) as usize;
+    let tag = 42 + 2 * entries;
+    assert_eq!(msb[tag..tag + 4], 0u32.to_le_bytes());
+    assert_eq!(lsb[tag..tag + 4], 1u32.to_le_bytes());
+    // Nothing else in the header differs
+    let body_start = 9 + u32::from_le_bytes(msb[5..9].try_into().unwrap()) as usize;
+    assert_eq!(msb[..tag], lsb[..tag]);
+    assert_eq!(msb[tag + 4..body_start], lsb[tag + 4..body_start]);
+
+    for (compressed, flipped_tag) in [(msb, 1u8), (lsb, 0u8)] {
+        assert_eq!(decompress_to_vec(&compressed).unwrap(), data);
+        let mut flipped = compressed.clone();
+        flipped[tag] = flipped_tag;
+        // Either the codes stop matching and decoding fails, or it decodes something else
+        if let Ok(decoded) = decompress_to_vec(&flipped) {
+            assert_ne!(decoded, data);
+        }
+    }
+}
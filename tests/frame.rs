@@ -0,0 +1,30 @@
+//! Framed files sent one after another on a stream and taken back off it one at a time
+
+use ruffman::{compress_with, decompress_to_vec, read_frame, write_frame, CompressOptions, RuffmanError};
+
+#[test]
+fn framed_messages_in_one_buffer_are_read_back_one_by_one() {
+    let framed = CompressOptions { framed: true, ..CompressOptions::default() };
+    let messages: [&[u8]; 3] = [b"first message", b"the second, a little longer than the first", b"3"];
+    let stream: Vec<u8> = messages.iter().flat_map(|message| compress_with(message, &framed)).collect();
+
+    let mut reader = &stream[..];
+    for message in messages {
+        let frame = read_frame(&mut reader).unwrap().expect("a frame for every message");
+        assert_eq!(decompress_to_vec(&frame).unwrap(), message);
+    }
+    assert!(read_frame(&mut reader).unwrap().is_none());
+}
+
+#[test]
+fn a_stream_cut_inside_a_frame_is_corrupt() {
+    let mut stream = Vec::new();
+    write_frame(&mut stream, b"whole").unwrap();
+    write_frame(&mut stream, b"cut short").unwrap();
+    // Inside the second frame's body, then inside its length
+    for cut in [stream.len() - 1, 9 + 2] {
+        let mut reader = &stream[..cut];
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap(), b"whole");
+        assert!(matches!(read_frame(&mut reader), Err(RuffmanError::CorruptData(_))));
+    }
+}
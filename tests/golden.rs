@@ -0,0 +1,96 @@
+//! Golden files: `tests/fixtures/golden/<case>.huff` is what compressing each case's input
+//! with its options gave when the format was last changed on purpose. Decoding every
+//! fixture must give the input back, and compressing the input again must give the
+//! fixture byte for byte, so any accidental change to the format fails here.
+//!
+//! After an intentional format change, regenerate the fixtures with
+//! `RUFFMAN_UPDATE_FIXTURES=1 cargo test --test golden` and commit them with the change.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use ruffman::{compress_with, decompress_to_vec, read_frame, BitOrder, CompressOptions, TieBreak};
+
+// Set to write the fixtures instead of checking them
+const UPDATE: &str = "RUFFMAN_UPDATE_FIXTURES";
+
+const TEXT: &[u8] = b"It was the best of times, it was the worst of times, it was the age of wisdom, \
+    it was the age of foolishness.\r\n";
+
+struct Case {
+    name: &'static str,
+    input: Vec<u8>,
+    options: CompressOptions,
+}
+
+fn case(name: &'static str, input: Vec<u8>, options: CompressOptions) -> Case {
+    Case { name, input, options }
+}
+
+fn cases() -> Vec<Case> {
+    let text = TEXT.repeat(8);
+    let all_bytes: Vec<u8> = (0..=255u8).cycle().take(1024).collect();
+    let records = b"1,alpha,3.5\n2,beta,4.25\n3,gamma,5\n".repeat(10);
+    let defaults = CompressOptions::default;
+    vec![
+        case("empty", Vec::new(), defaults()),
+        case("single-symbol", vec![7; 100], defaults()),
+        case("text", text.clone(), defaults()),
+        case("all-bytes", all_bytes.clone(), defaults()),
+        case("nibbles", all_bytes, CompressOptions { nibbles: true, ..defaults() }),
+        case("columns", records, CompressOptions { columns: Some(b','), ..defaults() }),
+        case("order1", text.clone(), CompressOptions { order1: true, ..defaults() }),
+        case("body-checksum", text.clone(), CompressOptions { body_checksum: true, ..defaults() }),
+        case("lsb-first", text.clone(), CompressOptions { bit_order: BitOrder::LsbFirst, ..defaults() }),
+        case("symbol-desc", text.clone(), CompressOptions { tie_break: TieBreak::SymbolDesc, ..defaults() }),
+        case("comment", text.clone(), CompressOptions { comment: Some("golden".to_string()), ..defaults() }),
+        case("created", text.clone(), CompressOptions { created: Some(1_700_000_000), ..defaults() }),
+        case("framed", text, CompressOptions { framed: true, ..defaults() }),
+    ]
+}
+
+fn fixture_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/golden").join(format!("{}.huff", name))
+}
+
+fn read_fixture(name: &str) -> Vec<u8> {
+    fs::read(fixture_path(name)).unwrap_or_else(|e| panic!("reading fixture {}: {}", name, e))
+}
+
+#[test]
+fn fixtures_decompress_to_their_inputs() {
+    // The fixtures are being rewritten, and are checked on the next run
+    if env::var_os(UPDATE).is_some() {
+        return;
+    }
+    for case in cases() {
+        let fixture = read_fixture(case.name);
+        let compressed = match case.options.framed {
+            true => read_frame(&mut &fixture[..]).unwrap().expect("a framed fixture holds a frame"),
+            false => fixture,
+        };
+        let decompressed = decompress_to_vec(&compressed).unwrap_or_else(|e| panic!("{}: {}", case.name, e));
+        assert_eq!(decompressed, case.input, "{} decompressed to something else", case.name);
+    }
+}
+
+#[test]
+fn inputs_compress_to_their_fixtures() {
+    let update = env::var_os(UPDATE).is_some();
+    for case in cases() {
+        // `created` is left at `None` except in the case that tests it, so the clock never matters
+        let compressed = compress_with(&case.input, &case.options);
+        if update {
+            fs::create_dir_all(fixture_path(case.name).parent().unwrap()).unwrap();
+            fs::write(fixture_path(case.name), &compressed).unwrap();
+            continue;
+        }
+        assert!(
+            compressed == read_fixture(case.name),
+            "{} no longer compresses to its fixture; if the format changed on purpose, regenerate the \
+             fixtures with RUFFMAN_UPDATE_FIXTURES=1 cargo test --test golden",
+            case.name
+        );
+    }
+}
@@ -0,0 +1,33 @@
+//! With the `gzip` feature, `Format::Gzip` writes standard gzip members, which every
+//! decompression entry point recognises by their magic bytes.
+#![cfg(feature = "gzip")]
+
+use std::fs;
+
+use ruffman::{compress_file_with, compress_with, decompress_file, decompress_to_vec, CompressOptions, Format};
+
+const TEXT: &[u8] = b"the gzip backend round-trips like the native one, the gzip backend round-trips\n";
+
+fn gzip() -> CompressOptions {
+    CompressOptions { format: Format::Gzip, ..CompressOptions::default() }
+}
+
+#[test]
+fn gzip_buffers_round_trip() {
+    let compressed = compress_with(TEXT, &gzip());
+    assert_eq!(&compressed[..2], &[0x1f, 0x8b]);
+    assert_eq!(decompress_to_vec(&compressed).unwrap(), TEXT);
+}
+
+#[test]
+fn gzip_files_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let (input, compressed, output) =
+        (dir.path().join("input.txt"), dir.path().join("input.gz"), dir.path().join("output.txt"));
+    fs::write(&input, TEXT.repeat(20)).unwrap();
+
+    compress_file_with(&input, &compressed, &gzip()).unwrap();
+    assert_eq!(&fs::read(&compressed).unwrap()[..2], &[0x1f, 0x8b]);
+    decompress_file(&compressed, &output).unwrap();
+    assert_eq!(fs::read(&output).unwrap(), TEXT.repeat(20));
+}
@@ -0,0 +1,27 @@
+//! With the `blake3` feature, contents can be checked against a hash without writing them out
+#![cfg(feature = "blake3")]
+
+use ruffman::{compress, compress_with_stats, decompressed_hash, DecompressOptions};
+
+const TEXT: &[u8] = b"hash what this decompresses to without keeping it anywhere";
+
+#[test]
+fn the_decompressed_hash_is_the_hash_of_the_input() {
+    let compressed = compress(TEXT);
+    let hash = decompressed_hash(&compressed[..], &DecompressOptions::default()).unwrap();
+    assert_eq!(hash, *blake3::hash(TEXT).as_bytes());
+
+    let (_, stats) = compress_with_stats(TEXT);
+    let hashes = stats.hashes.unwrap();
+    assert_eq!(hashes.original, hash);
+    assert_eq!(hashes.compressed, *blake3::hash(&compressed).as_bytes());
+}
+
+#[test]
+fn a_damaged_buffer_has_no_hash_even_when_recovering() {
+    let compressed = compress(&TEXT.repeat(10));
+    let truncated = &compressed[..compressed.len() - 5];
+    assert!(decompressed_hash(truncated, &DecompressOptions::default()).is_err());
+    let recover = DecompressOptions { recover: true, ..DecompressOptions::default() };
+    assert!(decompressed_hash(truncated, &recover).is_err());
+}
@@ -0,0 +1,51 @@
+//! Replacing files with their compressed or decompressed form, as gzip does
+
+use std::fs;
+
+use ruffman::{compress_in_place, decompress_in_place, CompressOptions, DecompressOptions, InPlaceOptions};
+
+const TEXT: &[u8] = b"a file that compresses in place and comes back the same\n";
+
+#[test]
+fn files_compress_and_decompress_in_place() {
+    let dir = tempfile::tempdir().unwrap();
+    let original = dir.path().join("notes.txt");
+    fs::write(&original, TEXT.repeat(30)).unwrap();
+
+    let (compressed, _) =
+        compress_in_place(&original, &CompressOptions::default(), &InPlaceOptions::default()).unwrap();
+    assert_eq!(compressed, dir.path().join("notes.txt.huff"));
+    assert!(!original.exists());
+    assert!(fs::read(&compressed).unwrap().starts_with(b"RUFF"));
+
+    let (restored, report) =
+        decompress_in_place(&compressed, &DecompressOptions::default(), &InPlaceOptions::default()).unwrap();
+    assert_eq!(restored, original);
+    assert!(!compressed.exists());
+    assert_eq!(fs::read(&restored).unwrap(), TEXT.repeat(30));
+    assert_eq!(report.bytes_written, TEXT.len() * 30);
+    // No temporary files are left behind
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn keep_and_force_decide_what_is_left() {
+    let dir = tempfile::tempdir().unwrap();
+    let original = dir.path().join("notes.txt");
+    let compressed = dir.path().join("notes.txt.huff");
+    fs::write(&original, TEXT).unwrap();
+    fs::write(&compressed, b"an older compressed copy").unwrap();
+
+    let keep = InPlaceOptions { keep: true, force: false };
+    assert!(compress_in_place(&original, &CompressOptions::default(), &keep).is_err());
+    assert_eq!(fs::read(&compressed).unwrap(), b"an older compressed copy");
+
+    let forced = InPlaceOptions { keep: true, force: true };
+    compress_in_place(&original, &CompressOptions::default(), &forced).unwrap();
+    assert_eq!(fs::read(&original).unwrap(), TEXT);
+    assert_eq!(ruffman::decompress_to_vec(&fs::read(&compressed).unwrap()).unwrap(), TEXT);
+
+    let elsewhere = dir.path().join("notes.bin");
+    fs::write(&elsewhere, TEXT).unwrap();
+    assert!(decompress_in_place(&elsewhere, &DecompressOptions::default(), &InPlaceOptions::default()).is_err());
+}
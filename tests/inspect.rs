@@ -0,0 +1,75 @@
+//! What `inspect` reports about compressed buffers
+
+use ruffman::{
+    code_lengths, compress, compress_with, compress_with_stats, decompress_to_vec, inspect, read_compression_stats,
+    CompressOptions,
+};
+
+#[test]
+fn efficiency_is_a_fraction() {
+    let skewed: Vec<u8> = (0..4000u32).map(|i| if i % 10 == 0 { b'b' } else { b'a' }).collect();
+    let uniform: Vec<u8> = (0..=255u8).cycle().take(4096).collect();
+    let text = include_bytes!("../README.md");
+    for data in [&skewed[..], &uniform[..], &text[..], b"x"] {
+        let efficiency = inspect(&compress(data)).unwrap().efficiency();
+        assert!((0.0..=1.0).contains(&efficiency), "efficiency {}", efficiency);
+    }
+    // Equal frequencies of a power-of-two alphabet are coded exactly at their entropy
+    assert!((inspect(&compress(&uniform)).unwrap().efficiency() - 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn a_comment_is_read_back() {
+    let comment = Some("nightly export, schema v3".to_string());
+    let options = CompressOptions { comment, ..CompressOptions::default() };
+    let compressed = compress_with(b"id,name\n1,ada\n", &options);
+    assert_eq!(inspect(&compressed).unwrap().comment.as_deref(), Some("nightly export, schema v3"));
+    assert_eq!(inspect(&compress(b"id,name\n1,ada\n")).unwrap().comment, None);
+    assert_eq!(decompress_to_vec(&compressed).unwrap(), b"id,name\n1,ada\n");
+}
+
+// One byte repeated takes a bit each, so the body is an eighth of the input
+#[test]
+fn a_single_repeated_byte_is_flagged() {
+    let data = vec![b'z'; 80_000];
+    let compressed = compress(&data);
+    let inspection = inspect(&compressed).unwrap();
+    assert!(inspection.single_symbol());
+    assert_eq!((inspection.distinct_symbols, inspection.entropy, inspection.efficiency()), (1, 0.0, 1.0));
+    let stats = read_compression_stats(&compressed).unwrap();
+    assert_eq!(stats.distinct_symbols, 1);
+    let (_, stats) = compress_with_stats(&data);
+    assert!((0.125..0.13).contains(&stats.ratio()), "ratio {}", stats.ratio());
+    assert!(!inspect(&compress(b"zy")).unwrap().single_symbol());
+}
+
+#[test]
+fn tree_depth_is_the_longest_code() {
+    let skewed: Vec<u8> = (0..12u8).flat_map(|byte| std::iter::repeat_n(byte, 1 << byte)).collect();
+    for data in [&b"abracadabra"[..], b"zz", &skewed, include_bytes!("../src/inspect.rs")] {
+        let inspection = inspect(&compress(data)).unwrap();
+        let longest = code_lengths(data).into_iter().max().unwrap() as usize;
+        assert_eq!(inspection.tree_depth, longest);
+        assert_eq!(inspection.tree_depth, inspection.longest_code);
+        // A lone symbol still hangs below a root, for its one-bit code
+        assert_eq!(inspection.tree_nodes, (2 * inspection.distinct_symbols - 1).max(2));
+    }
+    assert_eq!(inspect(&compress(&skewed)).unwrap().tree_depth, 11);
+}
+
+#[test]
+fn padding_bits_fill_the_last_byte_and_do_not_matter() {
+    for data in [&b"abracadabra"[..], b"abcdefgh", b"q"] {
+        let compressed = compress(data);
+        let lengths = code_lengths(data);
+        let bits: usize = data.iter().map(|&byte| lengths[byte as usize] as usize).sum();
+        let inspection = inspect(&compressed).unwrap();
+        assert_eq!(inspection.padding_bits as usize, (8 - bits % 8) % 8);
+        assert_eq!((compressed.len() - inspection.header_size) * 8, bits + inspection.padding_bits as usize);
+
+        // Bodies are packed most significant bit first, so the padding is the low bits
+        let mut repadded = compressed.clone();
+        *repadded.last_mut().unwrap() ^= ((1u16 << inspection.padding_bits) - 1) as u8;
+        assert_eq!(decompress_to_vec(&repadded).unwrap(), data);
+    }
+}
@@ -0,0 +1,47 @@
+//! With the `log` feature, the library reports what it does through the `log` facade,
+//! for hosts that collect logs. The logger is global, so this file holds a single test.
+#![cfg(feature = "log")]
+
+use std::fs;
+use std::sync::Mutex;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use ruffman::{compress, compress_file_with, decompress_file, CompressOptions};
+
+static EVENTS: Mutex<Vec<(Level, String)>> = Mutex::new(Vec::new());
+
+struct Capture;
+
+impl Log for Capture {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        EVENTS.lock().unwrap().push((record.level(), record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+}
+
+#[test]
+fn file_operations_log_what_they_do() {
+    log::set_logger(&Capture).unwrap();
+    log::set_max_level(LevelFilter::Debug);
+    let dir = tempfile::tempdir().unwrap();
+    let (input, compressed, restored) =
+        (dir.path().join("input.huff"), dir.path().join("twice.huff"), dir.path().join("restored"));
+    fs::write(&input, compress(b"already compressed once")).unwrap();
+
+    compress_file_with(&input, &compressed, &CompressOptions::default()).unwrap();
+    decompress_file(&compressed, &restored).unwrap();
+
+    let events = EVENTS.lock().unwrap();
+    let logged = |level: Level, text: &str| events.iter().any(|(l, message)| *l == level && message.contains(text));
+    assert!(logged(Level::Info, &format!("compressing {}", input.display())), "{:?}", events);
+    assert!(logged(Level::Debug, "distinct symbols"), "{:?}", events);
+    assert!(logged(Level::Warn, "compressed already"), "{:?}", events);
+    assert!(logged(Level::Info, &format!("wrote {}", compressed.display())), "{:?}", events);
+    assert!(logged(Level::Info, &format!("decompressing {}", compressed.display())), "{:?}", events);
+    assert!(logged(Level::Info, &format!("wrote {}", restored.display())), "{:?}", events);
+}
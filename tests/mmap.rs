@@ -0,0 +1,34 @@
+//! With the `mmap` feature, `compress_file` maps its input instead of reading it, which
+//! must not change a single byte of the output.
+#![cfg(feature = "mmap")]
+
+use std::fs;
+
+use ruffman::{compress_file, compress_reader_to_file, decompress_to_vec, CompressOptions};
+
+#[test]
+fn mapped_and_read_inputs_compress_the_same() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.txt");
+    let data: Vec<u8> = include_bytes!("../src/codec.rs").repeat(4);
+    fs::write(&input, &data).unwrap();
+    let (mapped, read) = (dir.path().join("mapped.huff"), dir.path().join("read.huff"));
+
+    compress_file(&input, &mapped).unwrap();
+    compress_reader_to_file(fs::File::open(&input).unwrap(), &read, &CompressOptions::default()).unwrap();
+    let mapped = fs::read(&mapped).unwrap();
+    assert_eq!(mapped, fs::read(&read).unwrap());
+    assert_eq!(mapped, ruffman::compress(&data));
+    assert_eq!(decompress_to_vec(&mapped).unwrap(), data);
+}
+
+// Empty files cannot be mapped everywhere, so they are read instead
+#[test]
+fn an_empty_file_compresses_as_an_empty_buffer() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("empty");
+    fs::write(&input, b"").unwrap();
+    let output = dir.path().join("empty.huff");
+    compress_file(&input, &output).unwrap();
+    assert_eq!(fs::read(&output).unwrap(), ruffman::compress(b""));
+}
@@ -0,0 +1,57 @@
+//! Byte models trained on sample data, merged and saved as CSV
+
+use ruffman::{
+    compress_with_warnings, decompress_to_vec, merge_models, read_code_lengths, CompressOptions, Model, Warning,
+};
+
+#[test]
+fn merged_shards_count_like_the_whole() {
+    let (a, b): (&[u8], &[u8]) = (b"first shard of the training data", b"and the second, with \x00 and \xff too");
+    let merged = merge_models(&Model::from_data(a), &Model::from_data(b));
+    assert_eq!(merged, Model::from_data(&[a, b].concat()));
+    assert_eq!(merged.code_lengths().unwrap(), Model::from_data(&[b, a].concat()).code_lengths().unwrap());
+    assert_eq!(merge_models(&Model::from_data(a), &Model::new()), Model::from_data(a));
+}
+
+#[test]
+fn a_model_survives_csv() {
+    let model = Model::from_data(include_bytes!("../README.md"));
+    let csv = model.to_csv();
+    assert!(csv.starts_with("byte,count\n"));
+    assert_eq!(csv.lines().count(), 257);
+    assert_eq!(Model::from_csv(&csv).unwrap(), model);
+    assert_eq!(Model::from_csv(&Model::new().to_csv()).unwrap(), Model::new());
+}
+
+#[test]
+fn only_a_smoothed_model_codes_bytes_it_never_saw() {
+    let model = Model::from_csv(&Model::from_data(b"training text without digits").to_csv()).unwrap();
+    let data = b"text with digits: 12345";
+    let reusing = |model: &Model| CompressOptions {
+        reuse_code_lengths: Some(model.code_lengths().unwrap()),
+        ..CompressOptions::default()
+    };
+
+    let (compressed, warnings) = compress_with_warnings(data, &reusing(&model));
+    assert!(warnings.contains(&Warning::CodeLengthsNotReused));
+    assert_ne!(read_code_lengths(&compressed).unwrap(), model.code_lengths().unwrap());
+    assert_eq!(decompress_to_vec(&compressed).unwrap(), data);
+
+    let smoothed = model.smoothed();
+    assert!(smoothed.code_lengths().unwrap().iter().all(|&length| length > 0));
+    let (compressed, warnings) = compress_with_warnings(data, &reusing(&smoothed));
+    assert!(!warnings.contains(&Warning::CodeLengthsNotReused));
+    assert_eq!(read_code_lengths(&compressed).unwrap(), smoothed.code_lengths().unwrap());
+    assert_eq!(decompress_to_vec(&compressed).unwrap(), data);
+}
+
+#[test]
+fn smoothing_survives_csv() {
+    let smoothed = Model::from_data(b"some training data").smoothed();
+    let imported = Model::from_csv(&smoothed.to_csv()).unwrap();
+    assert!(imported.is_smoothed());
+    assert_eq!(imported, smoothed);
+    // Smoothing an imported smoothed model adds nothing more
+    assert_eq!(imported.smoothed(), smoothed);
+    assert!(!Model::from_csv(&Model::from_data(b"raw").to_csv()).unwrap().is_smoothed());
+}
@@ -0,0 +1,94 @@
+use ruffman::{
+    compress, compress_file_with, compress_with, compress_with_warnings, decompress_file_with, decompress_to_vec,
+    decompress_with_code_lengths, read_code_lengths, scan, CompressOptions, DecompressOptions, RuffmanError, ScanStatus,
+    Warning,
+};
+
+const VERSION_1: &[u8] = b"The quick brown fox jumps over the lazy dog. The dog sleeps on.";
+const VERSION_2: &[u8] = b"The quick brown fox jumps over the lazy dog. The fox sleeps on.";
+
+fn referring(lengths: [u8; 256]) -> CompressOptions {
+    CompressOptions {
+        reuse_code_lengths: Some(lengths),
+        table_reference: true,
+        ..CompressOptions::default()
+    }
+}
+
+#[test]
+fn a_reused_table_is_stored_as_a_reference() {
+    let lengths = read_code_lengths(&compress(VERSION_1)).unwrap();
+    let stored = compress_with(
+        VERSION_2,
+        &CompressOptions {
+            reuse_code_lengths: Some(lengths),
+            ..CompressOptions::default()
+        },
+    );
+    let (referred, warnings) = compress_with_warnings(VERSION_2, &referring(lengths));
+    assert!(!warnings.contains(&Warning::CodeLengthsNotReused));
+    assert!(referred.len() < stored.len());
+    assert_eq!(decompress_with_code_lengths(&referred, &lengths).unwrap(), VERSION_2);
+    // Without the table, and with another one, it cannot be decoded
+    assert!(matches!(decompress_to_vec(&referred), Err(RuffmanError::CodeTableRequired)));
+    let other = read_code_lengths(&compress(b"something else entirely")).unwrap();
+    assert!(matches!(decompress_with_code_lengths(&referred, &other), Err(RuffmanError::CodeTableMismatch)));
+    assert!(matches!(scan(&referred, false), ScanStatus::Valid));
+}
+
+#[test]
+fn new_bytes_store_a_fresh_table() {
+    let lengths = read_code_lengths(&compress(VERSION_1)).unwrap();
+    let (compressed, warnings) = compress_with_warnings(b"New symbols: 0123456789!", &referring(lengths));
+    assert!(warnings.contains(&Warning::CodeLengthsNotReused));
+    assert_eq!(decompress_to_vec(&compressed).unwrap(), b"New symbols: 0123456789!");
+    assert_eq!(decompress_with_code_lengths(&compressed, &lengths).unwrap(), b"New symbols: 0123456789!");
+}
+
+#[test]
+fn files_refer_to_a_table_with_a_checksum_and_verification() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("v2.txt");
+    let output = dir.path().join("v2.txt.huff");
+    let restored = dir.path().join("v2.out");
+    std::fs::write(&input, VERSION_2).unwrap();
+    let lengths = read_code_lengths(&compress(VERSION_1)).unwrap();
+    let options = CompressOptions {
+        body_checksum: true,
+        verify_after: true,
+        ..referring(lengths)
+    };
+    compress_file_with(&input, &output, &options).unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), compress_with(VERSION_2, &options));
+
+    let options = DecompressOptions {
+        code_lengths: Some(lengths),
+        ..DecompressOptions::default()
+    };
+    decompress_file_with(&output, &restored, &options).unwrap();
+    assert_eq!(std::fs::read(&restored).unwrap(), VERSION_2);
+}
+
+#[test]
+fn a_damaged_reference_is_recovered_with_its_table() {
+    let dir = tempfile::tempdir().unwrap();
+    let (input, restored) = (dir.path().join("v2.txt.huff"), dir.path().join("v2.out"));
+    let lengths = read_code_lengths(&compress(VERSION_1)).unwrap();
+    let referred = compress_with(VERSION_2, &referring(lengths));
+    std::fs::write(&input, &referred[..referred.len() - 8]).unwrap();
+
+    let options = DecompressOptions {
+        recover: true,
+        code_lengths: Some(lengths),
+        ..DecompressOptions::default()
+    };
+    let report = decompress_file_with(&input, &restored, &options).unwrap();
+    assert!(report.damage.is_some());
+    let recovered = std::fs::read(&restored).unwrap();
+    assert!(!recovered.is_empty() && VERSION_2.starts_with(&recovered));
+    assert_eq!(report.bytes_written, recovered.len());
+
+    let other = read_code_lengths(&compress(b"something else entirely")).unwrap();
+    let other = DecompressOptions { code_lengths: Some(other), ..options };
+    assert!(matches!(decompress_file_with(&input, &restored, &other), Err(RuffmanError::CodeTableMismatch)));
+}
@@ -0,0 +1,62 @@
+//! Sorting a directory of files into valid, invalid and corrupt ruffman files
+
+use std::fs;
+use std::path::Path;
+
+use ruffman::{compress, compress_with, scan_dir, CompressOptions, ScanStatus};
+
+// (name, status) of every file found, in path order, as 'v', 'i' or 'c'
+fn classify(dir: &Path, deep: bool) -> Vec<(String, char)> {
+    let report = scan_dir(dir, deep).unwrap();
+    assert!(report.skipped.is_empty());
+    report
+        .entries
+        .iter()
+        .map(|entry| {
+            let name = entry.path.strip_prefix(dir).unwrap().to_string_lossy().into_owned();
+            let status = match entry.status {
+                ScanStatus::Valid => 'v',
+                ScanStatus::Invalid => 'i',
+                ScanStatus::Corrupt(_) => 'c',
+            };
+            (name, status)
+        })
+        .collect()
+}
+
+#[test]
+fn a_directory_scan_tells_valid_invalid_and_corrupt_files_apart() {
+    let dir = tempfile::tempdir().unwrap();
+    let text = b"a bucket of archives, some of them damaged".repeat(20);
+    let checksummed = compress_with(&text, &CompressOptions { body_checksum: true, ..CompressOptions::default() });
+    fs::create_dir(dir.path().join("nested")).unwrap();
+    fs::write(dir.path().join("a-valid.huff"), compress(&text)).unwrap();
+    fs::write(dir.path().join("nested").join("b-valid.huff"), &checksummed).unwrap();
+    fs::write(dir.path().join("c-notes.txt"), &text).unwrap();
+    fs::write(dir.path().join("d-empty"), b"").unwrap();
+    // Cut off inside the header
+    fs::write(dir.path().join("e-truncated.huff"), &checksummed[..20]).unwrap();
+    // An unknown mode byte
+    let mut unknown_mode = compress(&text);
+    unknown_mode[4] = 0x7f;
+    fs::write(dir.path().join("f-mode.huff"), &unknown_mode).unwrap();
+    // Only the body is damaged, which its checksum catches when the scan decodes
+    let mut damaged_body = checksummed.clone();
+    let last = damaged_body.len() - 1;
+    damaged_body[last] ^= 0xff;
+    fs::write(dir.path().join("g-body.huff"), &damaged_body).unwrap();
+
+    let expected = |body: char| {
+        vec![
+            ("a-valid.huff".to_string(), 'v'),
+            ("c-notes.txt".to_string(), 'i'),
+            ("d-empty".to_string(), 'i'),
+            ("e-truncated.huff".to_string(), 'c'),
+            ("f-mode.huff".to_string(), 'c'),
+            ("g-body.huff".to_string(), body),
+            (Path::new("nested").join("b-valid.huff").to_string_lossy().into_owned(), 'v'),
+        ]
+    };
+    assert_eq!(classify(dir.path(), false), expected('v'));
+    assert_eq!(classify(dir.path(), true), expected('c'));
+}
@@ -0,0 +1,49 @@
+//! Frames coded with a table both ends know in advance, which carry nothing but codes
+
+use ruffman::{code_lengths, compress_static, decompress_static, Model, RuffmanError, StaticTable};
+
+// A protocol whose messages only use these bytes, agreed at compile time
+const PROTOCOL_LENGTHS: [u8; 256] = {
+    let mut lengths = [0u8; 256];
+    lengths[b'G' as usize] = 2;
+    lengths[b'E' as usize] = 2;
+    lengths[b'T' as usize] = 2;
+    lengths[b' ' as usize] = 3;
+    lengths[b'/' as usize] = 4;
+    lengths[b'\n' as usize] = 4;
+    lengths
+};
+
+// The exact bits the codes take, rounded up to whole bytes
+fn body_len(data: &[u8], lengths: &[u8; 256]) -> usize {
+    data.iter().map(|&byte| lengths[byte as usize] as usize).sum::<usize>().div_ceil(8)
+}
+
+#[test]
+fn frames_carry_no_header() {
+    let table = StaticTable::new(&PROTOCOL_LENGTHS).unwrap();
+    for message in [&b"GET /\n"[..], b"GET GET /GET\n", b"T", b""] {
+        let frame = compress_static(message, &table);
+        assert_eq!(frame.len(), body_len(message, &PROTOCOL_LENGTHS));
+        // The longest code is under 8 bits, so the frame needs its count
+        assert_eq!(decompress_static(&frame, &table, Some(message.len())).unwrap(), message);
+    }
+}
+
+#[test]
+fn a_table_over_every_byte_needs_no_count() {
+    let model = Model::from_data(include_bytes!("../README.md")).smoothed();
+    let table = StaticTable::new(&model.code_lengths().unwrap()).unwrap();
+    for message in [&b"any bytes \x00\xff at all"[..], b"x", include_bytes!("../src/static_table.rs")] {
+        let frame = compress_static(message, &table);
+        assert_eq!(frame.len(), body_len(message, table.code_lengths()));
+        assert_eq!(decompress_static(&frame, &table, None).unwrap(), message);
+    }
+}
+
+#[test]
+fn lengths_that_are_no_prefix_code_are_rejected() {
+    let mut lengths = code_lengths(b"abc");
+    lengths[b'd' as usize] = 1;
+    assert!(matches!(StaticTable::new(&lengths), Err(RuffmanError::InvalidCodeLengths)));
+}
@@ -0,0 +1,80 @@
+use std::io::Cursor;
+
+use ruffman::{
+    compress_stream, compress_stream_indexed, compress_stream_indexed_with, decompress_stream_recover,
+    read_stream_index, RuffmanError,
+};
+
+const BLOCK_SIZE: usize = 1024;
+
+// Five blocks, each of a different letter, so a block's data is easy to pick out
+fn blocks() -> Vec<u8> {
+    (b'a'..=b'e').flat_map(|letter| [letter; BLOCK_SIZE]).collect()
+}
+
+#[test]
+fn only_the_corrupt_block_fails() {
+    let data = blocks();
+    let mut stream = Vec::new();
+    let index = compress_stream_indexed_with(data.as_slice(), &mut stream, BLOCK_SIZE, true).unwrap();
+    assert_eq!(index.members.len(), 5);
+
+    // Flip a byte in the middle of the third member
+    let third = index.members[2];
+    stream[(third.offset + third.compressed_size / 2) as usize] ^= 0x55;
+
+    let mut recovered = Vec::new();
+    let damaged = decompress_stream_recover(Cursor::new(&stream), &mut recovered).unwrap();
+    assert_eq!(damaged.len(), 1);
+    assert_eq!(damaged[0].number, 2);
+    assert_eq!(damaged[0].entry, third);
+    assert!(matches!(damaged[0].error, RuffmanError::CorruptDataAt { offset, .. } if offset == third.offset as usize));
+
+    let expected: Vec<u8> = data
+        .chunks(BLOCK_SIZE)
+        .enumerate()
+        .filter(|&(number, _)| number != 2)
+        .flat_map(|(_, block)| block.to_vec())
+        .collect();
+    assert_eq!(recovered, expected);
+}
+
+#[test]
+fn an_intact_stream_has_no_damaged_blocks() {
+    let data = blocks();
+    let mut stream = Vec::new();
+    compress_stream_indexed_with(data.as_slice(), &mut stream, BLOCK_SIZE, true).unwrap();
+    let mut recovered = Vec::new();
+    assert!(decompress_stream_recover(Cursor::new(&stream), &mut recovered).unwrap().is_empty());
+    assert_eq!(recovered, data);
+}
+
+#[test]
+fn indexes_read_back_with_and_without_checksums() {
+    for checksums in [false, true] {
+        let mut stream = Vec::new();
+        let written = compress_stream_indexed_with(&blocks()[..], &mut stream, BLOCK_SIZE, checksums).unwrap();
+        let index = read_stream_index(Cursor::new(&stream)).unwrap().unwrap();
+        assert_eq!(index, written);
+        assert!(index.members.iter().all(|member| member.checksum.is_some() == checksums));
+    }
+}
+
+#[test]
+fn the_footer_counts_the_members() {
+    let mut stream = Vec::new();
+    compress_stream_indexed(&blocks()[..], &mut stream, BLOCK_SIZE).unwrap();
+    let index = read_stream_index(Cursor::new(&stream)).unwrap().unwrap();
+    assert_eq!(index.members.len(), 5);
+    assert!(index.members.iter().all(|member| member.original_size == BLOCK_SIZE as u64));
+    let mut offset = 0;
+    for member in &index.members {
+        assert_eq!(member.offset, offset);
+        offset += member.compressed_size;
+    }
+
+    // A stream without an index has no footer to read
+    let mut plain = Vec::new();
+    compress_stream(&blocks()[..], &mut plain, BLOCK_SIZE).unwrap();
+    assert!(read_stream_index(Cursor::new(&plain)).unwrap().is_none());
+}
@@ -0,0 +1,12 @@
+//! A short run of the randomized round-trip check, so `cargo test` covers its cases too
+
+use ruffman::stress_round_trips;
+
+#[test]
+fn seeded_random_cases_round_trip() {
+    for seed in [1, 0x5eed] {
+        if let Err(failure) = stress_round_trips(25, seed) {
+            panic!("{}", failure);
+        }
+    }
+}
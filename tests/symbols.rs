@@ -0,0 +1,41 @@
+use ruffman::{code_lengths, symbol_code_lengths, RuffmanError, SymbolCodec};
+
+#[test]
+fn codes_bytes_as_compress_does() {
+    let data = b"abracadabra, a bar of abracadabra";
+    let codec = SymbolCodec::new(&data[..]).unwrap();
+    let lengths = code_lengths(data);
+    for (&byte, code) in codec.codes() {
+        assert_eq!(code.len(), lengths[byte as usize] as usize);
+    }
+    let body = codec.encode(data).unwrap();
+    assert_eq!(codec.decode(&body, data.len()).unwrap(), data);
+}
+
+#[test]
+fn codes_16_bit_samples() {
+    let samples: Vec<u16> = (0..4000u32).map(|i| (1000 + (i * 7919) % 600) as u16 & 0xfff0).collect();
+    let codec = SymbolCodec::new(&samples).unwrap();
+    let lengths = symbol_code_lengths(&samples);
+    assert_eq!(codec.codes().len(), lengths.len());
+    for (symbol, code) in codec.codes() {
+        assert_eq!(code.len(), lengths[symbol] as usize);
+    }
+    let body = codec.encode(&samples).unwrap();
+    assert!(body.len() < samples.len() * 2);
+    assert_eq!(codec.decode(&body, samples.len()).unwrap(), samples);
+}
+
+#[test]
+fn a_lone_symbol_gets_a_one_bit_code() {
+    let codec = SymbolCodec::new(&[300u16; 10]).unwrap();
+    assert_eq!(codec.code(300), Some("0"));
+    assert_eq!(codec.decode(&codec.encode(&[300; 10]).unwrap(), 10).unwrap(), [300; 10]);
+}
+
+#[test]
+fn empty_data_and_unknown_symbols_fail() {
+    assert!(matches!(SymbolCodec::<u16>::new(&[]), Err(RuffmanError::EmptyInput)));
+    let codec = SymbolCodec::new(&[1u16, 2, 2]).unwrap();
+    assert!(matches!(codec.encode(&[3]), Err(RuffmanError::Io(_))));
+}
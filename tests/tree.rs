@@ -0,0 +1,49 @@
+//! `build_tree`, the public way from byte frequencies to Huffman codes
+
+use ruffman::{build_tree, RuffmanError};
+
+fn frequencies(counts: &[(u8, usize)]) -> [usize; 256] {
+    let mut freq = [0; 256];
+    for &(byte, count) in counts {
+        freq[byte as usize] = count;
+    }
+    freq
+}
+
+// The textbook example (CLRS 16.3), whose tree has a unique shape, so the codes are known
+#[test]
+fn known_frequencies_give_the_known_codes() {
+    let freq = frequencies(&[(b'a', 45), (b'b', 13), (b'c', 12), (b'd', 16), (b'e', 9), (b'f', 5)]);
+    let tree = build_tree(&freq).unwrap();
+    for (byte, code) in [(b'a', "0"), (b'b', "101"), (b'c', "100"), (b'd', "111"), (b'e', "1101"), (b'f', "1100")] {
+        assert_eq!(tree.code(byte), Some(code), "code of {}", byte as char);
+        assert_eq!(tree.code_lengths()[byte as usize] as usize, code.len());
+    }
+    assert_eq!(tree.codes().len(), 6);
+    assert_eq!(tree.code(b'g'), None);
+    assert_eq!(tree.code_lengths().iter().filter(|&&length| length > 0).count(), 6);
+}
+
+#[test]
+fn one_byte_gets_a_one_bit_code_and_none_is_an_error() {
+    let tree = build_tree(&frequencies(&[(b'q', 3)])).unwrap();
+    assert_eq!(tree.code(b'q'), Some("0"));
+    assert!(matches!(build_tree(&[0; 256]), Err(RuffmanError::EmptyInput)));
+}
+
+#[test]
+fn frequencies_that_overflow_together_are_an_error() {
+    let cases = [
+        frequencies(&[(b'a', usize::MAX), (b'b', 1)]),
+        frequencies(&[(b'a', usize::MAX / 2 + 1), (b'b', usize::MAX / 2 + 1)]),
+        // The overflow comes only at the last merge
+        frequencies(&[(b'a', usize::MAX - 2), (b'b', 1), (b'c', 1), (b'd', 1)]),
+        [usize::MAX / 255; 256],
+    ];
+    for freq in cases {
+        assert!(matches!(build_tree(&freq), Err(RuffmanError::FrequencyOverflow)));
+    }
+    // Right at the limit is still fine
+    let tree = build_tree(&frequencies(&[(b'a', usize::MAX - 1), (b'b', 1)])).unwrap();
+    assert_eq!(tree.codes().len(), 2);
+}